@@ -1,3 +1,4 @@
 pub mod openai;
 pub mod srs;
 pub mod tacview;
+pub mod weather;
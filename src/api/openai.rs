@@ -1,6 +1,5 @@
-use std::time::Duration;
+use std::{collections::HashMap, fmt, future::Future, time::Duration};
 
-use anyhow::Context;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 use reqwest::{
@@ -9,7 +8,17 @@ use reqwest::{
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::config::OpenAiConfig;
+use crate::{
+    audio::ssml::format_as_ssml,
+    config::{CustomIntentConfig, MessageCategory, OpenAiConfig, ProviderConfig},
+};
+
+mod provider_chain;
+mod tokens;
+pub mod whisper_language_detect;
+
+use provider_chain::with_fallback;
+use tokens::{estimate_token_count, CHAT_CONTEXT_WINDOW, TRANSCRIBE_PROMPT_MAX_TOKENS};
 
 static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
     let mut headers = HeaderMap::new();
@@ -26,21 +35,220 @@ static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
         .expect("failed to build HTTP client")
 });
 
+#[derive(Debug)]
+pub enum OpenAiError {
+    /// The API responded with 429 Too Many Requests
+    RateLimit {
+        request_id: String,
+        body: String,
+    },
+    /// The API responded with 429 as the account ran out of credits, which waiting doesn't fix
+    QuotaExceeded {
+        request_id: String,
+        body: String,
+    },
+    /// The API responded with a 5xx status
+    ServerError {
+        status: u16,
+        request_id: String,
+        body: String,
+    },
+    NetworkError(reqwest::Error),
+    ParseError(serde_json::Error),
+    /// The API responded with another error status, or an unusable response
+    InvalidResponse(String),
+    /// The request was not sent, as the API would reject it
+    InvalidRequest(String),
+    /// The call did not return within the hard timeout
+    Timeout {
+        name: String,
+        timeout: Duration,
+    },
+}
+
+impl OpenAiError {
+    /// Whether the same call may succeed if made again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimit { .. } | Self::ServerError { .. } | Self::Timeout { .. } => true,
+            Self::NetworkError(error) => error.is_timeout() || error.is_connect(),
+            Self::QuotaExceeded { .. }
+            | Self::ParseError(_)
+            | Self::InvalidResponse(_)
+            | Self::InvalidRequest(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for OpenAiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RateLimit { request_id, body } => write!(
+                f,
+                "OpenAI API rate limit exceeded, request ID {request_id}: {body}"
+            ),
+            Self::QuotaExceeded { request_id, body } => write!(
+                f,
+                "OpenAI API quota exceeded, request ID {request_id}: {body}"
+            ),
+            Self::ServerError {
+                status,
+                request_id,
+                body,
+            } => write!(
+                f,
+                "OpenAI API server error {status}, request ID {request_id}: {body}"
+            ),
+            Self::NetworkError(error) => write!(f, "failed to request to OpenAI API: {error}"),
+            Self::ParseError(error) => write!(f, "failed to parse OpenAI API response: {error}"),
+            Self::InvalidResponse(message) => write!(f, "invalid OpenAI API response: {message}"),
+            Self::InvalidRequest(message) => write!(f, "invalid OpenAI API request: {message}"),
+            Self::Timeout { name, timeout } => write!(
+                f,
+                "OpenAI API {name} call did not return within {} seconds",
+                timeout.as_secs_f64()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OpenAiError {}
+
+impl From<reqwest::Error> for OpenAiError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::NetworkError(error)
+    }
+}
+
+/// Longer than any client timeout including the chat completion retry, so it only fires for a wedged call,
+/// e.g. one stuck in DNS resolution or a TLS handshake
+const HARD_TIMEOUT: Duration = Duration::from_secs(30);
+
+async fn with_hard_timeout<T>(
+    name: &str,
+    future: impl Future<Output = Result<T, OpenAiError>>,
+) -> Result<T, OpenAiError> {
+    with_timeout(name, HARD_TIMEOUT, future).await
+}
+
+async fn with_timeout<T>(
+    name: &str,
+    timeout: Duration,
+    future: impl Future<Output = Result<T, OpenAiError>>,
+) -> Result<T, OpenAiError> {
+    match tokio::time::timeout(timeout, future).await {
+        Ok(res) => res,
+        Err(_) => {
+            tracing::error!("OpenAI {} call did not return, abandoning it", name);
+            Err(OpenAiError::Timeout {
+                name: name.to_string(),
+                timeout,
+            })
+        }
+    }
+}
+
+/// Checks the status of an OpenAI API response and reads its body along with the `x-request-id` header,
+/// which looks the call up in the OpenAI dashboard and is what OpenAI support asks for
+async fn read_response(
+    name: &str,
+    resp: reqwest::Response,
+) -> Result<(String, Vec<u8>), OpenAiError> {
+    let request_id = resp
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string();
+    let status = resp.status();
+    let body = match resp.bytes().await {
+        Ok(body) => body.to_vec(),
+        Err(error) => {
+            tracing::error!(openai_request_id = %request_id, error = format!("{error:#}"), "failed to read OpenAI {} response", name);
+            return Err(error.into());
+        }
+    };
+    if !status.is_success() {
+        let body = String::from_utf8_lossy(&body);
+        tracing::error!(openai_request_id = %request_id, %status, %body, "OpenAI {} call failed", name);
+        return Err(get_status_error(status, request_id, body.into_owned()));
+    }
+    tracing::debug!(openai_request_id = %request_id, "OpenAI call completed");
+    Ok((request_id, body))
+}
+
+/// Whether the error body is OpenAI's `insufficient_quota` error, also sent with 429 Too Many Requests
+fn is_insufficient_quota(body: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(body).is_ok_and(|body| {
+        ["code", "type"]
+            .iter()
+            .any(|field| body["error"][field] == "insufficient_quota")
+    })
+}
+
+fn get_status_error(status: reqwest::StatusCode, request_id: String, body: String) -> OpenAiError {
+    if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+        if is_insufficient_quota(&body) {
+            OpenAiError::QuotaExceeded { request_id, body }
+        } else {
+            OpenAiError::RateLimit { request_id, body }
+        }
+    } else if status.is_server_error() {
+        OpenAiError::ServerError {
+            status: status.as_u16(),
+            request_id,
+            body,
+        }
+    } else {
+        OpenAiError::InvalidResponse(format!("{status}, request ID {request_id}: {body}"))
+    }
+}
+
+/// Parses a JSON response body, logging the request ID when it is malformed
+fn parse_response<T: DeserializeOwned>(
+    name: &str,
+    request_id: &str,
+    body: &str,
+) -> Result<T, OpenAiError> {
+    serde_json::from_str::<T>(body).map_err(|error| {
+        tracing::error!(openai_request_id = %request_id, error = format!("{error:#}"), %body, "failed to parse OpenAI {} response", name);
+        OpenAiError::ParseError(error)
+    })
+}
+
+/// The only transcription model returning per segment log probabilities
+pub const TRANSCRIBE_MODEL: &str = "whisper-1";
+
+#[derive(Debug, Deserialize)]
+struct TranscribeRespSegment {
+    avg_logprob: f64,
+}
+
 #[derive(Debug, Deserialize)]
 struct TranscribeResp {
     text: String,
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    segments: Vec<TranscribeRespSegment>,
 }
 
-pub async fn transcribe(
-    config: &OpenAiConfig,
+#[derive(Debug)]
+pub struct Transcription {
+    pub text: String,
+    /// Mean token probability from 0 to 1, if the model returns log probabilities
+    pub confidence: Option<f64>,
+    /// ISO-639-1 code of the spoken language, if the model returns it
+    pub language: Option<String>,
+}
+
+fn transcribe_prompt(
     self_callsign: &str,
     callsigns: &[String],
-    buf: Vec<u8>,
-) -> anyhow::Result<String> {
-    let form = Form::new()
-        .part("file", Part::stream(buf).file_name("audio.wav"))
-        .text("model", "whisper-1")
-        .text("language", "en").text("prompt", format!(r#"Your callsign is {}. You are a military AWACS controller. You are going to listen a pilot's transmission.
+    distinguish_comms_check: bool,
+) -> String {
+    format!(
+        r#"Your callsign is {}. You are a military AWACS controller. You are going to listen a pilot's transmission.
 
 Transmission usually looks like:
 
@@ -48,30 +256,155 @@ Transmission usually looks like:
 
 Possible intents are:
 - radio check
-- request bogey dope
+{}- request bogey dope
+- request element bogey dope
+- snap
+- commit
+- abort
+- request weather
+- request altimeter
+- request marshal
+- request tanker
+- request frequency of {{asset}}
+- report frequency of {{asset}}
 
 Possible callsigns are:
 
 - {}
 {}
 "#,
-    self_callsign,
-    self_callsign,
-    callsigns.iter().map(|callsign| format!("- {callsign}")).join("\n"),
-));
-    let resp = HTTP_CLIENT
-        .post("https://api.openai.com/v1/audio/transcriptions")
-        .bearer_auth(&config.api_key)
-        .multipart(form)
-        .send()
-        .await
-        .context("failed to request to OpenAI API")?
-        .text()
-        .await
-        .context("failed to read from OpenAI API response")?;
-    let resp = serde_json::from_str::<TranscribeResp>(&resp)
-        .with_context(|| format!("failed to parse OpenAI API response: {}", resp))?;
-    Ok(resp.text)
+        self_callsign,
+        if distinguish_comms_check {
+            "- comms check\n"
+        } else {
+            ""
+        },
+        self_callsign,
+        callsigns
+            .iter()
+            .map(|callsign| format!("- {callsign}"))
+            .join("\n"),
+    )
+}
+
+/// Transcription prompt with callsigns dropped from the end until it fits, as Whisper would otherwise
+/// cut off the start of it. Returns the prompt and the number of included callsigns.
+fn fit_transcribe_prompt(
+    self_callsign: &str,
+    callsigns: &[String],
+    distinguish_comms_check: bool,
+) -> (String, usize) {
+    let mut included_callsigns = callsigns;
+    let mut prompt = transcribe_prompt(self_callsign, included_callsigns, distinguish_comms_check);
+    while estimate_token_count(&prompt) > TRANSCRIBE_PROMPT_MAX_TOKENS
+        && !included_callsigns.is_empty()
+    {
+        included_callsigns = &included_callsigns[..included_callsigns.len() - 1];
+        prompt = transcribe_prompt(self_callsign, included_callsigns, distinguish_comms_check);
+    }
+    (prompt, included_callsigns.len())
+}
+
+/// The provider's model in place of the default one, but not in place of an escalation model
+fn get_transcribe_model<'a>(provider: &'a ProviderConfig, model: &'a str) -> &'a str {
+    if model == TRANSCRIBE_MODEL {
+        provider.model.as_deref().unwrap_or(model)
+    } else {
+        model
+    }
+}
+
+pub async fn transcribe(
+    config: &OpenAiConfig,
+    model: &str,
+    language: Option<&str>,
+    self_callsign: &str,
+    callsigns: &[String],
+    distinguish_comms_check: bool,
+    buf: Vec<u8>,
+) -> Result<Transcription, OpenAiError> {
+    let (prompt, included_callsigns) =
+        fit_transcribe_prompt(self_callsign, callsigns, distinguish_comms_check);
+    if included_callsigns < callsigns.len() {
+        tracing::warn!(
+            total = callsigns.len(),
+            included = included_callsigns,
+            "transcription prompt is too long, truncated the callsign list"
+        );
+    }
+
+    let providers = config.get_transcribe_providers();
+    with_fallback("transcription", &providers, |provider| {
+        transcribe_with(
+            config,
+            provider,
+            get_transcribe_model(provider, model),
+            language,
+            &prompt,
+            buf.clone(),
+        )
+    })
+    .await
+}
+
+async fn transcribe_with(
+    config: &OpenAiConfig,
+    provider: &ProviderConfig,
+    model: &str,
+    language: Option<&str>,
+    prompt: &str,
+    buf: Vec<u8>,
+) -> Result<Transcription, OpenAiError> {
+    let mut form = Form::new()
+        .part("file", Part::stream(buf).file_name("audio.wav"))
+        .text("model", model.to_string())
+        .text(
+            "response_format",
+            if model == TRANSCRIBE_MODEL {
+                "verbose_json"
+            } else {
+                "json"
+            },
+        )
+        .text("temperature", config.transcribe_temperature.to_string())
+        .text("prompt", prompt.to_string());
+    // Whisper detects the language itself when not given one
+    if let Some(language) = language {
+        form = form.text("language", language.to_string());
+    }
+    let (request_id, body) = with_hard_timeout("transcription", async {
+        let resp = HTTP_CLIENT
+            .post(provider.endpoint_url("audio/transcriptions"))
+            .bearer_auth(provider.api_key.as_deref().unwrap_or(&config.api_key))
+            .multipart(form)
+            .send()
+            .await?;
+        read_response("transcription", resp).await
+    })
+    .await?;
+    let resp = parse_response::<TranscribeResp>(
+        "transcription",
+        &request_id,
+        &String::from_utf8_lossy(&body),
+    )?;
+    let confidence = (!resp.segments.is_empty()).then(|| {
+        let avg_logprob = resp
+            .segments
+            .iter()
+            .map(|segment| segment.avg_logprob)
+            .sum::<f64>()
+            / resp.segments.len() as f64;
+        avg_logprob.exp()
+    });
+    Ok(Transcription {
+        text: resp.text,
+        confidence,
+        language: resp
+            .language
+            .as_deref()
+            .and_then(whisper_language_detect::to_language_code)
+            .map(str::to_string),
+    })
 }
 
 #[derive(Deserialize, Serialize)]
@@ -105,11 +438,34 @@ struct ChatCompletionResp {
     choices: Vec<ChatCompletionRespChoice>,
 }
 
+/// Parsing is cheaper to retry than transcription, so it gets a longer timeout than the shared client
+const CHAT_COMPLETION_TIMEOUT: Duration = Duration::from_secs(10);
+
+async fn request_chat_completion(
+    config: &OpenAiConfig,
+    req: &ChatCompletionReq,
+) -> reqwest::Result<reqwest::Response> {
+    HTTP_CLIENT
+        .post("https://api.openai.com/v1/chat/completions")
+        .bearer_auth(&config.api_key)
+        .timeout(CHAT_COMPLETION_TIMEOUT)
+        .json(req)
+        .send()
+        .await
+}
+
 pub async fn parse_transmission<T: DeserializeOwned>(
     config: &OpenAiConfig,
     self_callsign: &str,
+    custom_intents: &HashMap<String, CustomIntentConfig>,
+    distinguish_comms_check: bool,
     transmission: String,
-) -> anyhow::Result<T> {
+) -> Result<T, OpenAiError> {
+    let comms_check = if distinguish_comms_check {
+        "- comms_check: the pilot asks for a \"comms check\" instead of a \"radio check\"\n"
+    } else {
+        ""
+    };
     let req = ChatCompletionReq {
         messages: vec![
             ChatCompletionMessage {
@@ -118,8 +474,20 @@ pub async fn parse_transmission<T: DeserializeOwned>(
 
 Possible intents are:
 - radio_check
-- request_bogey_dope
-- unknown
+{}- request_bogey_dope
+- request_element_bogey_dope
+- request_snap
+- request_commit
+- request_abort
+- request_weather
+- request_altimeter
+- request_marshal
+- request_status
+- request_tanker
+- knock_it_off
+- request_frequency
+- report_frequency
+{}- unknown
 
 Input usually looks like:
 {{to callsign}}, {{from callsign}}, {{intent}}
@@ -129,10 +497,67 @@ Output must be all lowercased and looks like:
 {{
   "to_callsign": "{{to callsign}}",
   "from_callsign": "{{from callsign}}",
-  "intent: "{{intent}}"
+  "intent": "{{intent}}"
+}}
+
+When the intent is request_commit and the pilot describes the target's bearing and range, also include them as numbers:
+
+{{
+  "to_callsign": "{{to callsign}}",
+  "from_callsign": "{{from callsign}}",
+  "intent": "request_commit",
+  "bearing": {{bearing}},
+  "range": {{range}}
+}}
+
+When the intent is request_element_bogey_dope, the lead requests a bogey dope for a two-ship element, e.g. "chevy 1-1 and 1-2".
+The lead is the from callsign. Also include the full callsign of the wingman:
+
+{{
+  "to_callsign": "{{to callsign}}",
+  "from_callsign": "{{lead callsign}}",
+  "intent": "request_element_bogey_dope",
+  "wingman_callsign": "{{wingman callsign}}"
+}}
+
+When the intent is request_tanker and the pilot names a tanker, also include its callsign:
+
+{{
+  "to_callsign": "{{to callsign}}",
+  "from_callsign": "{{from callsign}}",
+  "intent": "request_tanker",
+  "tanker": "{{tanker callsign}}"
+}}
+
+When the intent is request_frequency, also include the asset the pilot is asking about:
+
+{{
+  "to_callsign": "{{to callsign}}",
+  "from_callsign": "{{from callsign}}",
+  "intent": "request_frequency",
+  "asset": "{{asset}}"
+}}
+
+When the intent is report_frequency, also include the asset, the frequency in MHz as a number, and the modulation if stated:
+
+{{
+  "to_callsign": "{{to callsign}}",
+  "from_callsign": "{{from callsign}}",
+  "intent": "report_frequency",
+  "asset": "{{asset}}",
+  "frequency_mhz": {{frequency}},
+  "modulation": "{{am or fm}}"
 }}
 "#,
-                    self_callsign
+                    self_callsign,
+                    comms_check,
+                    custom_intents
+                        .iter()
+                        .map(|(name, custom_intent)| format!(
+                            "- {}: {}\n",
+                            name, custom_intent.description
+                        ))
+                        .join(""),
                 ),
                 role: "system".to_string(),
             },
@@ -142,56 +567,247 @@ Output must be all lowercased and looks like:
             },
         ],
         model: "gpt-3.5-turbo-1106",
-        max_tokens: 100,
+        max_tokens: config.chat_max_tokens,
         response_format: ChatCompletionReqResponseFormat { ty: "json_object" },
-        temperature: 0.,
+        temperature: config.chat_temperature,
     };
-    let resp_str = HTTP_CLIENT
-        .post("https://api.openai.com/v1/chat/completions")
-        .bearer_auth(&config.api_key)
-        .json(&req)
-        .send()
-        .await
-        .context("failed to request to OpenAI API")?
-        .text()
-        .await
-        .context("failed to read from OpenAI API response")?;
-    let resp = serde_json::from_str::<ChatCompletionResp>(&resp_str)
-        .with_context(|| format!("failed to parse OpenAI API response: {}", resp_str))?;
-    let choice = resp
-        .choices
-        .first()
-        .with_context(|| format!("OpenAI returned empty choices, raw response: {}", resp_str))?;
-    serde_json::from_str::<T>(&choice.message.content)
-        .with_context(|| format!("failed to parse OpenAI API response: {}", resp_str))
+    let prompt_tokens = req
+        .messages
+        .iter()
+        .map(|message| estimate_token_count(&message.content))
+        .sum::<usize>();
+    if prompt_tokens + req.max_tokens >= CHAT_CONTEXT_WINDOW {
+        return Err(OpenAiError::InvalidRequest(format!(
+            "chat completion prompt is too long, estimated {} prompt tokens with {} max tokens",
+            prompt_tokens, req.max_tokens
+        )));
+    }
+    let (request_id, body) = with_hard_timeout("chat completion", async {
+        let attempt = || async {
+            let resp = request_chat_completion(config, &req).await?;
+            read_response("chat completion", resp).await
+        };
+        match attempt().await {
+            Err(error) if error.is_retryable() => {
+                tracing::warn!(
+                    error = format!("{error:#}"),
+                    "OpenAI chat completion failed, retrying once"
+                );
+                attempt().await
+            }
+            res => res,
+        }
+    })
+    .await?;
+    let resp_str = String::from_utf8_lossy(&body);
+    let resp = parse_response::<ChatCompletionResp>("chat completion", &request_id, &resp_str)?;
+    let Some(choice) = resp.choices.first() else {
+        tracing::error!(openai_request_id = %request_id, "OpenAI returned empty choices");
+        return Err(OpenAiError::InvalidResponse(format!(
+            "empty choices, request ID {request_id}, raw response: {resp_str}"
+        )));
+    };
+    parse_response::<T>("chat completion", &request_id, &choice.message.content)
 }
 
 #[derive(Debug, Serialize)]
 struct SpeechReq<'a> {
-    model: &'static str,
+    model: &'a str,
     input: &'a str,
     voice: &'a str,
     response_format: &'static str,
     speed: f64,
 }
 
-pub async fn speech(config: &OpenAiConfig, input: &str) -> anyhow::Result<Vec<u8>> {
+pub async fn speech(
+    config: &OpenAiConfig,
+    input: &str,
+    category: MessageCategory,
+) -> Result<Vec<u8>, OpenAiError> {
+    let providers = config.get_speech_providers();
+    with_fallback("speech", &providers, |provider| {
+        speech_with(config, provider, input, category)
+    })
+    .await
+}
+
+async fn speech_with(
+    config: &OpenAiConfig,
+    provider: &ProviderConfig,
+    input: &str,
+    category: MessageCategory,
+) -> Result<Vec<u8>, OpenAiError> {
+    let ssml;
+    let input = if provider.use_ssml {
+        ssml = format_as_ssml(input, &config.ssml);
+        &ssml
+    } else {
+        input
+    };
     let req = SpeechReq {
-        model: "tts-1",
+        model: provider.model.as_deref().unwrap_or("tts-1"),
         input,
         voice: &config.speech_voice,
         response_format: "opus",
-        speed: config.speech_speed,
+        speed: config.get_speech_speed(category),
     };
+    let (_, body) = with_hard_timeout("speech", async {
+        let resp = HTTP_CLIENT
+            .post(provider.endpoint_url("audio/speech"))
+            .bearer_auth(provider.api_key.as_deref().unwrap_or(&config.api_key))
+            .json(&req)
+            .send()
+            .await?;
+        read_response("speech", resp).await
+    })
+    .await?;
+    Ok(body)
+}
+
+/// Lists the available models, to check that the API key works
+pub async fn list_models(config: &OpenAiConfig) -> Result<(), OpenAiError> {
     let resp = HTTP_CLIENT
-        .post("https://api.openai.com/v1/audio/speech")
+        .get("https://api.openai.com/v1/models")
         .bearer_auth(&config.api_key)
-        .json(&req)
         .send()
-        .await
-        .context("failed to request to OpenAI API")?
-        .bytes()
-        .await
-        .context("failed to read from OpenAI API response")?;
-    Ok(resp.to_vec())
+        .await?;
+    read_response("list models", resp).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcribe_prompt_keeps_every_callsign_that_fits() {
+        let callsigns = vec!["Chevy 1-1".to_string(), "Enfield 2-1".to_string()];
+        let (prompt, included) = fit_transcribe_prompt("Magic", &callsigns, false);
+        assert_eq!(included, 2);
+        assert!(prompt.contains("- Enfield 2-1"));
+    }
+
+    #[test]
+    fn transcribe_prompt_lists_comms_check_only_when_distinguished() {
+        let prompt = transcribe_prompt("Magic", &[], false);
+        assert!(
+            prompt.contains("- radio check\n- request bogey dope"),
+            "{prompt}"
+        );
+        assert!(!prompt.contains("comms check"), "{prompt}");
+        let prompt = transcribe_prompt("Magic", &[], true);
+        assert!(
+            prompt.contains("- radio check\n- comms check\n- request bogey dope"),
+            "{prompt}"
+        );
+    }
+
+    #[test]
+    fn transcribe_prompt_drops_callsigns_from_the_end_to_fit() {
+        let callsigns = (0..200)
+            .map(|i| format!("Callsign {i}"))
+            .collect::<Vec<_>>();
+        let (prompt, included) = fit_transcribe_prompt("Magic", &callsigns, false);
+        assert!(included > 0 && included < callsigns.len());
+        assert!(estimate_token_count(&prompt) <= TRANSCRIBE_PROMPT_MAX_TOKENS);
+        assert!(prompt.contains("- Callsign 0\n"));
+        assert!(!prompt.contains(&format!("- Callsign {included}\n")));
+    }
+
+    #[test]
+    fn provider_model_replaces_only_the_default_transcription_model() {
+        let provider = ProviderConfig {
+            name: "local".to_string(),
+            base_url: "http://localhost:8000/v1".to_string(),
+            api_key: None,
+            model: Some("Systran/faster-whisper-small".to_string()),
+            use_ssml: false,
+        };
+        assert_eq!(
+            get_transcribe_model(&provider, TRANSCRIBE_MODEL),
+            "Systran/faster-whisper-small"
+        );
+        assert_eq!(
+            get_transcribe_model(&provider, "gpt-4o-transcribe"),
+            "gpt-4o-transcribe"
+        );
+    }
+
+    #[tokio::test]
+    async fn wedged_call_is_abandoned() {
+        let res = with_timeout(
+            "test",
+            Duration::from_millis(10),
+            std::future::pending::<Result<(), OpenAiError>>(),
+        )
+        .await;
+        let error = res.unwrap_err();
+        assert!(matches!(&error, OpenAiError::Timeout { name, .. } if name == "test"));
+        assert!(error.is_retryable());
+        assert_eq!(
+            error.to_string(),
+            "OpenAI API test call did not return within 0.01 seconds"
+        );
+    }
+
+    #[tokio::test]
+    async fn call_returning_in_time_keeps_its_result() {
+        let res = with_timeout("test", Duration::from_secs(1), async { Ok(42) }).await;
+        assert_eq!(res.unwrap(), 42);
+
+        let res = with_timeout("test", Duration::from_secs(1), async {
+            Err::<(), _>(OpenAiError::InvalidRequest("test".to_string()))
+        })
+        .await;
+        assert!(matches!(res, Err(OpenAiError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn rate_limits_are_retried_but_not_exhausted_quota() {
+        let error = get_status_error(
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            "req_1".to_string(),
+            r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#
+                .to_string(),
+        );
+        assert!(
+            matches!(&error, OpenAiError::RateLimit { request_id, .. } if request_id == "req_1")
+        );
+        assert!(error.is_retryable());
+
+        let error = get_status_error(
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+            "req_2".to_string(),
+            r#"{"error":{"message":"You exceeded your current quota","type":"insufficient_quota","code":"insufficient_quota"}}"#
+                .to_string(),
+        );
+        assert!(
+            matches!(&error, OpenAiError::QuotaExceeded { request_id, .. } if request_id == "req_2")
+        );
+        assert!(!error.is_retryable());
+        assert!(error.to_string().contains("req_2"), "{error}");
+    }
+
+    #[test]
+    fn server_errors_keep_the_request_id_and_body() {
+        let error = get_status_error(
+            reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            "req_3".to_string(),
+            "overloaded".to_string(),
+        );
+        assert!(matches!(
+            &error,
+            OpenAiError::ServerError { status: 503, request_id, body }
+                if request_id == "req_3" && body == "overloaded"
+        ));
+        assert!(error.is_retryable());
+
+        let error = get_status_error(
+            reqwest::StatusCode::BAD_REQUEST,
+            "req_4".to_string(),
+            "bad".to_string(),
+        );
+        assert!(matches!(error, OpenAiError::InvalidResponse(_)));
+        assert!(!error.is_retryable());
+    }
 }
@@ -0,0 +1,61 @@
+//! Falling back to the next transcription or speech provider when one fails
+
+use std::{collections::HashMap, future::Future, sync::Mutex};
+
+use once_cell::sync::Lazy;
+
+use crate::config::ProviderConfig;
+
+use super::OpenAiError;
+
+/// Successful calls by capability and provider name, logged to see how often fallbacks are needed
+static SUCCESS_COUNTS: Lazy<Mutex<HashMap<(&'static str, String), u64>>> =
+    Lazy::new(Default::default);
+
+fn record_success(capability: &'static str, provider: &str, fallback: bool) {
+    let successes = {
+        let mut success_counts = SUCCESS_COUNTS.lock().unwrap();
+        let successes = success_counts
+            .entry((capability, provider.to_string()))
+            .or_default();
+        *successes += 1;
+        *successes
+    };
+    if fallback {
+        tracing::info!(
+            capability,
+            provider,
+            successes,
+            "fallback provider succeeded"
+        );
+    } else {
+        tracing::debug!(capability, provider, successes, "provider succeeded");
+    }
+}
+
+/// Calls each provider in order until one succeeds, returning the error of the last one if all fail
+pub async fn with_fallback<'a, T, Fut>(
+    capability: &'static str,
+    providers: &'a [ProviderConfig],
+    mut call: impl FnMut(&'a ProviderConfig) -> Fut,
+) -> Result<T, OpenAiError>
+where
+    Fut: Future<Output = Result<T, OpenAiError>>,
+{
+    let mut last_error = OpenAiError::InvalidRequest(format!("no {capability} provider"));
+    for (index, provider) in providers.iter().enumerate() {
+        match call(provider).await {
+            Ok(value) => {
+                record_success(capability, &provider.name, index > 0);
+                return Ok(value);
+            }
+            Err(error) => {
+                if index + 1 < providers.len() {
+                    tracing::warn!(capability, provider = %provider.name, error = format!("{error:#}"), "provider failed, trying the next one");
+                }
+                last_error = error;
+            }
+        }
+    }
+    Err(last_error)
+}
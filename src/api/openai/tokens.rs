@@ -0,0 +1,35 @@
+//! Rough token estimation, for checking prompt sizes before calling the API
+
+/// Context window of the chat completion model
+pub const CHAT_CONTEXT_WINDOW: usize = 4096;
+
+/// Whisper only considers the last 224 tokens of the prompt
+pub const TRANSCRIBE_PROMPT_MAX_TOKENS: usize = 224;
+
+/// Estimates the token count of the text without a tokenizer.
+///
+/// English text is about 4 characters per token, while callsigns and numbers are closer to a token per
+/// word, so the larger of the two is taken.
+pub fn estimate_token_count(text: &str) -> usize {
+    let by_chars = text.chars().count().div_ceil(4);
+    let by_words = text.split_whitespace().count();
+    by_chars.max(by_words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prose_is_estimated_by_characters() {
+        assert_eq!(estimate_token_count(""), 0);
+        assert_eq!(estimate_token_count("transcription"), 4);
+        assert_eq!(estimate_token_count("request bogey dope"), 5);
+    }
+
+    #[test]
+    fn short_words_are_estimated_by_words() {
+        assert_eq!(estimate_token_count("1 1 2 2 3 3"), 6);
+        assert_eq!(estimate_token_count("- a\n- b\n- c"), 6);
+    }
+}
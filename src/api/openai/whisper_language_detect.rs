@@ -0,0 +1,111 @@
+//! Detecting the spoken language with Whisper before transcribing in it
+
+use super::{transcribe, OpenAiError, Transcription, TRANSCRIBE_MODEL};
+use crate::config::OpenAiConfig;
+
+/// ISO-639-1 codes of the language names Whisper returns in verbose JSON responses
+const LANGUAGE_CODES: &[(&str, &str)] = &[
+    ("arabic", "ar"),
+    ("chinese", "zh"),
+    ("czech", "cs"),
+    ("danish", "da"),
+    ("dutch", "nl"),
+    ("english", "en"),
+    ("finnish", "fi"),
+    ("french", "fr"),
+    ("german", "de"),
+    ("greek", "el"),
+    ("hebrew", "he"),
+    ("hungarian", "hu"),
+    ("italian", "it"),
+    ("japanese", "ja"),
+    ("korean", "ko"),
+    ("norwegian", "no"),
+    ("persian", "fa"),
+    ("polish", "pl"),
+    ("portuguese", "pt"),
+    ("romanian", "ro"),
+    ("russian", "ru"),
+    ("serbian", "sr"),
+    ("spanish", "es"),
+    ("swedish", "sv"),
+    ("turkish", "tr"),
+    ("ukrainian", "uk"),
+];
+
+/// Converts a language detected by Whisper, either a name or a code, to an ISO-639-1 code
+pub fn to_language_code(language: &str) -> Option<&'static str> {
+    let language = language.trim().to_lowercase();
+    LANGUAGE_CODES
+        .iter()
+        .find(|(name, code)| *name == language || *code == language)
+        .map(|(_, code)| *code)
+}
+
+/// Transcribes in the given language if already known for the pilot.
+/// Otherwise transcribes without a language hint, and again in the detected language if it is not the
+/// configured one, as a hinted transcription is more accurate.
+pub async fn transcribe_detecting_language(
+    config: &OpenAiConfig,
+    known_language: Option<&str>,
+    self_callsign: &str,
+    callsigns: &[String],
+    distinguish_comms_check: bool,
+    buf: Vec<u8>,
+) -> Result<Transcription, OpenAiError> {
+    if let Some(language) = known_language {
+        return transcribe(
+            config,
+            TRANSCRIBE_MODEL,
+            Some(language),
+            self_callsign,
+            callsigns,
+            distinguish_comms_check,
+            buf,
+        )
+        .await;
+    }
+
+    let detected = transcribe(
+        config,
+        TRANSCRIBE_MODEL,
+        None,
+        self_callsign,
+        callsigns,
+        distinguish_comms_check,
+        buf.clone(),
+    )
+    .await?;
+    let Some(language) = detected.language.as_deref() else {
+        return Ok(detected);
+    };
+    if language == config.transcribe_language {
+        return Ok(detected);
+    }
+
+    tracing::info!(
+        transcript = %detected.text,
+        language,
+        "detected a language other than the configured one, transcribing again"
+    );
+    match transcribe(
+        config,
+        TRANSCRIBE_MODEL,
+        Some(language),
+        self_callsign,
+        callsigns,
+        distinguish_comms_check,
+        buf,
+    )
+    .await
+    {
+        Ok(transcription) => Ok(transcription),
+        Err(error) => {
+            tracing::warn!(
+                error = format!("{error:#}"),
+                "transcription in the detected language failed, using the first transcript"
+            );
+            Ok(detected)
+        }
+    }
+}
@@ -1,11 +1,29 @@
-use std::net::ToSocketAddrs;
+use std::{net::ToSocketAddrs, sync::Arc, time::Duration};
 
 use anyhow::Context;
+use futures_util::{
+    stream::{SplitSink, SplitStream},
+    StreamExt,
+};
+use srs::{
+    message::{GameMessage, LatLngPosition, Radio},
+    VoiceStream,
+};
+use stopper::Stopper;
+use tokio::sync::Mutex;
 
 use crate::config::SrsConfig;
 
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Unit the bot appears as on SRS, as it is not a unit in the mission
+const UNIT_ID: u32 = 100000001;
+const UNIT_NAME: &str = "External AWACS";
+
+/// `game_rx` receives game state messages sent on to the server, or none if its sender is dropped
 pub async fn connect(
     config: &SrsConfig,
+    game_rx: futures_channel::mpsc::UnboundedReceiver<GameMessage>,
     stop_rx: tokio::sync::oneshot::Receiver<()>,
 ) -> anyhow::Result<srs::VoiceStream> {
     let mut client = srs::Client::new(
@@ -13,7 +31,7 @@ pub async fn connect(
         config.frequency,
         config.coalition.clone().into(),
     );
-    client.set_unit(100000001, "External AWACS");
+    client.set_unit(UNIT_ID, UNIT_NAME);
 
     tracing::info!(
         "connecting to SimpleRadioStandalone server at `{}:{}`",
@@ -21,7 +39,6 @@ pub async fn connect(
         config.port
     );
 
-    let (_, game_rx) = futures_channel::mpsc::unbounded();
     let stream = client
         .start(
             (config.host.as_str(), config.port)
@@ -52,3 +69,135 @@ pub async fn connect(
 
     Ok(stream)
 }
+
+/// Halves of the current SRS connection, swapped in place on reconnect so the loops using them keep running
+#[derive(Clone)]
+pub struct SrsClientState {
+    config: SrsConfig,
+    // `VoiceStream` implements `Sink<Vec<u8>>`, where each item is a single encoded Opus frame.
+    // Frames are moved into the sink, so no copy is made on the transmit path.
+    sink: Arc<Mutex<SplitSink<VoiceStream, Vec<u8>>>>,
+    stream: Arc<Mutex<SplitStream<VoiceStream>>>,
+    /// Stops the SRS client of the current connection
+    stop_tx: Arc<std::sync::Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
+    /// Game state messages of the current connection
+    game_tx: Arc<std::sync::Mutex<futures_channel::mpsc::UnboundedSender<GameMessage>>>,
+    /// Last game state message, sent again on reconnect
+    game_message: Arc<std::sync::Mutex<Option<GameMessage>>>,
+}
+
+impl SrsClientState {
+    /// Connects, and stops the client when `stopper` stops
+    pub async fn connect(config: SrsConfig, stopper: Stopper) -> anyhow::Result<Self> {
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+        let (game_tx, game_rx) = futures_channel::mpsc::unbounded();
+        // `StreamExt::split` is generic over the sink item type, so it has to be spelled out here
+        let (sink, stream) = connect(&config, game_rx, stop_rx).await?.split::<Vec<u8>>();
+        let client_state = Self {
+            config,
+            sink: Arc::new(Mutex::new(sink)),
+            stream: Arc::new(Mutex::new(stream)),
+            stop_tx: Arc::new(std::sync::Mutex::new(Some(stop_tx))),
+            game_tx: Arc::new(std::sync::Mutex::new(game_tx)),
+            game_message: Arc::new(std::sync::Mutex::new(None)),
+        };
+
+        let stopping = client_state.clone();
+        tokio::spawn(async move {
+            stopper.stop_future(std::future::pending::<()>()).await;
+            stopping.stop();
+        });
+
+        Ok(client_state)
+    }
+
+    pub fn sink(&self) -> &Mutex<SplitSink<VoiceStream, Vec<u8>>> {
+        &self.sink
+    }
+
+    pub fn stream(&self) -> &Mutex<SplitStream<VoiceStream>> {
+        &self.stream
+    }
+
+    pub fn config(&self) -> &SrsConfig {
+        &self.config
+    }
+
+    /// Sends the bot's position to the server, along with its unit and radio.
+    /// SRS clients sync these with the server, which shows the unit on the map of other clients
+    /// and, if enabled on the server, uses the position for line of sight and distance limits.
+    /// The radio replaces the one set at connection, so it has the configured frequency.
+    pub fn update_position(&self, (lat, lng): (f64, f64), altitude_meters: f64) {
+        let game_message = GameMessage {
+            name: self.config.username.clone(),
+            unit_id: UNIT_ID,
+            unit: UNIT_NAME.to_string(),
+            pos: LatLngPosition {
+                lat,
+                lng,
+                alt: altitude_meters,
+            },
+            radios: vec![Radio {
+                name: UNIT_NAME.to_string(),
+                freq: self.config.frequency as f64,
+                // AM, as used for aircraft radios
+                modulation: 0,
+                enc: false,
+                enc_key: 0,
+            }],
+        };
+        let _ = self
+            .game_tx
+            .lock()
+            .unwrap()
+            .unbounded_send(game_message.clone());
+        *self.game_message.lock().unwrap() = Some(game_message);
+    }
+
+    fn stop(&self) {
+        if let Some(stop_tx) = self.stop_tx.lock().unwrap().take() {
+            let _ = stop_tx.send(());
+        }
+    }
+
+    /// Reconnects with the same username, frequency, coalition, and unit, retrying until connected.
+    /// Returns `false` if stopped before reconnecting.
+    pub async fn reconnect(&self, stopper: &Stopper) -> bool {
+        self.stop();
+        loop {
+            let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+            let (game_tx, game_rx) = futures_channel::mpsc::unbounded();
+            if let Some(game_message) = self.game_message.lock().unwrap().clone() {
+                let _ = game_tx.unbounded_send(game_message);
+            }
+            match stopper
+                .stop_future(connect(&self.config, game_rx, stop_rx))
+                .await
+            {
+                None => return false,
+                Some(Ok(voice_stream)) => {
+                    let (sink, stream) = voice_stream.split::<Vec<u8>>();
+                    *self.sink.lock().await = sink;
+                    *self.stream.lock().await = stream;
+                    *self.stop_tx.lock().unwrap() = Some(stop_tx);
+                    *self.game_tx.lock().unwrap() = game_tx;
+                    tracing::info!("reconnected to SimpleRadioStandalone server");
+                    return true;
+                }
+                Some(Err(error)) => {
+                    tracing::error!(
+                        error = format!("{error:#}"),
+                        "failed to reconnect to SimpleRadioStandalone server"
+                    );
+                    if stopper
+                        .stop_future(tokio::time::sleep(RECONNECT_INTERVAL))
+                        .await
+                        .is_none()
+                    {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+}
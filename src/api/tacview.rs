@@ -1,6 +1,15 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use anyhow::Context;
+use stopper::Stopper;
 use tacview_realtime_client::acmi::RealTimeReader;
-use tokio::{io::BufStream, net::TcpStream};
+use tokio::{io::BufStream, net::TcpStream, sync::Notify};
 
 use crate::config::TacviewConfig;
 
@@ -25,3 +34,49 @@ pub async fn connect(
         )
     })
 }
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Time of the last record received from Tacview, in Unix milliseconds
+#[derive(Clone)]
+pub struct Heartbeat(Arc<AtomicU64>);
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self(Arc::new(AtomicU64::new(unix_millis())))
+    }
+
+    pub fn beat(&self) {
+        self.0.store(unix_millis(), Ordering::Relaxed);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_millis(unix_millis().saturating_sub(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+/// Notifies `hung` when no record was received for the heartbeat timeout,
+/// as the connection can stay open while the server stops sending data
+pub async fn health_loop(
+    config: TacviewConfig,
+    heartbeat: Heartbeat,
+    hung: Arc<Notify>,
+    stopper: Stopper,
+) {
+    let timeout = Duration::from_secs(config.heartbeat_timeout_secs);
+    let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        if heartbeat.elapsed() > timeout {
+            tracing::warn!("Tacview connection appears hung, forcing reconnect");
+            hung.notify_one();
+        }
+    }
+    tracing::info!("exiting Tacview health loop");
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+
+use crate::config::{TheaterAirport, WeatherApiConfig, WeatherProvider};
+
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "user-agent",
+        concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"))
+            .parse()
+            .expect("failed to parse header value"),
+    );
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("failed to build HTTP client")
+});
+
+/// Current observation at an airport
+#[derive(Debug, Clone, Default)]
+pub struct MetarData {
+    /// Direction the wind blows from in degrees
+    pub wind_direction: Option<f64>,
+    pub wind_speed_kts: Option<f64>,
+    pub visibility_miles: Option<f64>,
+    /// Whether the provider reports cloud layers
+    pub has_clouds: bool,
+    /// Lowest broken or overcast layer, `None` if clear
+    pub ceiling_ft: Option<f64>,
+    pub temperature_c: Option<f64>,
+    pub dew_point_c: Option<f64>,
+    pub altimeter_inhg: Option<f64>,
+}
+
+fn hpa_to_inhg(hpa: f64) -> f64 {
+    hpa * 0.02953
+}
+
+#[derive(Deserialize)]
+struct AvWxValue {
+    value: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct AvWxCloud {
+    #[serde(rename = "type")]
+    ty: String,
+    /// Hundreds of feet
+    altitude: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct AvWxUnits {
+    altimeter: String,
+}
+
+#[derive(Deserialize)]
+struct AvWxMetar {
+    wind_direction: Option<AvWxValue>,
+    wind_speed: Option<AvWxValue>,
+    visibility: Option<AvWxValue>,
+    #[serde(default)]
+    clouds: Vec<AvWxCloud>,
+    temperature: Option<AvWxValue>,
+    dewpoint: Option<AvWxValue>,
+    altimeter: Option<AvWxValue>,
+    units: AvWxUnits,
+}
+
+async fn get_avwx_metar(
+    airport: &TheaterAirport,
+    config: &WeatherApiConfig,
+) -> anyhow::Result<MetarData> {
+    let api_key = config
+        .api_key
+        .as_deref()
+        .context("AvWx requires `weather_api.api_key`")?;
+    let resp = HTTP_CLIENT
+        .get(format!("https://avwx.rest/api/metar/{}", airport.icao))
+        .bearer_auth(api_key)
+        .send()
+        .await
+        .context("failed to request to AvWx API")?
+        .text()
+        .await
+        .context("failed to read from AvWx API response")?;
+    let metar = serde_json::from_str::<AvWxMetar>(&resp)
+        .with_context(|| format!("failed to parse AvWx API response: {}", resp))?;
+
+    let value = |value: Option<AvWxValue>| value.and_then(|value| value.value);
+    let altimeter = value(metar.altimeter);
+    Ok(MetarData {
+        wind_direction: value(metar.wind_direction),
+        wind_speed_kts: value(metar.wind_speed),
+        visibility_miles: value(metar.visibility),
+        has_clouds: true,
+        ceiling_ft: metar
+            .clouds
+            .iter()
+            .filter(|cloud| cloud.ty == "BKN" || cloud.ty == "OVC")
+            .filter_map(|cloud| cloud.altitude)
+            .reduce(f64::min)
+            .map(|altitude| altitude * 100.),
+        temperature_c: value(metar.temperature),
+        dew_point_c: value(metar.dewpoint),
+        altimeter_inhg: if metar.units.altimeter.eq_ignore_ascii_case("hpa") {
+            altimeter.map(hpa_to_inhg)
+        } else {
+            altimeter
+        },
+    })
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoCurrent {
+    temperature_2m: Option<f64>,
+    dew_point_2m: Option<f64>,
+    /// Hectopascals
+    pressure_msl: Option<f64>,
+    wind_speed_10m: Option<f64>,
+    wind_direction_10m: Option<f64>,
+    /// Meters
+    visibility: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoResp {
+    current: OpenMeteoCurrent,
+}
+
+async fn get_open_meteo_metar(airport: &TheaterAirport) -> anyhow::Result<MetarData> {
+    let resp = HTTP_CLIENT
+        .get("https://api.open-meteo.com/v1/forecast")
+        .query(&[
+            ("latitude", airport.latitude.to_string()),
+            ("longitude", airport.longitude.to_string()),
+            (
+                "current",
+                "temperature_2m,dew_point_2m,pressure_msl,wind_speed_10m,wind_direction_10m,visibility"
+                    .to_string(),
+            ),
+            ("wind_speed_unit", "kn".to_string()),
+        ])
+        .send()
+        .await
+        .context("failed to request to Open-Meteo API")?
+        .text()
+        .await
+        .context("failed to read from Open-Meteo API response")?;
+    let current = serde_json::from_str::<OpenMeteoResp>(&resp)
+        .with_context(|| format!("failed to parse Open-Meteo API response: {}", resp))?
+        .current;
+
+    Ok(MetarData {
+        wind_direction: current.wind_direction_10m,
+        wind_speed_kts: current.wind_speed_10m,
+        visibility_miles: current.visibility.map(|meters| meters / 1609.344),
+        // Open-Meteo has no cloud base
+        has_clouds: false,
+        ceiling_ft: None,
+        temperature_c: current.temperature_2m,
+        dew_point_c: current.dew_point_2m,
+        altimeter_inhg: current.pressure_msl.map(hpa_to_inhg),
+    })
+}
+
+pub async fn get_metar(
+    airport: &TheaterAirport,
+    config: &WeatherApiConfig,
+) -> anyhow::Result<MetarData> {
+    match config.provider {
+        WeatherProvider::AvWx => get_avwx_metar(airport, config).await,
+        WeatherProvider::OpenMeteo => get_open_meteo_metar(airport).await,
+    }
+}
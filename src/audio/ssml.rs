@@ -0,0 +1,137 @@
+//! Marking up speech with SSML, for speech providers supporting it
+
+use std::fmt::Write;
+
+use crate::config::SsmlConfig;
+
+/// Every transmission starts with the callsigns of the addressee and the sender, e.g. "Chevy 1-1, Magic, ..."
+const LEADING_CALLSIGNS: usize = 2;
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Whether the clause is a callsign with flight and element numbers, e.g. "Chevy 1-2"
+fn is_numbered_callsign(clause: &str) -> bool {
+    let mut words = clause.split_whitespace();
+    let Some(numbers) = words.next_back() else {
+        return false;
+    };
+    words.next().is_some()
+        && numbers.split_once('-').is_some_and(|(flight, element)| {
+            [flight, element]
+                .iter()
+                .all(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+        })
+}
+
+/// Reads bearings spelled digit by digit, e.g. "0 9 0", as characters rather than as a number
+fn tag_bearings(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let is_digit_at = |i: usize| chars.get(i).is_some_and(char::is_ascii_digit);
+    let is_word_at = |i: usize| chars.get(i).is_some_and(|c| c.is_alphanumeric());
+    let is_spelled_digit_before = |i: usize| i >= 2 && chars[i - 1] == ' ' && is_digit_at(i - 2);
+    // Exactly three digits, not part of a longer number spelled digit by digit
+    let is_bearing_at = |i: usize| {
+        (i == 0 || !is_word_at(i - 1))
+            && !is_spelled_digit_before(i)
+            && is_digit_at(i)
+            && chars.get(i + 1) == Some(&' ')
+            && is_digit_at(i + 2)
+            && chars.get(i + 3) == Some(&' ')
+            && is_digit_at(i + 4)
+            && !is_word_at(i + 5)
+            && !(chars.get(i + 5) == Some(&' ') && is_digit_at(i + 6))
+    };
+
+    let mut tagged = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if is_bearing_at(i) {
+            let _ = write!(
+                tagged,
+                "<say-as interpret-as=\"characters\">{}{}{}</say-as>",
+                chars[i],
+                chars[i + 2],
+                chars[i + 4]
+            );
+            i += 5;
+        } else {
+            tagged.push(chars[i]);
+            i += 1;
+        }
+    }
+    tagged
+}
+
+/// Wraps the speech in `<speak>` at the configured rate, pausing after callsigns and tagging bearings
+pub fn format_as_ssml(text: &str, config: &SsmlConfig) -> String {
+    let clauses = text.split(',').collect::<Vec<_>>();
+    let mut body = String::new();
+    for (index, clause) in clauses.iter().enumerate() {
+        body.push_str(&tag_bearings(&escape_xml(clause)));
+        if index + 1 < clauses.len() {
+            body.push(',');
+            if index < LEADING_CALLSIGNS || is_numbered_callsign(clause) {
+                let _ = write!(body, "<break time=\"{}ms\"/>", config.callsign_break_ms);
+            }
+        }
+    }
+    format!(
+        "<speak><prosody rate=\"{}%\">{}</prosody></speak>",
+        config.rate_percent, body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bearings_are_read_as_characters() {
+        assert_eq!(
+            format_as_ssml(
+                "Chevy 1-1, Magic, single braa 0 9 0, 25, 20 thousands, hot",
+                &SsmlConfig::default()
+            ),
+            "<speak><prosody rate=\"90%\">Chevy 1-1,<break time=\"200ms\"/> Magic,<break time=\"200ms\"/> \
+             single braa <say-as interpret-as=\"characters\">090</say-as>, 25, 20 thousands, hot\
+             </prosody></speak>"
+        );
+        assert_eq!(
+            tag_bearings("bullseye 2 7 0/40"),
+            "bullseye <say-as interpret-as=\"characters\">270</say-as>/40"
+        );
+        // Ranges read digit by digit and longer numbers are not bearings
+        assert_eq!(tag_bearings("2 5, 1 2 3 4"), "2 5, 1 2 3 4");
+    }
+
+    #[test]
+    fn callsigns_are_followed_by_a_break() {
+        let config = SsmlConfig {
+            callsign_break_ms: 300,
+            rate_percent: 85,
+        };
+        assert_eq!(
+            format_as_ssml("all players, Magic, Chevy 1-2, engaged", &config),
+            "<speak><prosody rate=\"85%\">all players,<break time=\"300ms\"/> Magic,<break time=\"300ms\"/> \
+             Chevy 1-2,<break time=\"300ms\"/> engaged</prosody></speak>"
+        );
+        assert!(is_numbered_callsign(" Enfield 11-2"));
+        assert!(!is_numbered_callsign(" 1-1"));
+        assert!(!is_numbered_callsign(" 20 thousands"));
+    }
+
+    #[test]
+    fn markup_in_the_text_is_escaped() {
+        assert_eq!(
+            format_as_ssml("Chevy 1-1, Magic, <break/> & \"more\"", &SsmlConfig::default()),
+            "<speak><prosody rate=\"90%\">Chevy 1-1,<break time=\"200ms\"/> Magic,<break time=\"200ms\"/> \
+             &lt;break/&gt; &amp; &quot;more&quot;</prosody></speak>"
+        );
+    }
+}
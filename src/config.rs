@@ -1,4 +1,8 @@
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use clap::Parser;
@@ -8,9 +12,70 @@ use serde::Deserialize;
 pub struct CliConfig {
     #[arg(short, long, default_value = "config.toml")]
     pub config: PathBuf,
+    /// Check connectivity to Tacview, SRS, and OpenAI, then exit
+    #[arg(long)]
+    pub check: bool,
+    /// Write the mission timeline to this file on shutdown, as JSON if it ends with `.json`,
+    /// or a Markdown report for the debrief otherwise. Only the last 10000 events are kept.
+    #[arg(long)]
+    pub export_timeline: Option<PathBuf>,
+}
+
+/// How the spoken callsign is checked against the SRS client that transmitted
+#[derive(Clone, Default, Deserialize)]
+pub enum SenderCheck {
+    #[default]
+    Off,
+    /// Log when a client uses a different callsign than before
+    Warn,
+    /// Also replace a callsign not on scope with the one the client used before
+    Correct,
+}
+
+/// What to do when the requester's position is stale
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub enum StaleRequesterHandling {
+    /// Respond "your position is stale" instead of a response from the stale position
+    #[default]
+    Respond,
+    /// Extrapolate the requester's position from its last ground speed and track
+    DeadReckon,
+}
+
+/// How bearings from the bullseye are called in pictures and threat warnings
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub enum BearingPronunciation {
+    /// Digit by digit, e.g. "2 7 0"
+    #[default]
+    NumericPhonetic,
+    /// The nearest of the eight compass points, e.g. "west"
+    CardinalCompass,
+}
+
+/// How much detail bogey dope responses go into
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum VerbosityLevel {
+    /// Also the full aircraft type, heading, and time to closure
+    Detailed,
+    #[default]
+    Standard,
+    /// Only bearing, range, altitude, and aspect, for a busy frequency
+    Terse,
 }
 
 #[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SelfPosition {
+    FixedCoords {
+        latitude: f64,
+        longitude: f64,
+        altitude_ft: f64,
+    },
+    /// Aircraft in Tacview whose pilot or name matches the callsign
+    TrackedCallsign { callsign: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 pub enum Coalition {
     Blue,
     Red,
@@ -32,10 +97,591 @@ impl Coalition {
     }
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct FrequencyEntry {
+    pub frequency_mhz: f64,
+    /// e.g. "AM" or "FM"
+    pub modulation: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+/// Racetrack orbit of a tanker or other support aircraft.
+/// The orbit starts at the anchor, flies `leg_nm` on `heading_deg`, and turns right.
+#[derive(Clone, Debug, Deserialize)]
+pub struct OrbitTrack {
+    pub callsign: String,
+    pub anchor_lat: f64,
+    pub anchor_lon: f64,
+    pub heading_deg: f64,
+    pub leg_nm: f64,
+    pub altitude_ft: f64,
+    /// Ground speed along the orbit
+    #[serde(default = "default_orbit_speed_knots")]
+    pub speed_knots: f64,
+}
+
+/// Intent answered with a fixed response
+#[derive(Clone, Debug, Deserialize)]
+pub struct CustomIntentConfig {
+    /// Tells the parser when a transmission has this intent
+    pub description: String,
+    pub response: String,
+}
+
 #[derive(Clone, Deserialize)]
 pub struct CommonConfig {
     pub callsign: String,
     pub coalition: Coalition,
+    /// Known asset frequencies, keyed by asset name (e.g. tanker callsign)
+    #[serde(default)]
+    pub frequency_db: HashMap<String, FrequencyEntry>,
+    /// Orbits of tankers, answering tanker requests with their estimated position
+    #[serde(default)]
+    pub orbit_tracks: Vec<OrbitTrack>,
+    /// Answer weather requests from the weather in the Tacview global properties
+    #[serde(default)]
+    pub enable_weather_requests: bool,
+    /// Intents answered with a canned response, keyed by intent name
+    #[serde(default)]
+    pub custom_intents: HashMap<String, CustomIntentConfig>,
+    /// Ignore coalitions entirely, for servers whose Tacview feed has no coalition data
+    #[serde(default)]
+    pub open_mode: bool,
+    /// Identical transcripts within this window are only parsed once
+    #[serde(default = "default_transcript_dedup_window_secs")]
+    pub transcript_dedup_window_secs: f64,
+    /// Strip non-speech annotations like "[BLANK_AUDIO]" or "(wind blowing)" from transcripts
+    #[serde(default = "default_strip_non_speech_annotations")]
+    pub strip_non_speech_annotations: bool,
+    /// Correct brevity terms Whisper commonly mishears with the built-in corrections,
+    /// like "Bogota" for "bogey dope", before parsing
+    #[serde(default = "default_brevity_correction")]
+    pub brevity_correction: bool,
+    /// Additional mishearings to correct, replacing the built-in correction of the same words.
+    /// Applied even when `brevity_correction` is off.
+    #[serde(default)]
+    pub brevity_corrections: HashMap<String, String>,
+    /// Split a transcript into up to this many transmissions for pilots talking back to back, each starting at
+    /// the controller's callsign or the pilot's callsign right before it. 1 to parse every transcript as a single transmission.
+    #[serde(default = "default_max_transcript_segments")]
+    pub max_transcript_segments: usize,
+    /// Merge transmissions of the same SRS client less than this many seconds apart into one,
+    /// for pilots keying up in quick succession. Disabled if unset.
+    #[serde(default)]
+    pub transmission_merge_window_secs: Option<f64>,
+    /// Stop merging once a transmission is this many seconds long
+    #[serde(default = "default_max_merged_transmission_secs")]
+    pub max_merged_transmission_secs: f64,
+    /// Reset the Opus decoder after this many consecutive decode errors, 0 to never reset
+    #[serde(default = "default_opus_decoder_reset_after_errors")]
+    pub opus_decoder_reset_after_errors: usize,
+    /// Send only the frames WebRTC VAD detects as voice to Whisper, skipping audio with too little voice
+    #[serde(default)]
+    pub use_webrtc_vad: bool,
+    /// Read letters of alphanumeric identifiers in callsigns phonetically, e.g. "4A" as "4 alpha"
+    #[serde(default)]
+    pub phonetic_callsigns: bool,
+    /// Read ranges digit by digit ("4 7") like bearings, instead of as a number ("47")
+    #[serde(default)]
+    pub spell_range_digits: bool,
+    /// Contacts beyond this range in nautical miles are not given as snap vectors
+    #[serde(default = "default_snap_max_range_nm")]
+    pub snap_max_range_nm: f64,
+    #[serde(default)]
+    pub fast_mover: FastMoverConfig,
+    /// Number of recent headings averaged for the aspect of a contact, 1 to disable smoothing
+    #[serde(default = "default_heading_smoothing_window")]
+    pub heading_smoothing_window: usize,
+    /// Bandits at most this many nautical miles farther than the closest one are tied with it.
+    /// Ties go to the bandit hotter to the requester, then to the lowest object ID.
+    #[serde(default = "default_closest_bandit_tie_nm")]
+    pub closest_bandit_tie_nm: f64,
+    /// Extrapolate bandit positions from their last update by up to this many seconds
+    #[serde(default)]
+    pub dead_reckoning_max_secs: Option<f64>,
+    /// Append the time until the requester and the bandit merge to bogey dopes, e.g. "merge in 45 seconds"
+    #[serde(default)]
+    pub report_time_to_merge: bool,
+    /// A moving requester whose position was not updated for this many seconds is stale.
+    /// Disabled if unset.
+    #[serde(default)]
+    pub requester_stale_secs: Option<f64>,
+    #[serde(default)]
+    pub stale_requester_handling: StaleRequesterHandling,
+    /// Response to a bogey dope when the requester is the only air object on scope,
+    /// as opposed to "Scope is currently clear" when there are air objects but no bandits
+    #[serde(default = "default_no_picture_response")]
+    pub no_picture_response: String,
+    /// Preface responses with what the controller understood, e.g. "copy your bogey dope"
+    #[serde(default)]
+    pub read_back_requests: bool,
+    /// Bearings from the bullseye in pictures and threat warnings. BRAA calls always use digits.
+    #[serde(default)]
+    pub bearing_pronunciation: BearingPronunciation,
+    /// Adjust the verbosity of bogey dope responses to the number of pilots and threats
+    #[serde(default = "default_auto_verbosity")]
+    pub auto_verbosity: bool,
+    /// Always use this verbosity instead
+    #[serde(default)]
+    pub verbosity_override: Option<VerbosityLevel>,
+    /// Check the spoken callsign against the SRS client that transmitted
+    #[serde(default)]
+    pub sender_check: SenderCheck,
+    /// Use the callsign the SRS client used before when a pilot does not state one
+    #[serde(default = "default_infer_missing_callsign")]
+    pub infer_missing_callsign: bool,
+    /// Only respond to transmissions addressed to exactly the controller's callsign,
+    /// instead of also with a qualifier like "Magic flight"
+    #[serde(default)]
+    pub strict_addressing: bool,
+    /// Never respond to these callsigns, e.g. mission scripts transmitting on SRS
+    #[serde(default)]
+    pub callsign_blocklist: Vec<String>,
+    /// Only respond to these callsigns if set
+    #[serde(default)]
+    pub callsign_allowlist: Option<Vec<String>>,
+    /// Relay transcripts with "mayday" spoken at least twice in a row on the frequency of every controller
+    #[serde(default)]
+    pub enable_mayday_relay: bool,
+    /// A pilot's MAYDAY is relayed at most once in this many seconds
+    #[serde(default = "default_mayday_relay_cooldown_secs")]
+    pub mayday_relay_cooldown_secs: u64,
+    /// Callsigns allowed to broadcast "knock it off" through the controller, disabled if empty
+    #[serde(default)]
+    pub knock_it_off_callsigns: Vec<String>,
+    /// Ask for a radio check the first time each SRS client transmits
+    #[serde(default)]
+    pub first_contact_radio_check: bool,
+    /// Response to a pilot of another coalition
+    #[serde(default = "default_wrong_coalition_response")]
+    pub wrong_coalition_response: String,
+    /// Parse "comms check" apart from "radio check", answering it with `comms_check_response`
+    /// instead of "5 by 5"
+    #[serde(default)]
+    pub distinguish_comms_check: bool,
+    #[serde(default = "default_comms_check_response")]
+    pub comms_check_response: String,
+    /// Also give a pilot of another coalition a bullseye picture of their hostiles, for training
+    #[serde(default)]
+    pub wrong_coalition_picture: bool,
+    /// Broadcast a picture call every this many seconds
+    #[serde(default)]
+    pub auto_picture_interval_secs: Option<u64>,
+    /// Callsign the automatic picture calls are addressed to, "all players" if unset
+    #[serde(default)]
+    pub auto_picture_to_callsign: Option<String>,
+    /// An automatic picture is not repeated if every group moved less than this many nautical miles
+    #[serde(default = "default_picture_dedup_radius_nm")]
+    pub picture_dedup_radius_nm: f64,
+    /// Bandits within this many nautical miles of each other are called as a group, otherwise singly
+    #[serde(default = "default_group_radius_nm")]
+    pub group_radius_nm: f64,
+    /// Friendlies within this many nautical miles of each other on a similar heading fly as one element
+    #[serde(default = "default_formation_radius_nm")]
+    pub formation_radius_nm: f64,
+    /// Announce friendly pilots joining the session to this callsign, e.g. "all players"
+    #[serde(default)]
+    pub new_pilot_broadcast_callsign: Option<String>,
+    /// Broadcast a threat call when a hostile air defense is within range of a friendly aircraft
+    #[serde(default)]
+    pub enable_threat_warnings: bool,
+    /// Range in nautical miles from the nearest friendly aircraft within which threats are called
+    #[serde(default = "default_threat_warning_range_nm")]
+    pub threat_warning_range_nm: f64,
+    /// Only call threats whose name contains one of these, or every air defense if empty
+    #[serde(default)]
+    pub threat_warning_types: Vec<String>,
+    /// Engagement ranges in nautical miles of air defenses whose name contains the key, the largest if several do.
+    /// Pilots heading into one of these are warned to defend.
+    #[serde(default)]
+    pub sam_ranges: HashMap<String, f64>,
+    /// Warn pilots this many minutes before they would enter an engagement zone
+    #[serde(default = "default_sam_warning_time_minutes")]
+    pub sam_warning_time_minutes: f64,
+    /// Position of the controller itself, e.g. an AWACS orbit
+    #[serde(default)]
+    pub self_position: Option<SelfPosition>,
+    /// Range of the controller's radar in nautical miles, used when `self_position` is known
+    #[serde(default = "default_self_radar_range_nm")]
+    pub self_radar_range_nm: f64,
+    #[serde(default)]
+    pub radio_discipline: RadioDisciplineConfig,
+    #[serde(default)]
+    pub overload: OverloadConfig,
+    #[serde(default)]
+    pub marshal: MarshalConfig,
+    #[serde(default)]
+    pub altitude_layers: AltitudeLayerConfig,
+    #[serde(default)]
+    pub picture_on_change: PictureOnChangeConfig,
+    /// Only respond to requests during this daily window, always if unset
+    #[serde(default)]
+    pub active_hours: Option<ActiveHoursConfig>,
+}
+
+/// Transcripts not meeting these are treated as garbled and ignored
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct RadioDisciplineConfig {
+    pub min_word_count: usize,
+    pub max_word_count: usize,
+    /// The AWACS callsign must appear within this many leading words
+    pub requires_callsign_in_first_n_words: usize,
+    /// Require at least one known pilot callsign to appear
+    pub reject_if_no_known_callsign: bool,
+}
+
+impl Default for RadioDisciplineConfig {
+    fn default() -> Self {
+        Self {
+            min_word_count: 3,
+            max_word_count: 30,
+            requires_callsign_in_first_n_words: 5,
+            reject_if_no_known_callsign: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MarshalPoint {
+    /// Radial from the carrier in degrees
+    pub radial_deg: f64,
+    pub distance_nm: f64,
+    pub altitude_ft: f64,
+    /// Reserves the point for pilots whose callsign starts with this, e.g. "Chevy 1" for the Chevy 1 flight
+    #[serde(default)]
+    pub callsign_slot: Option<String>,
+}
+
+/// Altitude bands for labeling picture groups as "high", "medium", or "low"
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct AltitudeLayerConfig {
+    pub enabled: bool,
+    /// Groups below this altitude are low
+    pub low_max_ft: f64,
+    /// Groups at or above this altitude are high
+    pub high_min_ft: f64,
+}
+
+impl Default for AltitudeLayerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low_max_ft: 10000.,
+            high_min_ft: 25000.,
+        }
+    }
+}
+
+/// Automatic picture calls when the picture changes, instead of on a fixed interval.
+/// A group appearing or disappearing, moving more than `picture_dedup_radius_nm`,
+/// or crossing one of the range thresholds to the nearest friendly is a change.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct PictureOnChangeConfig {
+    pub enabled: bool,
+    /// At least this long between two automatic pictures
+    pub min_interval_secs: u64,
+    /// Ranges in nautical miles from the nearest friendly
+    pub range_thresholds_nm: Vec<f64>,
+}
+
+impl Default for PictureOnChangeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_interval_secs: 60,
+            range_thresholds_nm: vec![40., 20., 10.],
+        }
+    }
+}
+
+/// Daily window in UTC during which requests are handled.
+/// Uses the Tacview mission time when known, the wall clock otherwise.
+#[derive(Clone, Deserialize)]
+pub struct ActiveHoursConfig {
+    /// "HH:MM"
+    pub start: String,
+    /// "HH:MM", may be earlier than `start` for a window past midnight
+    pub end: String,
+    /// Response to requests outside the window, or ignore them silently if unset
+    #[serde(default)]
+    pub off_duty_response: Option<String>,
+}
+
+impl ActiveHoursConfig {
+    /// Seconds since midnight
+    fn parse_time(time: &str) -> anyhow::Result<f64> {
+        let (hours, minutes) = time
+            .split_once(':')
+            .with_context(|| format!("`{time}` is not in HH:MM format"))?;
+        let hours = hours
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|hours| *hours < 24)
+            .with_context(|| format!("invalid hours in `{time}`"))?;
+        let minutes = minutes
+            .trim()
+            .parse::<u32>()
+            .ok()
+            .filter(|minutes| *minutes < 60)
+            .with_context(|| format!("invalid minutes in `{time}`"))?;
+        Ok(f64::from(hours * 3600 + minutes * 60))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        Self::parse_time(&self.start).context("invalid `active_hours.start`")?;
+        Self::parse_time(&self.end).context("invalid `active_hours.end`")?;
+        Ok(())
+    }
+
+    /// Whether the time of day in seconds since midnight is within the window
+    pub fn contains(&self, time_of_day: f64) -> bool {
+        let (Ok(start), Ok(end)) = (Self::parse_time(&self.start), Self::parse_time(&self.end))
+        else {
+            return true;
+        };
+        if start <= end {
+            (start..end).contains(&time_of_day)
+        } else {
+            time_of_day >= start || time_of_day < end
+        }
+    }
+}
+
+/// Carrier recovery stack. Marshal requests are only answered when marshal points are configured.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct MarshalConfig {
+    /// Marshal points in approach order, one pilot each
+    pub marshal_radials: Vec<MarshalPoint>,
+    /// Time between successive approaches
+    pub approach_interval_minutes: u64,
+}
+
+impl Default for MarshalConfig {
+    fn default() -> Self {
+        Self {
+            marshal_radials: Vec::new(),
+            approach_interval_minutes: 1,
+        }
+    }
+}
+
+/// When more requests than `max_requests` arrive within `window_secs`, the AWACS announces
+/// "working, standby", handles requests one per `throttle_interval_secs`, and drops repeated requests
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct OverloadConfig {
+    pub enabled: bool,
+    pub max_requests: usize,
+    pub window_secs: f64,
+    pub throttle_interval_secs: f64,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_requests: 6,
+            window_secs: 30.,
+            throttle_interval_secs: 3.,
+        }
+    }
+}
+
+/// Thresholds for calling a contact "fast" or "very fast".
+/// Mach is used when Tacview provides it, knots of true airspeed otherwise.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct FastMoverThresholds {
+    pub fast_mach: f64,
+    pub very_fast_mach: f64,
+    pub fast_knots: f64,
+    pub very_fast_knots: f64,
+}
+
+impl Default for FastMoverThresholds {
+    fn default() -> Self {
+        Self {
+            fast_mach: 1.,
+            very_fast_mach: 1.5,
+            fast_knots: 600.,
+            very_fast_knots: 900.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum AircraftCategory {
+    Fighter,
+    Attack,
+    Bomber,
+    Transport,
+    Helicopter,
+}
+
+impl AircraftCategory {
+    fn default_fast_mover_thresholds(&self) -> Option<FastMoverThresholds> {
+        let (fast_mach, very_fast_mach, fast_knots, very_fast_knots) = match self {
+            Self::Fighter => return None,
+            Self::Attack => (0.75, 0.9, 450., 550.),
+            Self::Bomber => (0.9, 1.2, 550., 750.),
+            Self::Transport => (0.65, 0.8, 400., 500.),
+            Self::Helicopter => (0.25, 0.3, 150., 200.),
+        };
+        Some(FastMoverThresholds {
+            fast_mach,
+            very_fast_mach,
+            fast_knots,
+            very_fast_knots,
+        })
+    }
+}
+
+/// Thresholds for fighters and unknown types, and per aircraft category for the others
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FastMoverConfig {
+    #[serde(flatten)]
+    pub default: FastMoverThresholds,
+    /// Overrides of the built-in thresholds of each category
+    pub categories: HashMap<AircraftCategory, FastMoverThresholds>,
+}
+
+impl FastMoverConfig {
+    pub fn thresholds(&self, category: Option<AircraftCategory>) -> FastMoverThresholds {
+        category
+            .and_then(|category| {
+                self.categories
+                    .get(&category)
+                    .copied()
+                    .or_else(|| category.default_fast_mover_thresholds())
+            })
+            .unwrap_or(self.default)
+    }
+}
+
+fn default_heading_smoothing_window() -> usize {
+    3
+}
+
+fn default_closest_bandit_tie_nm() -> f64 {
+    0.5
+}
+
+fn default_snap_max_range_nm() -> f64 {
+    80.
+}
+
+fn default_sam_warning_time_minutes() -> f64 {
+    2.
+}
+
+fn default_self_radar_range_nm() -> f64 {
+    200.
+}
+
+fn default_threat_warning_range_nm() -> f64 {
+    40.
+}
+
+fn default_picture_dedup_radius_nm() -> f64 {
+    3.
+}
+
+fn default_orbit_speed_knots() -> f64 {
+    420.
+}
+
+fn default_group_radius_nm() -> f64 {
+    3.
+}
+
+fn default_formation_radius_nm() -> f64 {
+    1.
+}
+
+fn default_wrong_coalition_response() -> String {
+    "You are not in my coalition".to_string()
+}
+
+fn default_comms_check_response() -> String {
+    "copy".to_string()
+}
+
+fn default_no_picture_response() -> String {
+    "No picture".to_string()
+}
+
+fn default_transcript_dedup_window_secs() -> f64 {
+    2.
+}
+
+fn default_auto_verbosity() -> bool {
+    true
+}
+
+fn default_mayday_relay_cooldown_secs() -> u64 {
+    60
+}
+
+fn default_infer_missing_callsign() -> bool {
+    true
+}
+
+fn default_strip_non_speech_annotations() -> bool {
+    true
+}
+
+fn default_brevity_correction() -> bool {
+    true
+}
+
+fn default_opus_decoder_reset_after_errors() -> usize {
+    10
+}
+
+fn default_max_merged_transmission_secs() -> f64 {
+    30.
+}
+
+fn default_max_transcript_segments() -> usize {
+    1
+}
+
+impl CommonConfig {
+    /// Tacview coalition of friendlies, or `None` in open mode
+    pub fn friendly_tacview_coalition(&self) -> Option<&'static str> {
+        (!self.open_mode).then(|| self.coalition.as_tacview_coalition())
+    }
+
+    /// Tacview coalition of hostiles, or `None` in open mode
+    pub fn hostile_tacview_coalition(&self) -> Option<&'static str> {
+        (!self.open_mode).then(|| self.coalition.flip().as_tacview_coalition())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(active_hours) = &self.active_hours {
+            active_hours.validate()?;
+        }
+        for track in &self.orbit_tracks {
+            anyhow::ensure!(
+                track.speed_knots > 0.,
+                "`speed_knots` of orbit track `{}` must be positive",
+                track.callsign
+            );
+            anyhow::ensure!(
+                track.leg_nm > 0.,
+                "`leg_nm` of orbit track `{}` must be positive",
+                track.callsign
+            );
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Deserialize)]
@@ -45,6 +691,34 @@ pub struct TacviewConfig {
     pub username: String,
     #[serde(default)]
     pub password: Option<String>,
+    /// Objects that never receive a position are dropped after this many seconds
+    #[serde(default = "default_incomplete_object_timeout_secs")]
+    pub incomplete_object_timeout_secs: u64,
+    /// Reconnect when no data was received for this many seconds
+    #[serde(default = "default_heartbeat_timeout_secs")]
+    pub heartbeat_timeout_secs: u64,
+    /// Track at most this many objects, evicting the least recently updated aircraft and weapons. Unlimited if unset.
+    #[serde(default)]
+    pub max_objects: Option<usize>,
+    /// Index object positions, for faster closest bandit lookups on missions with many objects
+    #[serde(default)]
+    pub spatial_index: bool,
+    /// Positions implying a faster ground speed are treated as glitches, keeping the last speed and track
+    #[serde(default = "default_max_plausible_speed_knots")]
+    pub max_plausible_speed_knots: f64,
+}
+
+fn default_heartbeat_timeout_secs() -> u64 {
+    60
+}
+
+/// Above the aircraft and most missiles of DCS, e.g. a MiG-25 at about 1650 knots at altitude
+fn default_max_plausible_speed_knots() -> f64 {
+    4000.
+}
+
+fn default_incomplete_object_timeout_secs() -> u64 {
+    300
 }
 
 #[derive(Clone, Deserialize)]
@@ -71,6 +745,29 @@ pub struct SrsConfig {
     pub username: String,
     pub coalition: SrsConfigCoalition,
     pub frequency: u64,
+    /// Report the bot's position to the SRS server: `common.self_position` if set,
+    /// the friendly bullseye otherwise
+    #[serde(default)]
+    pub report_position: bool,
+    /// Altitude reported at the bullseye
+    #[serde(default = "default_bullseye_altitude_ft")]
+    pub bullseye_altitude_ft: f64,
+}
+
+fn default_bullseye_altitude_ft() -> f64 {
+    30000.
+}
+
+/// Kind of an outgoing transmission, for per-kind speech settings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum MessageCategory {
+    /// Replies to pilot requests
+    #[default]
+    Response,
+    /// Threat, SAM, and distress calls
+    Warning,
+    /// Unsolicited calls to everyone, like automatic pictures
+    Broadcast,
 }
 
 #[derive(Clone, Deserialize)]
@@ -78,22 +775,483 @@ pub struct OpenAiConfig {
     pub api_key: String,
     pub speech_voice: String,
     pub speech_speed: f64,
+    /// Speech speeds of message categories, overriding `speech_speed`
+    #[serde(default)]
+    pub speech_speed_overrides: HashMap<MessageCategory, f64>,
+    #[serde(default)]
+    pub transcribe_temperature: f64,
+    /// ISO-639-1 code of the language pilots are expected to speak
+    #[serde(default = "default_transcribe_language")]
+    pub transcribe_language: String,
+    /// Let Whisper detect the language of a pilot's transmissions, remembering it per SRS client once confirmed
+    #[serde(default)]
+    pub language_detect_enabled: bool,
+    /// Transcribe again with this model when Whisper is not confident, e.g. "gpt-4o-transcribe"
+    #[serde(default)]
+    pub transcribe_escalation_model: Option<String>,
+    /// Transcriptions below this confidence from 0 to 1 are escalated
+    #[serde(default = "default_transcribe_escalation_min_confidence")]
+    pub transcribe_escalation_min_confidence: f64,
+    #[serde(default)]
+    pub chat_temperature: f64,
+    #[serde(default = "default_chat_max_tokens")]
+    pub chat_max_tokens: usize,
+    /// OGG Opus file transmitted instead when speech generation fails
+    #[serde(default)]
+    pub speech_fallback_file: Option<PathBuf>,
+    /// Send speech as SSML, for speech APIs supporting it like Azure TTS or ElevenLabs.
+    /// Only used without `speech_providers`, which each have their own `use_ssml`.
+    #[serde(default)]
+    pub use_ssml: bool,
+    /// Markup of the speech sent with `use_ssml`
+    #[serde(default)]
+    pub ssml: SsmlConfig,
+    /// Transcription providers tried in order until one succeeds, OpenAI only if empty
+    #[serde(default)]
+    pub transcribe_providers: Vec<ProviderConfig>,
+    /// Speech providers tried in order until one succeeds, OpenAI only if empty
+    #[serde(default)]
+    pub speech_providers: Vec<ProviderConfig>,
 }
 
+/// OpenAI compatible API serving transcription or speech, e.g. a local Whisper or TTS server
 #[derive(Clone, Deserialize)]
-pub struct Config {
+pub struct ProviderConfig {
+    /// Logged when the provider is used or fails
+    pub name: String,
+    #[serde(default = "default_provider_base_url")]
+    pub base_url: String,
+    /// `openai.api_key` if unset
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Model used with this provider instead of the default OpenAI one.
+    /// Transcriptions escalated to `openai.transcribe_escalation_model` use that model instead.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Send speech as SSML, for speech providers supporting it like Azure TTS or ElevenLabs
+    #[serde(default)]
+    pub use_ssml: bool,
+}
+
+impl ProviderConfig {
+    /// URL of the API endpoint, e.g. `audio/speech`, with or without a trailing slash on `base_url`
+    pub fn endpoint_url(&self, endpoint: &str) -> String {
+        format!("{}/{endpoint}", self.base_url.trim_end_matches('/'))
+    }
+}
+
+/// Pauses and pace of speech sent as SSML
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct SsmlConfig {
+    /// Pause after each callsign
+    pub callsign_break_ms: u64,
+    /// Speaking rate in percent of the provider's normal rate
+    pub rate_percent: u32,
+}
+
+impl Default for SsmlConfig {
+    fn default() -> Self {
+        Self {
+            callsign_break_ms: 200,
+            rate_percent: 90,
+        }
+    }
+}
+
+fn default_provider_base_url() -> String {
+    "https://api.openai.com/v1".to_string()
+}
+
+fn default_chat_max_tokens() -> usize {
+    100
+}
+
+fn default_transcribe_escalation_min_confidence() -> f64 {
+    0.5
+}
+
+fn default_transcribe_language() -> String {
+    "en".to_string()
+}
+
+impl OpenAiConfig {
+    /// Configured transcription providers, or OpenAI alone
+    pub fn get_transcribe_providers(&self) -> Cow<'_, [ProviderConfig]> {
+        self.get_providers(&self.transcribe_providers)
+    }
+
+    /// Configured speech providers, or OpenAI alone
+    pub fn get_speech_providers(&self) -> Cow<'_, [ProviderConfig]> {
+        self.get_providers(&self.speech_providers)
+    }
+
+    fn get_providers<'a>(&self, providers: &'a [ProviderConfig]) -> Cow<'a, [ProviderConfig]> {
+        if providers.is_empty() {
+            Cow::Owned(vec![ProviderConfig {
+                name: "openai".to_string(),
+                base_url: default_provider_base_url(),
+                api_key: None,
+                model: None,
+                use_ssml: self.use_ssml,
+            }])
+        } else {
+            Cow::Borrowed(providers)
+        }
+    }
+
+    pub fn get_speech_speed(&self, category: MessageCategory) -> f64 {
+        self.speech_speed_overrides
+            .get(&category)
+            .copied()
+            .unwrap_or(self.speech_speed)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            (0. ..=2.).contains(&self.transcribe_temperature),
+            "`openai.transcribe_temperature` must be between 0 and 2"
+        );
+        anyhow::ensure!(
+            self.transcribe_language.len() == 2
+                && self
+                    .transcribe_language
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase()),
+            "`openai.transcribe_language` must be an ISO-639-1 code like \"en\""
+        );
+        anyhow::ensure!(
+            (0. ..=1.).contains(&self.transcribe_escalation_min_confidence),
+            "`openai.transcribe_escalation_min_confidence` must be between 0 and 1"
+        );
+        anyhow::ensure!(
+            (0. ..=2.).contains(&self.chat_temperature),
+            "`openai.chat_temperature` must be between 0 and 2"
+        );
+        anyhow::ensure!(
+            (0.25..=4.).contains(&self.speech_speed),
+            "`openai.speech_speed` must be between 0.25 and 4"
+        );
+        for (category, speed) in &self.speech_speed_overrides {
+            anyhow::ensure!(
+                (0.25..=4.).contains(speed),
+                "`openai.speech_speed_overrides` of {:?} must be between 0.25 and 4",
+                category
+            );
+        }
+        anyhow::ensure!(
+            self.chat_max_tokens > 0,
+            "`openai.chat_max_tokens` must be positive"
+        );
+        for provider in self
+            .transcribe_providers
+            .iter()
+            .chain(&self.speech_providers)
+        {
+            anyhow::ensure!(
+                provider.base_url.starts_with("http://")
+                    || provider.base_url.starts_with("https://"),
+                "`base_url` of provider `{}` must be an HTTP URL",
+                provider.name
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub enum WeatherProvider {
+    OpenMeteo,
+    /// Requires `api_key`
+    AvWx,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TheaterAirport {
+    pub icao: String,
+    /// Spoken name, e.g. "Nellis"
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct WeatherApiConfig {
+    pub provider: WeatherProvider,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    /// Altimeter requests are answered for the airport closest to the requester
+    pub theater_airports: Vec<TheaterAirport>,
+}
+
+/// A controller instance serving one coalition on its own SRS frequency
+#[derive(Clone, Deserialize)]
+pub struct CoalitionConfig {
     pub common: CommonConfig,
-    pub tacview: TacviewConfig,
     pub srs: SrsConfig,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Config {
+    /// Base config file to inherit from, relative to this file.
+    /// Keys present in this file override the base.
+    #[serde(default)]
+    pub extends: Option<PathBuf>,
+    /// Single controller syntax, used together with `srs`
+    #[serde(default)]
+    pub common: Option<CommonConfig>,
+    pub tacview: TacviewConfig,
+    #[serde(default)]
+    pub srs: Option<SrsConfig>,
     pub openai: OpenAiConfig,
+    /// Real-world weather for altimeter requests
+    #[serde(default)]
+    pub weather_api: Option<WeatherApiConfig>,
+    /// Multiple controller syntax, instead of `common` and `srs`
+    #[serde(default)]
+    pub coalitions: Vec<CoalitionConfig>,
+}
+
+/// Maximum number of base config files in an `extends` chain
+const MAX_EXTENDS_DEPTH: usize = 3;
+
+/// Merges `overlay` into `base`, recursing into tables. Other values in `overlay` replace the base.
+fn merge_toml(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_toml(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
 }
 
 impl Config {
+    /// Reads the config file and every base config file it extends, merged with the child taking precedence
+    async fn read_merged_toml(path: &Path) -> anyhow::Result<toml::Value> {
+        let mut chain = Vec::new();
+        let mut visited = Vec::new();
+        let mut next_path = Some(path.to_path_buf());
+        while let Some(path) = next_path.take() {
+            if chain.len() > MAX_EXTENDS_DEPTH {
+                anyhow::bail!(
+                    "config file `{}` exceeds the maximum of {} levels of `extends`",
+                    path.display(),
+                    MAX_EXTENDS_DEPTH
+                );
+            }
+            let canonical_path = tokio::fs::canonicalize(&path)
+                .await
+                .with_context(|| format!("failed to read config file `{}`", path.display()))?;
+            if visited.contains(&canonical_path) {
+                anyhow::bail!("config file `{}` extends itself", path.display());
+            }
+            visited.push(canonical_path);
+
+            let s = tokio::fs::read_to_string(&path)
+                .await
+                .with_context(|| format!("failed to read config file `{}`", path.display()))?;
+            let value = toml::from_str::<toml::Value>(&s)
+                .with_context(|| format!("failed to parse config file `{}`", path.display()))?;
+            if let Some(extends) = value.get("extends") {
+                let extends = extends.as_str().with_context(|| {
+                    format!(
+                        "`extends` must be a path in config file `{}`",
+                        path.display()
+                    )
+                })?;
+                next_path = Some(path.parent().unwrap_or_else(|| Path::new("")).join(extends));
+            }
+            chain.push(value);
+        }
+
+        let mut merged = chain.pop().unwrap();
+        while let Some(value) = chain.pop() {
+            merge_toml(&mut merged, value);
+        }
+        Ok(merged)
+    }
+
     pub async fn from_path(path: &Path) -> anyhow::Result<Self> {
-        let s = tokio::fs::read_to_string(path)
-            .await
-            .with_context(|| format!("failed to read config file `{}`", path.display()))?;
-        toml::from_str(&s)
-            .with_context(|| format!("failed to parse config file `{}`", path.display()))
+        let config: Self = Self::read_merged_toml(path)
+            .await?
+            .try_into()
+            .with_context(|| format!("failed to parse config file `{}`", path.display()))?;
+        if let Some(extends) = &config.extends {
+            tracing::info!("config file extends `{}`", extends.display());
+        }
+        config
+            .validate()
+            .with_context(|| format!("invalid config file `{}`", path.display()))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        match (&self.common, &self.srs, self.coalitions.is_empty()) {
+            (Some(_), Some(_), true) | (None, None, false) => {}
+            (None, None, true) => {
+                anyhow::bail!("either `common` and `srs`, or `coalitions` must be configured")
+            }
+            (_, _, false) => {
+                anyhow::bail!("`common` and `srs` cannot be used together with `coalitions`")
+            }
+            _ => anyhow::bail!("`common` and `srs` must be configured together"),
+        }
+        for coalition_config in self.coalition_configs() {
+            coalition_config.common.validate()?;
+        }
+        self.openai.validate()
+    }
+
+    /// Every controller instance to run
+    pub fn coalition_configs(&self) -> Vec<CoalitionConfig> {
+        match (&self.common, &self.srs) {
+            (Some(common), Some(srs)) => vec![CoalitionConfig {
+                common: common.clone(),
+                srs: srs.clone(),
+            }],
+            _ => self.coalitions.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_CONFIG: &str = r#"
+[common]
+callsign = "Magic"
+coalition = "Blue"
+
+[tacview]
+host = "tacview.example.com"
+port = 42674
+username = "magic-bot"
+
+[srs]
+host = "srs.example.com"
+port = 5002
+username = "Magic Bot"
+coalition = "Blue"
+frequency = 136000000
+
+[openai]
+api_key = "OPENAI_API_KEY"
+speech_voice = "alloy"
+speech_speed = 1.0
+"#;
+
+    /// Writes the files into a directory of the test's own under the system temp directory
+    fn write_config_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "magic-gci-bot-config-{}-{test}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn child_toml_overrides_nested_keys_only() {
+        let mut base = toml::from_str::<toml::Value>(
+            "[common]\ncallsign = \"Magic\"\nopen_mode = true\n[srs]\nfrequency = 1",
+        )
+        .unwrap();
+        let overlay =
+            toml::from_str::<toml::Value>("[common]\ncallsign = \"Overlord\"\n[srs]\nport = 2")
+                .unwrap();
+        merge_toml(&mut base, overlay);
+        assert_eq!(base["common"]["callsign"].as_str(), Some("Overlord"));
+        assert_eq!(base["common"]["open_mode"].as_bool(), Some(true));
+        assert_eq!(base["srs"]["frequency"].as_integer(), Some(1));
+        assert_eq!(base["srs"]["port"].as_integer(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn extending_configs_take_precedence_over_their_bases() {
+        let dir = write_config_files(
+            "precedence",
+            &[
+                ("base.toml", BASE_CONFIG),
+                (
+                    "squadron.toml",
+                    "extends = \"base.toml\"\n[common]\ncallsign = \"Overlord\"\n\
+                     [srs]\nfrequency = 137000000\nusername = \"Overlord Bot\"",
+                ),
+                (
+                    "bot.toml",
+                    "extends = \"squadron.toml\"\n[srs]\nfrequency = 138000000",
+                ),
+            ],
+        );
+
+        let squadron = Config::from_path(&dir.join("squadron.toml")).await.unwrap();
+        assert_eq!(squadron.common.as_ref().unwrap().callsign, "Overlord");
+        assert_eq!(squadron.srs.as_ref().unwrap().frequency, 137000000);
+
+        let bot = Config::from_path(&dir.join("bot.toml")).await.unwrap();
+        let srs = bot.srs.as_ref().unwrap();
+        assert_eq!(bot.common.as_ref().unwrap().callsign, "Overlord");
+        assert_eq!(srs.frequency, 138000000);
+        assert_eq!(srs.username, "Overlord Bot");
+        assert_eq!(bot.tacview.host, "tacview.example.com");
+        assert_eq!(bot.extends.as_deref(), Some(Path::new("squadron.toml")));
+    }
+
+    #[test]
+    fn provider_endpoint_url_ignores_a_trailing_slash() {
+        let provider = |base_url: &str| ProviderConfig {
+            name: "local".to_string(),
+            base_url: base_url.to_string(),
+            api_key: None,
+            model: None,
+            use_ssml: false,
+        };
+        for base_url in ["http://localhost:8000/v1", "http://localhost:8000/v1/"] {
+            assert_eq!(
+                provider(base_url).endpoint_url("audio/speech"),
+                "http://localhost:8000/v1/audio/speech"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn extends_cycle_is_an_error() {
+        let dir = write_config_files(
+            "cycle",
+            &[
+                ("a.toml", "extends = \"b.toml\""),
+                ("b.toml", "extends = \"a.toml\""),
+            ],
+        );
+        let error = Config::from_path(&dir.join("a.toml")).await.err().unwrap();
+        assert!(format!("{error:#}").contains("extends itself"), "{error:#}");
+    }
+
+    #[tokio::test]
+    async fn extends_deeper_than_the_maximum_is_an_error() {
+        let dir = write_config_files(
+            "depth",
+            &[
+                ("0.toml", BASE_CONFIG),
+                ("1.toml", "extends = \"0.toml\""),
+                ("2.toml", "extends = \"1.toml\""),
+                ("3.toml", "extends = \"2.toml\""),
+                ("4.toml", "extends = \"3.toml\""),
+            ],
+        );
+        assert!(Config::from_path(&dir.join("3.toml")).await.is_ok());
+        let error = Config::from_path(&dir.join("4.toml")).await.err().unwrap();
+        assert!(format!("{error:#}").contains("maximum"), "{error:#}");
     }
 }
@@ -1,6 +1,10 @@
 //! Module about actual GCIing logic
 
-use std::sync::Arc;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use geo::{HaversineBearing, Point};
 use itertools::Itertools;
@@ -8,16 +12,91 @@ use stopper::Stopper;
 use tokio::sync::RwLock;
 
 use crate::{
-    config::CommonConfig,
+    api::tacview::Heartbeat,
+    config::{
+        AircraftCategory, BearingPronunciation, Coalition, CommonConfig, FastMoverConfig,
+        MessageCategory, SelfPosition, StaleRequesterHandling, WeatherApiConfig,
+    },
     recognition::{IncomingTransmission, Intent},
-    state::TacviewState,
-    transmission::OutgoingTransmission,
+    state::{normalize_callsign, TacviewObject, TacviewState},
+    timeline::TimelineRecorder,
+    transmission::{AbortedCommits, OutgoingTransmission},
 };
 
+mod addressing;
+mod callsign_blocklist;
+mod cardinal_conversion;
+mod commit;
+mod context_awareness;
+mod custom_intent;
+mod distress_relay;
+mod dope;
+mod formation;
+mod frequency_advisory;
+mod friendly_callsign_broadcast;
+mod knock_it_off;
+mod marshal;
+mod overload;
+mod picture;
+mod picture_brevity;
+mod picture_timer;
+mod racetrack;
+mod registry;
+mod snap_vector;
+mod srs_position;
+mod status;
+mod surface_attack;
+mod threat_warning;
+mod time_to_merge;
+mod two_ship_element;
+mod weather;
+mod weather_relay;
+
+pub use commit::commit_update_loop;
+pub use distress_relay::mayday_relay_loop;
+use dope::{format_dope, get_dope_report, DopeStyle};
+pub use friendly_callsign_broadcast::friendly_callsign_broadcast_loop;
+pub use marshal::marshal_stack_loop;
+use overload::{Admission, OverloadGuard};
+pub use picture_timer::picture_timer_loop;
+pub use registry::{HandlerRegistry, IntentHandler};
+pub use srs_position::srs_position_loop;
+pub use surface_attack::surface_attack_loop;
+pub use threat_warning::threat_warning_loop;
+
+/// Controller state shared between intent handlers and background tasks
+#[derive(Default)]
+pub struct GciState {
+    /// Keyed by pilot callsign
+    pub active_commits: Mutex<HashMap<String, commit::CommitState>>,
+    /// Shared with the transmission loop, which drops queued updates of aborted commits
+    pub aborted_commits: AbortedCommits,
+    /// Shared by every controller
+    pub timeline: TimelineRecorder,
+    pub marshal_stack: Mutex<marshal::MarshalStack>,
+}
+
+/// Callsign used when a transmission is addressed to everyone on frequency
+pub const BROADCAST_CALLSIGN: &str = "all players";
+
+/// Callsign used to answer a transmission whose sender did not state a callsign
+const LAST_STATION_CALLING: &str = "last station calling";
+
+/// A stale requester position is not dead reckoned further than this
+const STALE_REQUESTER_MAX_DEAD_RECKONING: Duration = Duration::from_secs(60);
+
 fn meters_to_feet(meters: f64) -> f64 {
     meters * 3.28084
 }
 
+fn feet_to_meters(feet: f64) -> f64 {
+    feet / 3.28084
+}
+
+fn meters_per_second_to_knots(meters_per_second: f64) -> f64 {
+    meters_per_second * 1.94384
+}
+
 fn get_bearing((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
     Point::new(lon1, lat1).haversine_bearing(Point::new(lon2, lat2))
 }
@@ -40,6 +119,57 @@ fn get_range((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
     d * 0.539957
 }
 
+/// Spells out each digit, zero-padded to `width`, e.g. `spell_digits(90, 3)` is "0 9 0"
+fn spell_digits(n: usize, width: usize) -> String {
+    format!("{:0width$}", n).chars().join(" ")
+}
+
+/// Altitude readout, e.g. "20 thousands" for contacts, or "angels 20" for friendly advisories
+fn format_altitude(meters: f64, angels: bool) -> String {
+    let feet = meters_to_feet(meters).max(0.);
+    match (angels, (feet / 1000.) as usize) {
+        (false, 0) => "on the deck".to_string(),
+        (false, 1) => "one thousand".to_string(),
+        (false, a) => format!("{} thousands", a),
+        (true, 0) => format!("cherubs {}", (feet / 100.) as usize),
+        (true, a) => format!("angels {}", a),
+    }
+}
+
+/// Position and altitude in meters of the controller itself, if configured and known
+fn get_self_position(
+    state: &TacviewState,
+    common_config: &CommonConfig,
+) -> Option<((f64, f64), f64)> {
+    match common_config.self_position.as_ref()? {
+        SelfPosition::FixedCoords {
+            latitude,
+            longitude,
+            altitude_ft,
+        } => Some(((*latitude, *longitude), feet_to_meters(*altitude_ft))),
+        SelfPosition::TrackedCallsign { callsign } => {
+            let object = state.find_own_aircraft(callsign)?;
+            Some((state.get_latlng(object)?, object.coords.altitude?))
+        }
+    }
+}
+
+/// Bearing and range from the bullseye, e.g. "bullseye 0 9 0, 25"
+fn format_bullseye(
+    bullseye_latlng: (f64, f64),
+    latlng: (f64, f64),
+    bearing_pronunciation: BearingPronunciation,
+) -> String {
+    format!(
+        "bullseye {}, {}",
+        cardinal_conversion::format_bearing(
+            get_bearing(bullseye_latlng, latlng),
+            bearing_pronunciation
+        ),
+        get_range(bullseye_latlng, latlng) as usize,
+    )
+}
+
 fn get_cardinal_point(heading: f64) -> &'static str {
     match (heading as isize + 360) % 360 {
         0..=22 | 338..=360 => "north",
@@ -53,6 +183,27 @@ fn get_cardinal_point(heading: f64) -> &'static str {
     }
 }
 
+fn get_speed_descriptor(object: &TacviewObject, config: &FastMoverConfig) -> Option<&'static str> {
+    let category = get_aircraft_category(get_aircraft_ty(object.name.as_deref()));
+    let thresholds = config.thresholds(category);
+    let (speed, fast, very_fast) = if let Some(mach) = object.mach {
+        (mach, thresholds.fast_mach, thresholds.very_fast_mach)
+    } else {
+        (
+            meters_per_second_to_knots(object.tas?),
+            thresholds.fast_knots,
+            thresholds.very_fast_knots,
+        )
+    };
+    if speed >= very_fast {
+        Some("very fast")
+    } else if speed >= fast {
+        Some("fast")
+    } else {
+        None
+    }
+}
+
 fn get_aircraft_ty(name: Option<&str>) -> &str {
     match name {
         Some("Tornado GR4") | Some("Tornado IDS") => "tornado",
@@ -129,39 +280,350 @@ fn get_aircraft_ty(name: Option<&str>) -> &str {
     }
 }
 
+/// Category of the aircraft type from `get_aircraft_ty`, `None` if unknown.
+/// Helicopters come first, so "black hawk" and "havoc" are never taken for the "hawk" and "havok" jets.
+fn get_aircraft_category(ty: &str) -> Option<AircraftCategory> {
+    match ty {
+        "black shark" | "hind" | "hip" | "halo" | "helix" | "black hawk" | "super stallion"
+        | "chinook" | "sea king" | "apache" | "cobra" | "seahawk" | "huey" | "havoc" | "kiowa"
+        | "gazelle" => Some(AircraftCategory::Helicopter),
+        "hornet" | "tomcat" | "phantom" | "flogger" | "flanker" | "foxbat" | "foxhound"
+        | "mirage" | "eagle" | "fulcrum" | "viper" | "thunder" | "tiger" | "saber" | "fagot"
+        | "farmer" | "fishbed" | "viggen" => Some(AircraftCategory::Fighter),
+        "tornado" | "frogfoot" | "fitter" | "fencer" | "nighthawk" | "warthog" | "harrier"
+        | "havok" | "fullback" | "hawk" | "albatros" | "aviojet" | "predator" | "reaper"
+        | "wing loong" => Some(AircraftCategory::Attack),
+        "backfire" | "stratofortress" | "bear" | "blackjack" | "lancer" => {
+            Some(AircraftCategory::Bomber)
+        }
+        "viking" | "hercules" | "curl" | "clank" | "globemaster" | "mainstay" | "sentry"
+        | "midas" | "hawkeye" | "candid" | "codling" | "hercules tanker" | "stratotanker"
+        | "mainring" => Some(AircraftCategory::Transport),
+        _ => None,
+    }
+}
+
+struct RadioCheckHandler;
+
+impl IntentHandler for RadioCheckHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        _state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message: "5 by 5".to_string(),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+/// Plain acknowledgment, for communities distinguishing a comms check from a radio check
+struct CommsCheckHandler;
+
+impl IntentHandler for CommsCheckHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        _state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message: common_config.comms_check_response.clone(),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+struct BogeyDopeHandler;
+
+impl IntentHandler for BogeyDopeHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        handle_bogey_dope(incoming_transmission, state, common_config, transmission_tx);
+    }
+}
+
+pub fn register_builtin_handlers(
+    registry: &mut HandlerRegistry,
+    common_config: &CommonConfig,
+    weather_api_config: Option<&WeatherApiConfig>,
+    gci_state: Arc<GciState>,
+    tacview_heartbeat: Heartbeat,
+    priority_transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    mayday_relay_tx: tokio::sync::broadcast::Sender<(Coalition, String)>,
+) {
+    registry.register(&Intent::RadioCheck, Arc::new(RadioCheckHandler));
+    if common_config.distinguish_comms_check {
+        registry.register(&Intent::CommsCheck, Arc::new(CommsCheckHandler));
+    }
+    registry.register(&Intent::RequestBogeyDope, Arc::new(BogeyDopeHandler));
+    registry.register(&Intent::RequestSnap, Arc::new(snap_vector::SnapHandler));
+    registry.register(
+        &Intent::RequestElementBogeyDope {
+            wingman_callsign: String::new(),
+        },
+        Arc::new(two_ship_element::ElementBogeyDopeHandler),
+    );
+    registry.register(
+        &Intent::RequestCommit {
+            bearing: None,
+            range: None,
+        },
+        Arc::new(commit::CommitHandler::new(gci_state.clone())),
+    );
+    registry.register(
+        &Intent::RequestAbort,
+        Arc::new(commit::AbortHandler::new(gci_state.clone())),
+    );
+
+    registry.register(
+        &Intent::RequestStatus,
+        Arc::new(status::StatusHandler::new(tacview_heartbeat)),
+    );
+
+    if common_config.enable_mayday_relay {
+        registry.register(
+            &Intent::Mayday {
+                transcript: String::new(),
+            },
+            Arc::new(distress_relay::MaydayHandler::new(
+                mayday_relay_tx,
+                gci_state.timeline.clone(),
+            )),
+        );
+    }
+
+    if !common_config.knock_it_off_callsigns.is_empty() {
+        registry.register(
+            &Intent::KnockItOff,
+            Arc::new(knock_it_off::KnockItOffHandler::new(
+                priority_transmission_tx,
+            )),
+        );
+    }
+
+    if common_config.enable_weather_requests {
+        registry.register(&Intent::RequestWeather, Arc::new(weather::WeatherHandler));
+    }
+
+    if let Some(weather_api_config) = weather_api_config {
+        registry.register(
+            &Intent::RequestAltimeter,
+            Arc::new(weather_relay::WeatherRelayHandler::new(
+                weather_api_config.clone(),
+            )),
+        );
+    }
+
+    if !common_config.orbit_tracks.is_empty() {
+        registry.register(
+            &Intent::RequestTanker { tanker: None },
+            Arc::new(racetrack::TankerHandler),
+        );
+    }
+
+    if !common_config.marshal.marshal_radials.is_empty() {
+        registry.register(
+            &Intent::RequestMarshal,
+            Arc::new(marshal::MarshalHandler::new(gci_state.clone())),
+        );
+    }
+
+    let frequency_handler = Arc::new(frequency_advisory::FrequencyHandler::new(
+        common_config.frequency_db.clone(),
+    ));
+    registry.register(
+        &Intent::RequestFrequency {
+            asset: String::new(),
+        },
+        frequency_handler.clone(),
+    );
+    registry.register(
+        &Intent::ReportFrequency {
+            asset: String::new(),
+            frequency_mhz: 0.,
+            modulation: None,
+        },
+        frequency_handler,
+    );
+
+    for (name, custom_intent) in &common_config.custom_intents {
+        if registry
+            .get(&Intent::Custom { name: name.clone() })
+            .is_some()
+        {
+            tracing::warn!(%name, "custom intent overrides a built-in intent");
+        }
+        registry.register_name(
+            name,
+            Arc::new(custom_intent::CannedResponseHandler::new(
+                custom_intent.response.clone(),
+            )),
+        );
+    }
+}
+
+/// Seconds since midnight UTC
+fn wall_clock_time_of_day() -> f64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (now.as_secs() % 86400) as f64
+}
+
 pub async fn gci_loop(
     common_config: CommonConfig,
     state: Arc<RwLock<TacviewState>>,
+    handler_registry: HandlerRegistry,
     mut recognition_rx: tokio::sync::mpsc::UnboundedReceiver<IncomingTransmission>,
     transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
     stopper: Stopper,
 ) {
-    while let Some(incoming_transmission) =
-        stopper.stop_future(recognition_rx.recv()).await.flatten()
-    {
-        if incoming_transmission.to_callsign.to_lowercase() == common_config.callsign.to_lowercase()
-        {
-            match incoming_transmission.intent {
-                Intent::Unknown => {
+    let mut overload_guard = OverloadGuard::new(common_config.overload.clone());
+    // Requests throttled while overloaded, with the time to handle them, in order
+    let mut throttled = VecDeque::<(Instant, IncomingTransmission)>::new();
+    // GUIDs of SRS clients that have transmitted to the controller
+    let mut seen_senders = HashSet::<String>::new();
+    loop {
+        let throttle_deadline = throttled
+            .front()
+            .map(|(at, _)| tokio::time::Instant::from_std(*at));
+        let next = stopper
+            .stop_future(async {
+                tokio::select! {
+                    incoming_transmission = recognition_rx.recv() => {
+                        incoming_transmission.map(|incoming_transmission| (incoming_transmission, false))
+                    }
+                    () = tokio::time::sleep_until(throttle_deadline.unwrap_or_else(tokio::time::Instant::now)), if throttle_deadline.is_some() => {
+                        throttled.pop_front().map(|(_, incoming_transmission)| (incoming_transmission, true))
+                    }
+                }
+            })
+            .await
+            .flatten();
+        let Some((incoming_transmission, was_throttled)) = next else {
+            break;
+        };
+        if was_throttled {
+            handle_request(
+                &handler_registry,
+                incoming_transmission,
+                &*state.read().await,
+                &common_config,
+                &transmission_tx,
+            );
+            continue;
+        }
+
+        if addressing::is_addressed_to(
+            &incoming_transmission.to_callsign,
+            &common_config.callsign,
+            common_config.strict_addressing,
+        ) {
+            if callsign_blocklist::is_blocked(&incoming_transmission.from_callsign, &common_config)
+            {
+                tracing::debug!(from_callsign = %incoming_transmission.from_callsign, "ignoring blocked callsign");
+                continue;
+            }
+
+            // Distress calls are relayed even without a callsign, and at any hour
+            let is_mayday = matches!(incoming_transmission.intent, Intent::Mayday { .. });
+            if let Some(active_hours) = common_config.active_hours.as_ref().filter(|_| !is_mayday) {
+                let time_of_day = state
+                    .read()
+                    .await
+                    .mission_time_of_day()
+                    .unwrap_or_else(wall_clock_time_of_day);
+                if !active_hours.contains(time_of_day) {
+                    tracing::debug!(from_callsign = %incoming_transmission.from_callsign, "ignoring request outside active hours");
+                    if let Some(off_duty_response) = &active_hours.off_duty_response {
+                        let _ = transmission_tx.send(OutgoingTransmission {
+                            to_callsign: Some(incoming_transmission.from_callsign.clone())
+                                .filter(|from_callsign| !from_callsign.trim().is_empty())
+                                .unwrap_or_else(|| LAST_STATION_CALLING.to_string()),
+                            from_callsign: common_config.callsign.clone(),
+                            message: off_duty_response.clone(),
+                            category: MessageCategory::Response,
+                            commit_started_at: None,
+                        });
+                    }
                     continue;
                 }
-                Intent::RadioCheck => {
+            }
+
+            if incoming_transmission.from_callsign.trim().is_empty() && !is_mayday {
+                let _ = transmission_tx.send(OutgoingTransmission {
+                    to_callsign: LAST_STATION_CALLING.to_string(),
+                    from_callsign: common_config.callsign.clone(),
+                    message: "say again your callsign".to_string(),
+                    category: MessageCategory::Response,
+                    commit_started_at: None,
+                });
+                continue;
+            }
+
+            if let Some(sender) = &incoming_transmission.sender {
+                if seen_senders.insert(sender.guid.clone())
+                    && common_config.first_contact_radio_check
+                    && !matches!(
+                        incoming_transmission.intent,
+                        Intent::RadioCheck | Intent::CommsCheck
+                    )
+                    && !is_mayday
+                {
                     let _ = transmission_tx.send(OutgoingTransmission {
-                        to_callsign: incoming_transmission.from_callsign,
+                        to_callsign: incoming_transmission.from_callsign.clone(),
                         from_callsign: common_config.callsign.clone(),
-                        message: "5 by 5".to_string(),
+                        message: "radio check, how do you read".to_string(),
+                        category: MessageCategory::Response,
+                        commit_started_at: None,
                     });
                 }
-                Intent::RequestBogeyDope => {
-                    let state = state.read().await;
-                    handle_bogey_dope(
-                        incoming_transmission,
-                        &state,
-                        &common_config,
-                        &transmission_tx,
-                    );
+            }
+
+            match overload_guard.admit(&incoming_transmission) {
+                Admission::Handle => {}
+                Admission::Throttle { announce, at } => {
+                    if announce {
+                        let _ = transmission_tx.send(OutgoingTransmission {
+                            to_callsign: BROADCAST_CALLSIGN.to_string(),
+                            from_callsign: common_config.callsign.clone(),
+                            message: "working, standby".to_string(),
+                            category: MessageCategory::Response,
+                            commit_started_at: None,
+                        });
+                    }
+                    throttled.push_back((at, incoming_transmission));
+                    continue;
+                }
+                Admission::Drop => {
+                    tracing::debug!(from_callsign = %incoming_transmission.from_callsign, intent = ?incoming_transmission.intent, "dropping duplicate request while overloaded");
+                    continue;
                 }
             }
+
+            handle_request(
+                &handler_registry,
+                incoming_transmission,
+                &*state.read().await,
+                &common_config,
+                &transmission_tx,
+            );
         } else {
             tracing::warn!(to_callsign = %incoming_transmission.to_callsign, "incoming transmission is not for the AWACS");
         }
@@ -169,126 +631,843 @@ pub async fn gci_loop(
     tracing::info!("exiting GCI loop");
 }
 
-fn handle_bogey_dope(
+/// Runs the handler of the request's intent, with a read back if enabled
+fn handle_request(
+    handler_registry: &HandlerRegistry,
+    incoming_transmission: IncomingTransmission,
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let Some(handler) = handler_registry.get(&incoming_transmission.intent) else {
+        tracing::debug!(intent = ?incoming_transmission.intent, "no handler for intent");
+        return;
+    };
+    match get_read_back(&incoming_transmission.intent).filter(|_| common_config.read_back_requests)
+    {
+        Some(read_back) => handle_with_read_back(
+            handler.as_ref(),
+            incoming_transmission,
+            read_back,
+            state,
+            common_config,
+            transmission_tx,
+        ),
+        None => handler.handle(incoming_transmission, state, common_config, transmission_tx),
+    }
+}
+
+/// Confirmation of what the controller understood, e.g. "copy your bogey dope"
+fn get_read_back(intent: &Intent) -> Option<String> {
+    let read_back = match intent {
+        Intent::RequestBogeyDope => "copy your bogey dope".to_string(),
+        Intent::RequestElementBogeyDope { .. } => "copy your element bogey dope".to_string(),
+        Intent::RequestSnap => "copy your snap".to_string(),
+        Intent::RequestCommit { .. } => "copy your commit".to_string(),
+        Intent::RequestAbort => "copy your abort".to_string(),
+        Intent::RequestWeather => "copy your weather request".to_string(),
+        Intent::RequestAltimeter => "copy your altimeter request".to_string(),
+        Intent::RequestMarshal => "copy your marshal request".to_string(),
+        Intent::RequestStatus => "copy your status request".to_string(),
+        Intent::RequestTanker { .. } => "copy your tanker request".to_string(),
+        Intent::RequestFrequency { asset } => {
+            format!("copy your request for the {asset} frequency")
+        }
+        Intent::ReportFrequency { asset, .. } => format!("copy {asset} frequency"),
+        // The response itself confirms these
+        Intent::RadioCheck
+        | Intent::CommsCheck
+        | Intent::KnockItOff
+        | Intent::Mayday { .. }
+        | Intent::Custom { .. } => return None,
+    };
+    Some(read_back)
+}
+
+/// Runs the handler, prefixing its first reply to the requester with the read back
+fn handle_with_read_back(
+    handler: &dyn IntentHandler,
     incoming_transmission: IncomingTransmission,
+    read_back: String,
     state: &TacviewState,
     common_config: &CommonConfig,
     transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
 ) {
-    if let Some(from_object) = state.find_air_object_by_callsign(
-        &incoming_transmission.from_callsign,
-        common_config.coalition.as_tacview_coalition(),
+    let requester = incoming_transmission.from_callsign.clone();
+    let mut read_back = Some(read_back);
+    let transmission_tx = transmission_tx.clone();
+    let mut forward = move |mut outgoing_transmission: OutgoingTransmission| {
+        if outgoing_transmission.to_callsign == requester {
+            if let Some(read_back) = read_back.take() {
+                outgoing_transmission.message =
+                    format!("{read_back}, {}", outgoing_transmission.message);
+            }
+        }
+        let _ = transmission_tx.send(outgoing_transmission);
+    };
+
+    let (handler_tx, mut handler_rx) = tokio::sync::mpsc::unbounded_channel();
+    handler.handle(incoming_transmission, state, common_config, &handler_tx);
+    drop(handler_tx);
+    loop {
+        match handler_rx.try_recv() {
+            Ok(outgoing_transmission) => forward(outgoing_transmission),
+            Err(tokio::sync::mpsc::error::TryRecvError::Empty) => {
+                // The handler replies from a task of its own
+                tokio::spawn(async move {
+                    while let Some(outgoing_transmission) = handler_rx.recv().await {
+                        forward(outgoing_transmission);
+                    }
+                });
+                return;
+            }
+            Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => return,
+        }
+    }
+}
+
+/// Finds the requesting aircraft and its absolute position, replying to the requester when it cannot be used
+fn locate_requester<'a>(
+    incoming_transmission: &IncomingTransmission,
+    state: &'a TacviewState,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) -> Option<(&'a TacviewObject, (f64, f64))> {
+    let reply = |message: &str| {
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign.clone(),
+            from_callsign: common_config.callsign.clone(),
+            message: message.to_string(),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    };
+
+    let Some(from_object) = state
+        .find_air_object_by_callsign(
+            &incoming_transmission.from_callsign,
+            common_config.friendly_tacview_coalition(),
+        )
+        .or_else(|| state.find_air_object_by_callsign(&incoming_transmission.from_callsign, None))
+    else {
+        reply("I cannot find you on scope");
+        return None;
+    };
+    if !common_config.open_mode
+        && from_object.coalition.as_deref() != Some(common_config.coalition.as_tacview_coalition())
+    {
+        reply(&common_config.wrong_coalition_response);
+        if common_config.wrong_coalition_picture {
+            // Picture from the requester's side, so our coalition is the hostile one
+            let groups = picture::get_picture_groups(
+                state,
+                common_config,
+                common_config.friendly_tacview_coalition(),
+            );
+            let bullseye_latlng = state
+                .find_bullseye(from_object.coalition.as_deref())
+                .and_then(|bullseye| state.get_latlng(bullseye));
+            reply(&picture::format_picture(
+                &groups,
+                bullseye_latlng,
+                &common_config.altitude_layers,
+                common_config.bearing_pronunciation,
+            ));
+        }
+        return None;
+    }
+    let Some(mut from_object_latlng) = state.get_latlng(from_object) else {
+        tracing::warn!("Tacview state is not initialized");
+        return None;
+    };
+    if let (Some(stale_secs), Some(position_age)) = (
+        common_config.requester_stale_secs,
+        from_object.get_position_age(),
     ) {
-        if from_object.coalition.as_deref() == Some(common_config.coalition.as_tacview_coalition())
-        {
-            if let (
-                Some(reference_latitude),
-                Some(reference_longitude),
-                Some(from_object_latitude),
-                Some(from_object_longitude),
-            ) = (
-                state.reference_latitude,
-                state.reference_longitude,
-                from_object.coords.latitude,
-                from_object.coords.longitude,
-            ) {
-                let from_object_latlng = (
-                    reference_latitude + from_object_latitude,
-                    reference_longitude + from_object_longitude,
-                );
-
-                let bandits = state.list_air_object_by_coalition(
-                    common_config.coalition.flip().as_tacview_coalition(),
-                );
-
-                if let Some((closest_bandit, range)) = bandits
-                    .filter_map(|bandit| {
-                        if let (Some(bandit_lat), Some(bandit_lng), Some(_), Some(_)) = (
-                            bandit.coords.latitude,
-                            bandit.coords.longitude,
-                            bandit.coords.altitude,
-                            bandit.coords.heading,
-                        ) {
-                            let bandit_latlng = (
-                                reference_latitude + bandit_lat,
-                                reference_longitude + bandit_lng,
-                            );
-                            Some((bandit, get_range(from_object_latlng, bandit_latlng)))
-                        } else {
-                            None
-                        }
-                    })
-                    .min_by(|(_bandit1, range1), (_bandit2, range2)| {
-                        range1.partial_cmp(range2).unwrap()
-                    })
+        if position_age.as_secs_f64() > stale_secs {
+            match common_config.stale_requester_handling {
+                StaleRequesterHandling::DeadReckon
+                    if position_age <= STALE_REQUESTER_MAX_DEAD_RECKONING =>
                 {
-                    let bandit_latlng = (
-                        reference_latitude + closest_bandit.coords.latitude.unwrap(),
-                        reference_longitude + closest_bandit.coords.longitude.unwrap(),
-                    );
-
-                    let bearing = get_bearing(from_object_latlng, bandit_latlng);
-
-                    let range = range as usize;
-
-                    let altitude_thousands =
-                        meters_to_feet(closest_bandit.coords.altitude.unwrap()) / 1000.;
-                    let altitude_str = match altitude_thousands as usize {
-                        0 => "on the deck".to_string(),
-                        1 => "one thousand".to_string(),
-                        a => format!("{} thousands", a),
-                    };
-
-                    let bandit_heading = closest_bandit.coords.heading.unwrap();
-                    let aspect_degrees = (((bearing - bandit_heading) as isize) + 360) % 360;
-                    let bandit_heading_cardinal = get_cardinal_point(bandit_heading);
-                    let aspect = match aspect_degrees {
-                        0..=60 | 300..=360 => {
-                            format!("drag {}", bandit_heading_cardinal)
-                        }
-                        61..=100 | 260..=299 => {
-                            format!("beam {}", bandit_heading_cardinal)
-                        }
-                        101..=140 | 220..=259 => {
-                            format!("flank {}", bandit_heading_cardinal)
-                        }
-                        _ => "hot".to_string(),
-                    };
-
-                    let bearing = ((bearing as isize) + 360) % 360;
-                    let bearing_str = format!("{:03}", bearing).chars().join(" ");
-
-                    let ty = get_aircraft_ty(closest_bandit.name.as_deref());
-
-                    let _ = transmission_tx.send(OutgoingTransmission {
-                        to_callsign: incoming_transmission.from_callsign,
-                        from_callsign: common_config.callsign.clone(),
-                        message: format!(
-                            "lead group braa {bearing_str}, {range}, {altitude_str}, {aspect}, hostile, {ty}"
-                        ),
-                    });
-                } else {
-                    let _ = transmission_tx.send(OutgoingTransmission {
-                        to_callsign: incoming_transmission.from_callsign,
-                        from_callsign: common_config.callsign.clone(),
-                        message: "Scope is currently clear".to_string(),
-                    });
+                    tracing::info!(from_callsign = %incoming_transmission.from_callsign, position_age_secs = position_age.as_secs_f64(), "dead reckoning stale requester position");
+                    from_object_latlng = state
+                        .get_extrapolated_latlng(from_object, STALE_REQUESTER_MAX_DEAD_RECKONING)
+                        .unwrap_or(from_object_latlng);
+                }
+                _ => {
+                    tracing::info!(from_callsign = %incoming_transmission.from_callsign, position_age_secs = position_age.as_secs_f64(), "requester position is stale");
+                    reply("your position is stale");
+                    return None;
                 }
-            } else {
-                tracing::warn!("Tacview state is not initialized");
             }
-        } else {
-            let _ = transmission_tx.send(OutgoingTransmission {
-                to_callsign: incoming_transmission.from_callsign,
-                from_callsign: common_config.callsign.clone(),
-                message: "You are not in my coalition".to_string(),
-            });
         }
+    }
+    Some((from_object, from_object_latlng))
+}
+
+/// Absolute position of the bandit, dead reckoned to the current time if enabled
+fn get_bandit_latlng(
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    bandit: &TacviewObject,
+) -> Option<(f64, f64)> {
+    match common_config.dead_reckoning_max_secs {
+        Some(max_secs) => {
+            state.get_extrapolated_latlng(bandit, Duration::from_secs_f64(max_secs.max(0.)))
+        }
+        None => state.get_latlng(bandit),
+    }
+}
+
+/// Range of the first spatial index lookup of the closest bandit, doubled until a bandit is found
+const CLOSEST_BANDIT_SEARCH_RANGE_NM: f64 = 20.;
+/// Beyond this range, the closest bandit is looked up with a linear scan
+const CLOSEST_BANDIT_MAX_SEARCH_RANGE_NM: f64 = 320.;
+/// Bandit positions are extrapolated, so they may be this far from their indexed position
+const CLOSEST_BANDIT_SEARCH_MARGIN_NM: f64 = 2.;
+
+/// Closest bandit with a known position, altitude, and heading, with its range in nautical miles
+fn find_closest_bandit<'a>(
+    state: &'a TacviewState,
+    common_config: &CommonConfig,
+    from_object: &TacviewObject,
+    from_object_latlng: (f64, f64),
+) -> Option<(&'a TacviewObject, f64)> {
+    let coalition = common_config.hostile_tacview_coalition();
+    let mut search_range = CLOSEST_BANDIT_SEARCH_RANGE_NM;
+    while search_range <= CLOSEST_BANDIT_MAX_SEARCH_RANGE_NM {
+        let Some(bandits) = state.list_air_object_by_coalition_near(
+            coalition,
+            from_object_latlng,
+            search_range + CLOSEST_BANDIT_SEARCH_MARGIN_NM,
+        ) else {
+            break;
+        };
+        let closest = find_closest_bandit_among(
+            state,
+            common_config,
+            from_object,
+            from_object_latlng,
+            bandits,
+        );
+        // Any bandit beyond the search range would be farther, and not tied
+        if let Some((_, range)) = closest {
+            if range + common_config.closest_bandit_tie_nm <= search_range {
+                return closest;
+            }
+        }
+        search_range *= 2.;
+    }
+    find_closest_bandit_among(
+        state,
+        common_config,
+        from_object,
+        from_object_latlng,
+        state.list_air_object_by_coalition(coalition),
+    )
+}
+
+fn find_closest_bandit_among<'a>(
+    state: &'a TacviewState,
+    common_config: &CommonConfig,
+    from_object: &TacviewObject,
+    from_object_latlng: (f64, f64),
+    bandits: impl Iterator<Item = &'a TacviewObject>,
+) -> Option<(&'a TacviewObject, f64)> {
+    let candidates = bandits
+        .filter(|bandit| !std::ptr::eq(*bandit, from_object))
+        .filter(|bandit| bandit.coords.altitude.is_some() && bandit.coords.heading.is_some())
+        .filter_map(|bandit| {
+            let bandit_latlng = get_bandit_latlng(state, common_config, bandit)?;
+            // In open mode every other aircraft is a bandit, but not the requester's wingmen
+            if common_config.open_mode
+                && formation::is_in_formation(
+                    common_config,
+                    (from_object, from_object_latlng),
+                    (bandit, bandit_latlng),
+                )
+            {
+                return None;
+            }
+            let angle_off = get_angle_off(
+                bandit.smoothed_heading(common_config.heading_smoothing_window)?,
+                get_bearing(bandit_latlng, from_object_latlng),
+            );
+            Some((
+                bandit,
+                get_range(from_object_latlng, bandit_latlng),
+                angle_off,
+            ))
+        })
+        .collect::<Vec<_>>();
+    // Ties are against the closest range, so the result doesn't depend on the order of the bandits
+    let closest_range = candidates
+        .iter()
+        .map(|(_, range, _)| *range)
+        .min_by(f64::total_cmp)?;
+    candidates
+        .into_iter()
+        .filter(|(_, range, _)| *range <= closest_range + common_config.closest_bandit_tie_nm)
+        .min_by(|(bandit1, _, angle_off1), (bandit2, _, angle_off2)| {
+            angle_off1
+                .total_cmp(angle_off2)
+                .then(bandit1.id.cmp(&bandit2.id))
+        })
+        .map(|(bandit, range, _)| (bandit, range))
+}
+
+/// Number of bandits within the group radius of the given one, including itself.
+/// Like pictures, only bandits with a known altitude and heading are counted.
+fn count_group_contacts(
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    bandit: &TacviewObject,
+    bandit_latlng: (f64, f64),
+) -> usize {
+    1 + state
+        .list_air_object_by_coalition(common_config.hostile_tacview_coalition())
+        .filter(|other| {
+            other.id != bandit.id
+                && other.coords.altitude.is_some()
+                && other.coords.heading.is_some()
+        })
+        .filter_map(|other| get_bandit_latlng(state, common_config, other))
+        .filter(|other_latlng| {
+            get_range(bandit_latlng, *other_latlng) <= common_config.group_radius_nm
+        })
+        .count()
+}
+
+/// Angle in degrees between a heading and a bearing, 0 when pointing straight at it
+fn get_angle_off(heading: f64, bearing: f64) -> f64 {
+    let difference = (heading - bearing).rem_euclid(360.);
+    difference.min(360. - difference)
+}
+
+fn handle_bogey_dope(
+    incoming_transmission: IncomingTransmission,
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let Some((from_object, from_object_latlng)) = locate_requester(
+        &incoming_transmission,
+        state,
+        common_config,
+        transmission_tx,
+    ) else {
+        return;
+    };
+
+    if let Some((closest_bandit, _range)) =
+        find_closest_bandit(state, common_config, from_object, from_object_latlng)
+    {
+        let Some(report) = get_dope_report(
+            state,
+            common_config,
+            from_object,
+            from_object_latlng,
+            closest_bandit,
+        ) else {
+            return;
+        };
+
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message: format_dope(
+                &report,
+                DopeStyle {
+                    verbosity: context_awareness::get_verbosity(state, common_config),
+                    ..DopeStyle::new(common_config)
+                },
+            ),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
     } else {
+        let message = if state.has_other_air_objects(from_object) {
+            "Scope is currently clear".to_string()
+        } else {
+            common_config.no_picture_response.clone()
+        };
         let _ = transmission_tx.send(OutgoingTransmission {
             to_callsign: incoming_transmission.from_callsign,
             from_callsign: common_config.callsign.clone(),
-            message: "I cannot find you on scope".to_string(),
+            message,
+            category: MessageCategory::Response,
+            commit_started_at: None,
         });
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::collections::HashSet;
+
+    use tacview_realtime_client::acmi::record::object_property::{Coords, Tag};
+
+    use super::*;
+
+    /// Blue controller "Magic", with `extra` lines of the common config
+    pub(crate) fn common_config(extra: &str) -> CommonConfig {
+        toml::from_str(&format!(
+            "callsign = \"Magic\"\ncoalition = \"Blue\"\n{extra}"
+        ))
+        .unwrap()
+    }
+
+    /// Air object at the latitude and longitude relative to a reference of 0, 0
+    pub(crate) fn air_object(
+        id: u64,
+        pilot: Option<&str>,
+        coalition: &str,
+        (latitude, longitude): (f64, f64),
+        altitude_ft: f64,
+        heading: f64,
+    ) -> TacviewObject {
+        TacviewObject {
+            id,
+            coords: Coords {
+                latitude: Some(latitude),
+                longitude: Some(longitude),
+                altitude: Some(feet_to_meters(altitude_ft)),
+                heading: Some(heading),
+                ..Default::default()
+            },
+            ty: HashSet::from([Tag::Air, Tag::FixedWing]),
+            name: Some("F-16C_50".to_string()),
+            pilot: pilot.map(str::to_string),
+            coalition: Some(coalition.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Friendly of a Blue controller
+    pub(crate) const FRIENDLY: &str = "Enemies";
+
+    pub(crate) fn state_with(objects: impl IntoIterator<Item = TacviewObject>) -> TacviewState {
+        let mut state = TacviewState::new(false);
+        state.reference_latitude = Some(0.);
+        state.reference_longitude = Some(0.);
+        for object in objects {
+            state.objects.insert(object.id, object);
+        }
+        state
+    }
+
+    pub(crate) fn request(from_callsign: &str, intent: Intent) -> IncomingTransmission {
+        IncomingTransmission {
+            to_callsign: "Magic".to_string(),
+            from_callsign: from_callsign.to_string(),
+            intent,
+            sender: None,
+        }
+    }
+
+    /// Messages of the transmissions sent so far
+    pub(crate) fn sent_messages(
+        transmission_rx: &mut tokio::sync::mpsc::UnboundedReceiver<OutgoingTransmission>,
+    ) -> Vec<String> {
+        std::iter::from_fn(|| transmission_rx.try_recv().ok())
+            .map(|transmission| transmission.message)
+            .collect()
+    }
+
+    fn bogey_dope_messages(state: &TacviewState, common_config: &CommonConfig) -> Vec<String> {
+        let (transmission_tx, mut transmission_rx) = tokio::sync::mpsc::unbounded_channel();
+        handle_bogey_dope(
+            request("Chevy 1-1", Intent::RequestBogeyDope),
+            state,
+            common_config,
+            &transmission_tx,
+        );
+        sent_messages(&mut transmission_rx)
+    }
+
+    #[test]
+    fn bogey_dope_alone_on_scope_is_no_picture() {
+        let state = state_with([air_object(
+            1,
+            Some("Chevy 1-1"),
+            FRIENDLY,
+            (0., 0.),
+            20000.,
+            0.,
+        )]);
+        assert_eq!(
+            bogey_dope_messages(&state, &common_config("")),
+            ["No picture"]
+        );
+        assert_eq!(
+            bogey_dope_messages(
+                &state,
+                &common_config("no_picture_response = \"Picture clean\"")
+            ),
+            ["Picture clean"]
+        );
+    }
+
+    #[test]
+    fn bogey_dope_without_bandits_is_clear() {
+        let state = state_with([
+            air_object(1, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.),
+            air_object(2, Some("Chevy 1-2"), FRIENDLY, (0.01, 0.), 20000., 0.),
+        ]);
+        assert_eq!(
+            bogey_dope_messages(&state, &common_config("")),
+            ["Scope is currently clear"]
+        );
+    }
+
+    /// Recipients and messages the GCI loop sends for the transmissions, answering radio checks
+    async fn gci_loop_messages(
+        common_config: CommonConfig,
+        incoming_transmissions: Vec<IncomingTransmission>,
+    ) -> Vec<(String, String)> {
+        let (recognition_tx, recognition_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (transmission_tx, mut transmission_rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut handler_registry = HandlerRegistry::new();
+        handler_registry.register(&Intent::RadioCheck, Arc::new(RadioCheckHandler));
+        for incoming_transmission in incoming_transmissions {
+            recognition_tx.send(incoming_transmission).unwrap();
+        }
+        drop(recognition_tx);
+        gci_loop(
+            common_config,
+            Arc::new(RwLock::new(state_with([]))),
+            handler_registry,
+            recognition_rx,
+            transmission_tx,
+            Stopper::new(),
+        )
+        .await;
+        std::iter::from_fn(|| transmission_rx.try_recv().ok())
+            .map(|transmission| (transmission.to_callsign, transmission.message))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn missing_callsign_is_asked_again() {
+        assert_eq!(
+            gci_loop_messages(common_config(""), vec![request("", Intent::RadioCheck)]).await,
+            [(
+                "last station calling".to_string(),
+                "say again your callsign".to_string()
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_callsign_is_not_asked_again_off_duty() {
+        // An empty window is never active
+        let off_duty = "[active_hours]\nstart = \"12:00\"\nend = \"12:00\"";
+        assert!(gci_loop_messages(
+            common_config(off_duty),
+            vec![request("", Intent::RadioCheck)]
+        )
+        .await
+        .is_empty());
+
+        let common_config = common_config(&format!(
+            "{off_duty}\noff_duty_response = \"controller off duty\""
+        ));
+        assert_eq!(
+            gci_loop_messages(common_config, vec![request("", Intent::RadioCheck)]).await,
+            [(
+                "last station calling".to_string(),
+                "controller off duty".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn friendly_altitude_uses_angels() {
+        assert_eq!(format_altitude(feet_to_meters(25000.), true), "angels 25");
+        assert_eq!(format_altitude(feet_to_meters(5400.), true), "angels 5");
+        assert_eq!(format_altitude(feet_to_meters(1000.), true), "angels 1");
+    }
+
+    #[test]
+    fn friendly_altitude_below_a_thousand_feet_uses_cherubs() {
+        assert_eq!(format_altitude(feet_to_meters(500.), true), "cherubs 5");
+        assert_eq!(format_altitude(-10., true), "cherubs 0");
+    }
+
+    #[test]
+    fn contact_altitude_uses_thousands() {
+        assert_eq!(
+            format_altitude(feet_to_meters(25000.), false),
+            "25 thousands"
+        );
+        assert_eq!(
+            format_altitude(feet_to_meters(1500.), false),
+            "one thousand"
+        );
+        assert_eq!(format_altitude(feet_to_meters(500.), false), "on the deck");
+    }
+
+    /// Closest bandit to a requester at 0, 0 among the bandits in the given order
+    fn closest_bandit_id(state: &TacviewState, order: &[u64]) -> Option<u64> {
+        let common_config = common_config("");
+        let from_object = &state.objects[&100];
+        find_closest_bandit_among(
+            state,
+            &common_config,
+            from_object,
+            (0., 0.),
+            order.iter().map(|id| &state.objects[id]),
+        )
+        .map(|(bandit, _)| bandit.id)
+    }
+
+    fn bandits_north(bandits: &[(u64, f64, f64)]) -> TacviewState {
+        state_with(
+            std::iter::once(air_object(
+                100,
+                Some("Chevy 1-1"),
+                FRIENDLY,
+                (0., 0.),
+                20000.,
+                0.,
+            ))
+            .chain(bandits.iter().map(|&(id, range_nm, heading)| {
+                air_object(id, None, "Allies", (range_nm / 60., 0.), 20000., heading)
+            })),
+        )
+    }
+
+    #[test]
+    fn closest_bandit_ties_are_against_the_closest_range() {
+        // Cold at 10 nm, beaming at 10.4 nm, and hot at 10.8 nm: the hot one is tied with the beaming one
+        // only, which is not tied with the closest, so it must never be picked
+        let state = bandits_north(&[(1, 10., 0.), (2, 10.4, 90.), (3, 10.8, 180.)]);
+        for order in [
+            [1, 2, 3],
+            [1, 3, 2],
+            [2, 1, 3],
+            [2, 3, 1],
+            [3, 1, 2],
+            [3, 2, 1],
+        ] {
+            assert_eq!(closest_bandit_id(&state, &order), Some(2), "{order:?}");
+        }
+    }
+
+    #[test]
+    fn closest_bandit_is_picked_regardless_of_aspect_beyond_the_tie() {
+        let state = bandits_north(&[(1, 10., 0.), (2, 12., 180.)]);
+        assert_eq!(closest_bandit_id(&state, &[2, 1]), Some(1));
+        assert_eq!(closest_bandit_id(&state, &[]), None);
+    }
+
+    #[test]
+    fn closest_bandit_ties_with_the_same_aspect_go_to_the_lowest_id() {
+        let state = bandits_north(&[(5, 10.2, 180.), (4, 10., 180.), (6, 10.1, 180.)]);
+        assert_eq!(closest_bandit_id(&state, &[5, 6, 4]), Some(4));
+        assert_eq!(closest_bandit_id(&state, &[6, 4, 5]), Some(4));
+    }
+
+    #[test]
+    fn spatial_index_finds_the_same_closest_bandit_as_a_linear_scan() {
+        // Deterministic pseudo-random positions, within about 600 nm so some lookups fall back to the scan
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % 20_000) as f64 / 1000. - 10.
+        };
+        let mut state = TacviewState::new(true);
+        state.reference_latitude = Some(0.);
+        state.reference_longitude = Some(0.);
+        for id in 1..=1000 {
+            let (coalition, pilot) = if id % 10 == 0 {
+                (FRIENDLY, Some("Chevy 1-1"))
+            } else {
+                ("Allies", None)
+            };
+            let position = (next() / 2., next());
+            let heading = (next() + 10.) * 18.;
+            state.insert_object(air_object(id, pilot, coalition, position, 20000., heading));
+        }
+        // A requester far from every bandit
+        state.insert_object(air_object(
+            1001,
+            Some("Chevy 1-1"),
+            FRIENDLY,
+            (30., 30.),
+            20000.,
+            0.,
+        ));
+
+        let common_config = common_config("");
+        for from_object in
+            state.list_air_object_by_coalition(common_config.friendly_tacview_coalition())
+        {
+            let from_object_latlng = (
+                from_object.coords.latitude.unwrap(),
+                from_object.coords.longitude.unwrap(),
+            );
+            let indexed =
+                find_closest_bandit(&state, &common_config, from_object, from_object_latlng);
+            let scanned = find_closest_bandit_among(
+                &state,
+                &common_config,
+                from_object,
+                from_object_latlng,
+                state.list_air_object_by_coalition(common_config.hostile_tacview_coalition()),
+            );
+            assert_eq!(
+                indexed.map(|(bandit, _)| bandit.id),
+                scanned.map(|(bandit, _)| bandit.id),
+                "from {}",
+                from_object.id
+            );
+        }
+    }
+
+    /// Requester at the origin flying north, whose position was last updated `age_secs` ago
+    fn requester_updated_ago(age_secs: u64, ground_speed: f64) -> TacviewState {
+        state_with([TacviewObject {
+            ground_speed: Some(ground_speed),
+            ground_track: Some(0.),
+            position_updated: Some(Instant::now() - Duration::from_secs(age_secs)),
+            ..air_object(1, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.)
+        }])
+    }
+
+    fn located_requester(
+        state: &TacviewState,
+        common_config: &CommonConfig,
+    ) -> (Option<(f64, f64)>, Vec<String>) {
+        let (transmission_tx, mut transmission_rx) = tokio::sync::mpsc::unbounded_channel();
+        let located = locate_requester(
+            &request("Chevy 1-1", Intent::RequestBogeyDope),
+            state,
+            common_config,
+            &transmission_tx,
+        )
+        .map(|(_, latlng)| latlng);
+        (located, sent_messages(&mut transmission_rx))
+    }
+
+    #[test]
+    fn fresh_requester_is_located() {
+        let common_config = common_config("requester_stale_secs = 10.0");
+        let state = requester_updated_ago(2, 400.);
+        assert_eq!(
+            located_requester(&state, &common_config),
+            (Some((0., 0.)), vec![])
+        );
+    }
+
+    #[test]
+    fn stale_requester_is_told_so() {
+        let common_config = common_config("requester_stale_secs = 10.0");
+        let state = requester_updated_ago(20, 400.);
+        assert_eq!(
+            located_requester(&state, &common_config),
+            (None, vec!["your position is stale".to_string()])
+        );
+    }
+
+    #[test]
+    fn parked_requester_is_never_stale() {
+        let common_config = common_config("requester_stale_secs = 10.0");
+        let state = requester_updated_ago(600, 10.);
+        assert_eq!(
+            located_requester(&state, &common_config),
+            (Some((0., 0.)), vec![])
+        );
+    }
+
+    #[test]
+    fn stale_requester_is_dead_reckoned_for_a_minute() {
+        let common_config =
+            common_config("requester_stale_secs = 10.0\nstale_requester_handling = \"DeadReckon\"");
+        // Three nautical miles north at 360 knots in 30 seconds
+        let state = requester_updated_ago(30, 360.);
+        let (located, messages) = located_requester(&state, &common_config);
+        let (lat, lon) = located.unwrap();
+        assert!(
+            (lat - 3. / 60.).abs() < 0.001 && lon.abs() < 1e-9,
+            "{lat}, {lon}"
+        );
+        assert!(messages.is_empty(), "{messages:?}");
+
+        let state = requester_updated_ago(90, 360.);
+        assert_eq!(
+            located_requester(&state, &common_config),
+            (None, vec!["your position is stale".to_string()])
+        );
+    }
+
+    /// Group size of bandit 1, at the origin
+    fn group_size(state: &TacviewState, common_config: &CommonConfig) -> usize {
+        count_group_contacts(state, common_config, &state.objects[&1], (0., 0.))
+    }
+
+    #[test]
+    fn group_includes_bandits_up_to_the_group_radius() {
+        let state = state_with([
+            air_object(1, None, "Allies", (0., 0.), 20000., 0.),
+            air_object(2, None, "Allies", (2.95 / 60., 0.), 20000., 0.),
+            air_object(3, None, "Allies", (0., -2.95 / 60.), 20000., 0.),
+        ]);
+        assert_eq!(
+            group_size(&state, &common_config("group_radius_nm = 3.0")),
+            3
+        );
+
+        let state = state_with([
+            air_object(1, None, "Allies", (0., 0.), 20000., 0.),
+            air_object(2, None, "Allies", (3.05 / 60., 0.), 20000., 0.),
+            air_object(3, None, "Allies", (0., -3.05 / 60.), 20000., 0.),
+        ]);
+        assert_eq!(
+            group_size(&state, &common_config("group_radius_nm = 3.0")),
+            1
+        );
+        // The radius is configurable
+        assert_eq!(
+            group_size(&state, &common_config("group_radius_nm = 5.0")),
+            3
+        );
+    }
+
+    #[test]
+    fn group_excludes_bandits_without_heading_or_friendlies() {
+        let mut without_heading = air_object(2, None, "Allies", (1. / 60., 0.), 20000., 0.);
+        without_heading.coords.heading = None;
+        let state = state_with([
+            air_object(1, None, "Allies", (0., 0.), 20000., 0.),
+            without_heading,
+            air_object(3, Some("Chevy 1-1"), FRIENDLY, (0., 1. / 60.), 20000., 0.),
+        ]);
+        assert_eq!(group_size(&state, &common_config("")), 1);
+    }
+
+    #[test]
+    fn helicopters_are_categorized_as_helicopters() {
+        for name in [
+            "Ka-50", "Mi-24P", "Mi-8MT", "Mi-26", "Ka-27", "UH-60A", "CH-53E", "CH-47D", "SH-3W",
+            "AH-64D", "AH-1W", "SH-60B", "UH-1H", "Mi-28N", "OH-58D", "SA342M",
+        ] {
+            assert_eq!(
+                get_aircraft_category(get_aircraft_ty(Some(name))),
+                Some(AircraftCategory::Helicopter),
+                "{name}"
+            );
+        }
+        // Jets with names close to helicopters' keep their own category
+        assert_eq!(
+            get_aircraft_category(get_aircraft_ty(Some("Hawk"))),
+            Some(AircraftCategory::Attack)
+        );
+        assert_eq!(
+            get_aircraft_category(get_aircraft_ty(Some("A-20G"))),
+            Some(AircraftCategory::Attack)
+        );
+    }
+}
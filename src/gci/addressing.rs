@@ -0,0 +1,74 @@
+//! Matching the callsign a transmission is addressed to against the controller's
+
+use crate::recognition::{to_words, NUMBER_WORDS};
+
+/// Whether the word could be a flight or element number rather than a qualifier like "flight" or "control"
+fn is_numeric(word: &str) -> bool {
+    word.chars().any(|c| c.is_ascii_digit()) || NUMBER_WORDS.contains(&word)
+}
+
+/// Whether the spoken callsign addresses the controller. Unless strict, the controller's callsign may be
+/// followed by a qualifier, so "Magic flight" and "Magic Control" address "Magic",
+/// but "Magic 2", "Magic 1-1", and "Overlord Magic" are other callsigns.
+pub fn is_addressed_to(to_callsign: &str, controller_callsign: &str, strict: bool) -> bool {
+    if strict {
+        return to_callsign.to_lowercase() == controller_callsign.to_lowercase();
+    }
+    let spoken = to_words(to_callsign);
+    let controller = to_words(controller_callsign);
+    match spoken.strip_prefix(controller.as_slice()) {
+        Some([]) => !controller.is_empty(),
+        Some([qualifier]) => !controller.is_empty() && !is_numeric(qualifier),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn callsign_with_a_qualifier_addresses_the_controller() {
+        for to_callsign in [
+            "Magic",
+            "magic",
+            "Magic Control",
+            "Magic flight",
+            "Magic, flight",
+        ] {
+            assert!(
+                is_addressed_to(to_callsign, "Magic", false),
+                "{to_callsign}"
+            );
+        }
+        assert!(is_addressed_to(
+            "Darkstar 1-1 control",
+            "Darkstar 1-1",
+            false
+        ));
+    }
+
+    #[test]
+    fn other_callsigns_containing_the_controller_do_not_address_it() {
+        for to_callsign in [
+            "Magic 2",
+            "Magic 1-1",
+            "Magic two",
+            "Overlord Magic",
+            "Magical",
+            "",
+        ] {
+            assert!(
+                !is_addressed_to(to_callsign, "Magic", false),
+                "{to_callsign}"
+            );
+        }
+        assert!(!is_addressed_to("Magic", "", false));
+    }
+
+    #[test]
+    fn strict_addressing_takes_only_the_callsign() {
+        assert!(is_addressed_to("magic", "Magic", true));
+        assert!(!is_addressed_to("Magic Control", "Magic", true));
+    }
+}
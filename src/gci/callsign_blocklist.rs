@@ -0,0 +1,40 @@
+//! Ignoring transmissions from blocklisted callsigns, or from any callsign not on the allowlist
+
+use crate::config::CommonConfig;
+
+use super::normalize_callsign;
+
+/// Whether the controller should not respond to the callsign
+pub fn is_blocked(from_callsign: &str, common_config: &CommonConfig) -> bool {
+    let from_callsign = normalize_callsign(from_callsign);
+    let matches = |callsign: &String| normalize_callsign(callsign) == from_callsign;
+    if common_config.callsign_blocklist.iter().any(matches) {
+        return true;
+    }
+    common_config
+        .callsign_allowlist
+        .as_ref()
+        .is_some_and(|allowlist| !allowlist.iter().any(matches))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gci::tests::common_config;
+
+    use super::*;
+
+    #[test]
+    fn blocklist_matches_normalized_callsigns() {
+        let common_config = common_config("callsign_blocklist = [\"Jäger 1\"]");
+        assert!(is_blocked("jager 1", &common_config));
+        assert!(is_blocked("JÄGER-1", &common_config));
+        assert!(!is_blocked("Jager 2", &common_config));
+    }
+
+    #[test]
+    fn allowlist_blocks_every_other_callsign() {
+        let common_config = common_config("callsign_allowlist = [\"Chevy 1-1\"]");
+        assert!(!is_blocked("chevy 11", &common_config));
+        assert!(is_blocked("Enfield 1-1", &common_config));
+    }
+}
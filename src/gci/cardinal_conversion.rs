@@ -0,0 +1,20 @@
+//! Approximate bearings called as compass points, e.g. "north west" instead of "3 1 5"
+
+use crate::config::BearingPronunciation;
+
+use super::{get_cardinal_point, spell_digits};
+
+/// One of the eight points of the compass rose
+pub fn bearing_to_cardinal(bearing: f64) -> &'static str {
+    get_cardinal_point(bearing)
+}
+
+/// Bearing in degrees, spelled out digit by digit or as a compass point
+pub fn format_bearing(bearing: f64, pronunciation: BearingPronunciation) -> String {
+    match pronunciation {
+        BearingPronunciation::NumericPhonetic => {
+            spell_digits((bearing as isize).rem_euclid(360) as usize, 3)
+        }
+        BearingPronunciation::CardinalCompass => bearing_to_cardinal(bearing).to_string(),
+    }
+}
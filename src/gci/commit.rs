@@ -0,0 +1,391 @@
+//! Committing a pilot onto a bandit, with periodic BRAA updates until the pilot aborts
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use geo::{HaversineDestination, Point};
+use stopper::Stopper;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    recognition::{IncomingTransmission, Intent},
+    state::TacviewState,
+    timeline::MissionEvent,
+    transmission::OutgoingTransmission,
+};
+
+use super::{
+    dope::{format_braa, get_dope_report, DopeStyle},
+    find_closest_bandit, format_altitude, get_bandit_latlng, get_bearing, get_range,
+    locate_requester, meters_per_second_to_knots, spell_digits, GciState, IntentHandler,
+};
+
+const COMMIT_UPDATE_INTERVAL: Duration = Duration::from_secs(30);
+/// How often active commits are checked for a due update
+const COMMIT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// A committed pilot within this range of the bandit has merged
+const MERGE_RANGE_NM: f64 = 3.;
+
+/// Commit of a pilot onto a bandit
+#[derive(Clone)]
+pub struct CommitState {
+    pub bandit_id: u64,
+    pub started_at: Instant,
+    pub last_update: Instant,
+    pub update_interval: Duration,
+    /// Whether the merge was recorded in the timeline
+    pub merged: bool,
+}
+
+impl CommitState {
+    fn new(bandit_id: u64) -> Self {
+        let now = Instant::now();
+        Self {
+            bandit_id,
+            started_at: now,
+            last_update: now,
+            update_interval: COMMIT_UPDATE_INTERVAL,
+            merged: false,
+        }
+    }
+}
+
+/// Heading to fly and time in hours until intercept, assuming both aircraft hold their speed and
+/// the bandit holds its heading. `None` when the pilot is too slow to ever catch the bandit.
+fn get_intercept(
+    range: f64,
+    bearing: f64,
+    bandit_heading: f64,
+    bandit_speed_kts: f64,
+    speed_kts: f64,
+) -> Option<(f64, f64)> {
+    // Flat east/north frame centered on the pilot, in nautical miles and knots
+    let (bx, by) = (
+        range * bearing.to_radians().sin(),
+        range * bearing.to_radians().cos(),
+    );
+    let (vx, vy) = (
+        bandit_speed_kts * bandit_heading.to_radians().sin(),
+        bandit_speed_kts * bandit_heading.to_radians().cos(),
+    );
+
+    // |B + Vt| = speed * t
+    let a = vx * vx + vy * vy - speed_kts * speed_kts;
+    let b = 2. * (bx * vx + by * vy);
+    let c = bx * bx + by * by;
+    let t = if a.abs() < f64::EPSILON {
+        (b < 0.).then(|| -c / b)?
+    } else {
+        let discriminant = b * b - 4. * a * c;
+        if discriminant < 0. {
+            return None;
+        }
+        let sqrt = discriminant.sqrt();
+        [(-b - sqrt) / (2. * a), (-b + sqrt) / (2. * a)]
+            .into_iter()
+            .filter(|t| *t > 0.)
+            .min_by(|t1, t2| t1.partial_cmp(t2).unwrap())?
+    };
+
+    let (ix, iy) = (bx + vx * t, by + vy * t);
+    let heading = (ix.atan2(iy).to_degrees() + 360.) % 360.;
+    Some((heading, t))
+}
+
+pub struct CommitHandler {
+    gci_state: Arc<GciState>,
+}
+
+impl CommitHandler {
+    pub fn new(gci_state: Arc<GciState>) -> Self {
+        Self { gci_state }
+    }
+}
+
+impl IntentHandler for CommitHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let Some((from_object, from_object_latlng)) = locate_requester(
+            &incoming_transmission,
+            state,
+            common_config,
+            transmission_tx,
+        ) else {
+            return;
+        };
+
+        // Look for the contact closest to the described position, or to the pilot otherwise
+        let target_latlng = match incoming_transmission.intent {
+            Intent::RequestCommit {
+                bearing: Some(bearing),
+                range: Some(range),
+            } => {
+                let (lat, lng) = from_object_latlng;
+                let point = Point::new(lng, lat).haversine_destination(bearing, range * 1852.);
+                (point.y(), point.x())
+            }
+            _ => from_object_latlng,
+        };
+
+        let Some((bandit, _)) =
+            find_closest_bandit(state, common_config, from_object, target_latlng)
+        else {
+            let _ = transmission_tx.send(OutgoingTransmission {
+                to_callsign: incoming_transmission.from_callsign,
+                from_callsign: common_config.callsign.clone(),
+                message: "Scope is currently clear".to_string(),
+                category: MessageCategory::Response,
+                commit_started_at: None,
+            });
+            return;
+        };
+
+        let bandit_latlng = get_bandit_latlng(state, common_config, bandit).unwrap();
+        let report = get_dope_report(
+            state,
+            common_config,
+            from_object,
+            from_object_latlng,
+            bandit,
+        )
+        .unwrap();
+        let braa = format_braa(&report, DopeStyle::new(common_config));
+        let bearing = get_bearing(from_object_latlng, bandit_latlng);
+        let range = get_range(from_object_latlng, bandit_latlng);
+
+        // Fall back to pure pursuit when speeds are unknown or the bandit cannot be caught
+        let intercept = match (bandit.tas, from_object.tas) {
+            (Some(bandit_tas), Some(tas)) => get_intercept(
+                range,
+                bearing,
+                bandit.coords.heading.unwrap(),
+                meters_per_second_to_knots(bandit_tas),
+                meters_per_second_to_knots(tas),
+            ),
+            _ => None,
+        };
+        let (heading, merge) = match intercept {
+            Some((heading, hours)) => {
+                let merge = match (hours * 60.).round() as usize {
+                    0 | 1 => ", 1 minute to merge".to_string(),
+                    minutes => format!(", {} minutes to merge", minutes),
+                };
+                (heading, merge)
+            }
+            None => (bearing, String::new()),
+        };
+        let heading = ((heading as isize) + 360) % 360;
+
+        self.gci_state.active_commits.lock().unwrap().insert(
+            incoming_transmission.from_callsign.clone(),
+            CommitState::new(bandit.id),
+        );
+        self.gci_state
+            .timeline
+            .record(MissionEvent::bandit_committed(
+                &incoming_transmission.from_callsign,
+                bandit.id,
+                bandit.name.as_deref(),
+            ));
+
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message: format!(
+                "commit, target braa {}, fly heading {}, {}{}",
+                braa,
+                spell_digits(heading as usize, 3),
+                format_altitude(bandit.coords.altitude.unwrap(), true),
+                merge
+            ),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+/// Heading perpendicular to the bandit's track, on the side away from the bandit
+fn get_escape_heading(bearing_to_bandit: f64, bandit_heading: f64) -> f64 {
+    let away = (bearing_to_bandit + 180.) % 360.;
+    [
+        (bandit_heading + 90.) % 360.,
+        (bandit_heading + 270.) % 360.,
+    ]
+    .into_iter()
+    .min_by(|h1, h2| {
+        let diff1 = ((h1 - away + 540.) % 360. - 180.).abs();
+        let diff2 = ((h2 - away + 540.) % 360. - 180.).abs();
+        diff1.partial_cmp(&diff2).unwrap()
+    })
+    .unwrap()
+}
+
+pub struct AbortHandler {
+    gci_state: Arc<GciState>,
+}
+
+impl AbortHandler {
+    pub fn new(gci_state: Arc<GciState>) -> Self {
+        Self { gci_state }
+    }
+}
+
+impl IntentHandler for AbortHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let aborted_commit = self
+            .gci_state
+            .active_commits
+            .lock()
+            .unwrap()
+            .remove(&incoming_transmission.from_callsign);
+        if let Some(commit) = &aborted_commit {
+            self.gci_state
+                .aborted_commits
+                .abort(&incoming_transmission.from_callsign, commit.started_at);
+        }
+        let committed_bandit_id = aborted_commit.map(|commit| commit.bandit_id);
+
+        let Some((from_object, from_object_latlng)) = locate_requester(
+            &incoming_transmission,
+            state,
+            common_config,
+            transmission_tx,
+        ) else {
+            return;
+        };
+
+        // Escape from the committed bandit, or from the closest one if there was no commit
+        let bandit = committed_bandit_id
+            .and_then(|id| state.objects.get(&id))
+            .or_else(|| {
+                find_closest_bandit(state, common_config, from_object, from_object_latlng)
+                    .map(|(bandit, _)| bandit)
+            });
+        let message = if let Some((bandit_latlng, bandit_heading)) = bandit.and_then(|bandit| {
+            Some((
+                get_bandit_latlng(state, common_config, bandit)?,
+                bandit.coords.heading?,
+            ))
+        }) {
+            let escape_heading = get_escape_heading(
+                get_bearing(from_object_latlng, bandit_latlng),
+                bandit_heading,
+            );
+            let turn = match from_object.coords.heading {
+                Some(heading) if (escape_heading - heading + 360.) % 360. > 180. => "left ",
+                Some(_) => "right ",
+                None => "",
+            };
+            let escape_heading = ((escape_heading as isize) + 360) % 360;
+            format!(
+                "abort acknowledged, come {}heading {}",
+                turn,
+                spell_digits(escape_heading as usize, 3)
+            )
+        } else {
+            "abort acknowledged".to_string()
+        };
+
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message,
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+pub async fn commit_update_loop(
+    common_config: CommonConfig,
+    gci_state: Arc<GciState>,
+    state: Arc<RwLock<TacviewState>>,
+    priority_transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    stopper: Stopper,
+) {
+    let mut interval = tokio::time::interval(COMMIT_CHECK_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let active_commits = gci_state.active_commits.lock().unwrap().clone();
+        if active_commits.is_empty() {
+            continue;
+        }
+
+        let state = state.read().await;
+        for (callsign, commit) in active_commits {
+            // Updates stop once the bandit is off scope
+            let Some(bandit) = state.objects.get(&commit.bandit_id) else {
+                tracing::debug!(%callsign, bandit_id = commit.bandit_id, committed_secs = commit.started_at.elapsed().as_secs(), "committed bandit off scope, ending commit");
+                gci_state
+                    .active_commits
+                    .lock()
+                    .unwrap()
+                    .retain(|other_callsign, other| {
+                        *other_callsign != callsign || other.started_at != commit.started_at
+                    });
+                continue;
+            };
+            let Some((from_object, from_object_latlng)) = state
+                .find_air_object_by_callsign(&callsign, common_config.friendly_tacview_coalition())
+                .and_then(|from_object| Some((from_object, state.get_latlng(from_object)?)))
+            else {
+                continue;
+            };
+            if !commit.merged
+                && get_bandit_latlng(&state, &common_config, bandit).is_some_and(|bandit_latlng| {
+                    get_range(from_object_latlng, bandit_latlng) <= MERGE_RANGE_NM
+                })
+            {
+                if let Some(current) = gci_state.active_commits.lock().unwrap().get_mut(&callsign) {
+                    if current.started_at == commit.started_at {
+                        current.merged = true;
+                    }
+                }
+                gci_state
+                    .timeline
+                    .record(MissionEvent::bandit_merged(&callsign, commit.bandit_id));
+            }
+            if commit.last_update.elapsed() < commit.update_interval {
+                continue;
+            }
+            let Some(braa) = get_dope_report(
+                &state,
+                &common_config,
+                from_object,
+                from_object_latlng,
+                bandit,
+            )
+            .map(|report| format_braa(&report, DopeStyle::new(&common_config))) else {
+                continue;
+            };
+            // The commit may have been aborted or replaced in the meantime
+            match gci_state.active_commits.lock().unwrap().get_mut(&callsign) {
+                Some(current) if current.started_at == commit.started_at => {
+                    current.last_update = Instant::now();
+                }
+                _ => continue,
+            }
+            let _ = priority_transmission_tx.send(OutgoingTransmission {
+                to_callsign: callsign,
+                from_callsign: common_config.callsign.clone(),
+                message: format!("target braa {}", braa),
+                category: MessageCategory::Response,
+                commit_started_at: Some(commit.started_at),
+            });
+        }
+    }
+    tracing::info!("exiting commit update loop");
+}
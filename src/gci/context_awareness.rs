@@ -0,0 +1,41 @@
+//! Verbosity of responses adjusted to how busy the frequency is
+
+use crate::{
+    config::{CommonConfig, VerbosityLevel},
+    state::TacviewState,
+};
+
+/// Quiet enough for detailed calls with at most this many pilots and threats
+const DETAILED_MAX_PILOTS: usize = 2;
+const DETAILED_MAX_THREATS: usize = 2;
+/// Busy enough for terse calls with this many pilots or threats
+const TERSE_MIN_PILOTS: usize = 6;
+const TERSE_MIN_THREATS: usize = 5;
+
+pub fn get_verbosity_level(checked_in_pilots: usize, active_threats: usize) -> VerbosityLevel {
+    if checked_in_pilots >= TERSE_MIN_PILOTS || active_threats >= TERSE_MIN_THREATS {
+        VerbosityLevel::Terse
+    } else if checked_in_pilots <= DETAILED_MAX_PILOTS && active_threats <= DETAILED_MAX_THREATS {
+        VerbosityLevel::Detailed
+    } else {
+        VerbosityLevel::Standard
+    }
+}
+
+/// Verbosity for the current airspace.
+/// Friendly aircraft with a pilot name count as checked-in pilots, and hostile aircraft as threats.
+pub fn get_verbosity(state: &TacviewState, common_config: &CommonConfig) -> VerbosityLevel {
+    if let Some(verbosity) = common_config.verbosity_override {
+        return verbosity;
+    }
+    if !common_config.auto_verbosity {
+        return VerbosityLevel::Standard;
+    }
+    let pilots = state
+        .list_air_callsigns_by_coalition(common_config.friendly_tacview_coalition())
+        .count();
+    let threats = state
+        .list_air_object_by_coalition(common_config.hostile_tacview_coalition())
+        .count();
+    get_verbosity_level(pilots, threats)
+}
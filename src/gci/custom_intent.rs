@@ -0,0 +1,38 @@
+//! Config-defined intents answered with a canned response
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    recognition::IncomingTransmission,
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::IntentHandler;
+
+pub struct CannedResponseHandler {
+    response: String,
+}
+
+impl CannedResponseHandler {
+    pub fn new(response: String) -> Self {
+        Self { response }
+    }
+}
+
+impl IntentHandler for CannedResponseHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        _state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message: self.response.clone(),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
@@ -0,0 +1,132 @@
+//! Relaying MAYDAY calls on the frequency of every controller of the same coalition
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use stopper::Stopper;
+
+use crate::{
+    config::{Coalition, CommonConfig, MessageCategory},
+    recognition::{IncomingTransmission, Intent},
+    state::TacviewState,
+    timeline::{MissionEvent, TimelineRecorder},
+    transmission::OutgoingTransmission,
+};
+
+use super::{IntentHandler, BROADCAST_CALLSIGN};
+
+pub struct MaydayHandler {
+    /// Received by the relay loop of every controller, with the coalition of the relaying one
+    mayday_relay_tx: tokio::sync::broadcast::Sender<(Coalition, String)>,
+    /// When each pilot's MAYDAY was last relayed
+    relayed_mayday: Mutex<HashMap<String, Instant>>,
+    timeline: TimelineRecorder,
+}
+
+impl MaydayHandler {
+    pub fn new(
+        mayday_relay_tx: tokio::sync::broadcast::Sender<(Coalition, String)>,
+        timeline: TimelineRecorder,
+    ) -> Self {
+        Self {
+            mayday_relay_tx,
+            relayed_mayday: Mutex::new(HashMap::new()),
+            timeline,
+        }
+    }
+}
+
+impl IntentHandler for MaydayHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        _state: &TacviewState,
+        common_config: &CommonConfig,
+        _transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let Intent::Mayday { transcript } = &incoming_transmission.intent else {
+            return;
+        };
+        tracing::warn!(mayday = true, from_callsign = %incoming_transmission.from_callsign, %transcript, "MAYDAY received");
+        self.timeline.record(MissionEvent::mayday(
+            &incoming_transmission.from_callsign,
+            transcript,
+        ));
+
+        let cooldown = Duration::from_secs(common_config.mayday_relay_cooldown_secs);
+        let mut relayed_mayday = self.relayed_mayday.lock().unwrap();
+        relayed_mayday.retain(|_, relayed_at| relayed_at.elapsed() < cooldown);
+        if relayed_mayday.contains_key(&incoming_transmission.from_callsign) {
+            tracing::debug!(from_callsign = %incoming_transmission.from_callsign, "MAYDAY already relayed");
+            return;
+        }
+        relayed_mayday.insert(incoming_transmission.from_callsign.clone(), Instant::now());
+
+        let _ = self.mayday_relay_tx.send((
+            common_config.coalition.clone(),
+            format!("MAYDAY relay: {transcript}, all stations stand by"),
+        ));
+    }
+}
+
+/// Transmits every MAYDAY relayed by a controller of the same coalition ahead of anything queued
+pub async fn mayday_relay_loop(
+    common_config: CommonConfig,
+    mut mayday_relay_rx: tokio::sync::broadcast::Receiver<(Coalition, String)>,
+    priority_transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    stopper: Stopper,
+) {
+    loop {
+        match stopper.stop_future(mayday_relay_rx.recv()).await {
+            Some(Ok((coalition, _))) if coalition != common_config.coalition => {}
+            Some(Ok((_, message))) => {
+                let _ = priority_transmission_tx.send(OutgoingTransmission {
+                    to_callsign: BROADCAST_CALLSIGN.to_string(),
+                    from_callsign: common_config.callsign.clone(),
+                    message,
+                    category: MessageCategory::Warning,
+                    commit_started_at: None,
+                });
+            }
+            Some(Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped))) => {
+                tracing::warn!(skipped, "MAYDAY relays skipped");
+            }
+            Some(Err(tokio::sync::broadcast::error::RecvError::Closed)) | None => break,
+        }
+    }
+    tracing::info!("exiting MAYDAY relay loop");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gci::tests::common_config;
+
+    #[tokio::test]
+    async fn mayday_of_another_coalition_is_not_relayed() {
+        let (mayday_relay_tx, mayday_relay_rx) = tokio::sync::broadcast::channel(16);
+        let (priority_transmission_tx, mut priority_transmission_rx) =
+            tokio::sync::mpsc::unbounded_channel();
+        mayday_relay_tx
+            .send((Coalition::Red, "MAYDAY relay: Red".to_string()))
+            .unwrap();
+        mayday_relay_tx
+            .send((Coalition::Blue, "MAYDAY relay: Blue".to_string()))
+            .unwrap();
+        drop(mayday_relay_tx);
+        mayday_relay_loop(
+            common_config(""),
+            mayday_relay_rx,
+            priority_transmission_tx,
+            Stopper::new(),
+        )
+        .await;
+        let messages = std::iter::from_fn(|| priority_transmission_rx.try_recv().ok())
+            .map(|transmission| transmission.message)
+            .collect::<Vec<_>>();
+        assert_eq!(messages, ["MAYDAY relay: Blue"]);
+    }
+}
@@ -0,0 +1,389 @@
+//! Structured BRAA of a contact, and its phraseology
+
+use crate::{
+    config::{CommonConfig, VerbosityLevel},
+    state::{TacviewObject, TacviewState},
+};
+
+use super::{
+    count_group_contacts, format_altitude, get_aircraft_ty, get_angle_off, get_bandit_latlng,
+    get_bearing, get_cardinal_point, get_range, get_speed_descriptor, spell_digits,
+    time_to_merge::{format_time_to_merge, get_closure, is_closing, time_to_merge},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aspect {
+    Hot,
+    Flank,
+    Beam,
+    Drag,
+}
+
+#[derive(Debug, Clone)]
+pub struct DopeReport {
+    /// Bearing from the requester in degrees, 0 to 359
+    pub bearing: usize,
+    /// Range from the requester in nautical miles
+    pub range: usize,
+    /// Altitude in meters
+    pub altitude: f64,
+    pub aspect: Aspect,
+    /// Heading of the contact in degrees
+    pub heading: f64,
+    /// "fast" or "very fast", if applicable
+    pub speed: Option<&'static str>,
+    pub ty: String,
+    /// Type name from Tacview, e.g. "Su-27"
+    pub full_ty: Option<String>,
+    /// Rate in knots the range to the requester shrinks at, from both velocities, negative when opening
+    pub closing_speed: Option<f64>,
+    /// Number of contacts called with this one as a group, 1 for a single
+    pub group_size: usize,
+    /// Seconds until the requester and the contact merge, if enabled and closing
+    pub time_to_merge: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DopeStyle {
+    pub spell_range_digits: bool,
+    pub verbosity: VerbosityLevel,
+}
+
+impl DopeStyle {
+    pub fn new(common_config: &CommonConfig) -> Self {
+        Self {
+            spell_range_digits: common_config.spell_range_digits,
+            verbosity: VerbosityLevel::Standard,
+        }
+    }
+}
+
+/// Aspect of a contact at the bearing from the requester, flying the heading
+fn get_aspect(bearing: f64, heading: f64) -> Aspect {
+    // Shortest difference, as the bearing is signed and both may wrap around north
+    match get_angle_off(bearing, heading).round() as usize {
+        0..=60 => Aspect::Drag,
+        61..=100 => Aspect::Beam,
+        101..=140 => Aspect::Flank,
+        _ => Aspect::Hot,
+    }
+}
+
+pub fn get_dope_report(
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    from_object: &TacviewObject,
+    from_latlng: (f64, f64),
+    bandit: &TacviewObject,
+) -> Option<DopeReport> {
+    let bandit_latlng = get_bandit_latlng(state, common_config, bandit)?;
+
+    let bearing = get_bearing(from_latlng, bandit_latlng);
+    let range_nm = get_range(from_latlng, bandit_latlng);
+    let range = range_nm as usize;
+
+    let heading = bandit.smoothed_heading(common_config.heading_smoothing_window)?;
+    let aspect = get_aspect(bearing, heading);
+
+    let velocities = from_object
+        .ground_speed
+        .zip(from_object.ground_track)
+        .zip(bandit.ground_speed.zip(bandit.ground_track));
+    let closing_speed =
+        velocities.map(|(requester, bandit)| get_closure(bearing, requester, bandit));
+    let time_to_merge = if common_config.report_time_to_merge {
+        velocities
+            .and_then(|(requester, bandit)| time_to_merge(range_nm, bearing, requester, bandit))
+    } else {
+        None
+    };
+
+    Some(DopeReport {
+        bearing: (((bearing as isize) + 360) % 360) as usize,
+        range,
+        altitude: bandit.coords.altitude?,
+        aspect,
+        heading,
+        speed: get_speed_descriptor(bandit, &common_config.fast_mover),
+        ty: get_aircraft_ty(bandit.name.as_deref()).to_string(),
+        full_ty: bandit.name.clone(),
+        closing_speed,
+        group_size: count_group_contacts(state, common_config, bandit, bandit_latlng),
+        time_to_merge,
+    })
+}
+
+/// Aspect with the speed descriptor, e.g. "flank north, fast"
+fn format_aspect(report: &DopeReport) -> String {
+    let heading_cardinal = get_cardinal_point(report.heading);
+    let aspect = match report.aspect {
+        Aspect::Hot => "hot".to_string(),
+        Aspect::Flank => format!("flank {}", heading_cardinal),
+        Aspect::Beam => format!("beam {}", heading_cardinal),
+        Aspect::Drag => format!("drag {}", heading_cardinal),
+    };
+    match report.speed {
+        Some(speed) => format!("{aspect}, {speed}"),
+        None => aspect,
+    }
+}
+
+fn format_range(report: &DopeReport, style: DopeStyle) -> String {
+    if style.spell_range_digits {
+        spell_digits(report.range, 1)
+    } else {
+        report.range.to_string()
+    }
+}
+
+/// Bearing, range, altitude, and aspect, e.g. "0 9 0, 25, 20 thousands, hot"
+pub fn format_braa(report: &DopeReport, style: DopeStyle) -> String {
+    let bearing_str = spell_digits(report.bearing, 3);
+    let range_str = format_range(report, style);
+    let altitude_str = format_altitude(report.altitude, false);
+    let aspect = format_aspect(report);
+
+    format!("{bearing_str}, {range_str}, {altitude_str}, {aspect}")
+}
+
+/// Full bogey dope, e.g. "lead group braa 0 9 0, 25, 20 thousands, hot, hostile, flanker",
+/// or "single braa ..." for a contact without others within the group radius
+pub fn format_dope(report: &DopeReport, style: DopeStyle) -> String {
+    let label = if report.group_size > 1 {
+        "lead group"
+    } else {
+        "single"
+    };
+    let dope = match style.verbosity {
+        VerbosityLevel::Terse => format!(
+            "{}, {}, {}, {}",
+            spell_digits(report.bearing, 3),
+            format_range(report, style),
+            format_altitude(report.altitude, false),
+            format_aspect(report)
+        ),
+        VerbosityLevel::Standard => format!(
+            "{label} braa {}, hostile, {}",
+            format_braa(report, style),
+            report.ty
+        ),
+        VerbosityLevel::Detailed => {
+            let mut dope = format!(
+                "{label} braa {}, heading {}, hostile, {}",
+                format_braa(report, style),
+                spell_digits(report.heading.round() as usize % 360, 3),
+                report.ty
+            );
+            if let Some(full_ty) = report.full_ty.as_deref() {
+                if !full_ty.eq_ignore_ascii_case(&report.ty) {
+                    dope.push_str(&format!(", {full_ty}"));
+                }
+            }
+            // The time to merge below is from the same closure, called more precisely
+            if let Some(closing_speed) = report
+                .closing_speed
+                .filter(|speed| is_closing(*speed) && report.time_to_merge.is_none())
+            {
+                let minutes = (report.range as f64 / closing_speed * 60.).round().max(1.) as usize;
+                let unit = if minutes == 1 { "minute" } else { "minutes" };
+                dope.push_str(&format!(", closing, {minutes} {unit}"));
+            }
+            dope
+        }
+    };
+    match report.time_to_merge {
+        Some(seconds) => format!("{dope}, {}", format_time_to_merge(seconds)),
+        None => dope,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> DopeReport {
+        DopeReport {
+            bearing: 90,
+            range: 25,
+            altitude: crate::gci::feet_to_meters(20500.),
+            aspect: Aspect::Hot,
+            heading: 270.,
+            speed: None,
+            ty: "flanker".to_string(),
+            full_ty: Some("Su-27".to_string()),
+            closing_speed: None,
+            group_size: 1,
+            time_to_merge: None,
+        }
+    }
+
+    fn style(verbosity: VerbosityLevel) -> DopeStyle {
+        DopeStyle {
+            spell_range_digits: false,
+            verbosity,
+        }
+    }
+
+    #[test]
+    fn aspect_wraps_around_north() {
+        assert_eq!(get_aspect(355., 5.), Aspect::Drag);
+        assert_eq!(get_aspect(5., 355.), Aspect::Drag);
+        // Signed bearing west of north
+        assert_eq!(get_aspect(-5., 355.), Aspect::Drag);
+        assert_eq!(get_aspect(-5., 175.), Aspect::Hot);
+        assert_eq!(get_aspect(-170., 10.), Aspect::Hot);
+        assert_eq!(get_aspect(350., 170.), Aspect::Hot);
+        assert_eq!(get_aspect(-90., 0.), Aspect::Beam);
+        assert_eq!(get_aspect(10., 280.), Aspect::Beam);
+        assert_eq!(get_aspect(-30., 90.), Aspect::Flank);
+        assert_eq!(get_aspect(340., 100.), Aspect::Flank);
+    }
+
+    #[test]
+    fn aspect_boundaries() {
+        assert_eq!(get_aspect(0., 60.), Aspect::Drag);
+        assert_eq!(get_aspect(0., 300.), Aspect::Drag);
+        assert_eq!(get_aspect(0., 61.), Aspect::Beam);
+        assert_eq!(get_aspect(0., 100.), Aspect::Beam);
+        assert_eq!(get_aspect(0., 260.), Aspect::Beam);
+        assert_eq!(get_aspect(0., 259.), Aspect::Flank);
+        assert_eq!(get_aspect(0., 101.), Aspect::Flank);
+        assert_eq!(get_aspect(0., 140.), Aspect::Flank);
+        assert_eq!(get_aspect(0., 141.), Aspect::Hot);
+        assert_eq!(get_aspect(0., 180.), Aspect::Hot);
+    }
+
+    #[test]
+    fn dope_report_aspect_of_a_contact_northwest_of_the_requester() {
+        use crate::gci::tests::{air_object, common_config, state_with, FRIENDLY};
+
+        let common_config = common_config("");
+        for (heading, aspect) in [
+            (359., Aspect::Drag),
+            (179., Aspect::Hot),
+            (89., Aspect::Beam),
+        ] {
+            let state = state_with([
+                air_object(1, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.),
+                air_object(2, None, "Allies", (0.3, -0.01), 20000., heading),
+            ]);
+            let report = get_dope_report(
+                &state,
+                &common_config,
+                &state.objects[&1],
+                (0., 0.),
+                &state.objects[&2],
+            )
+            .unwrap();
+            assert_eq!(report.aspect, aspect, "heading {heading}");
+        }
+    }
+
+    #[test]
+    fn dope_report_closing_speed_includes_the_requester_velocity() {
+        use crate::{
+            gci::tests::{air_object, common_config, state_with, FRIENDLY},
+            state::TacviewObject,
+        };
+
+        let moving = |object: TacviewObject, ground_speed: f64, ground_track: f64| TacviewObject {
+            ground_speed: Some(ground_speed),
+            ground_track: Some(ground_track),
+            ..object
+        };
+        let closing_speed = |requester_track: f64| {
+            let state = state_with([
+                moving(
+                    air_object(1, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.),
+                    400.,
+                    requester_track,
+                ),
+                moving(
+                    air_object(2, None, "Allies", (0.5, 0.), 20000., 180.),
+                    500.,
+                    180.,
+                ),
+            ]);
+            get_dope_report(
+                &state,
+                &common_config(""),
+                &state.objects[&1],
+                (0., 0.),
+                &state.objects[&2],
+            )
+            .unwrap()
+            .closing_speed
+            .unwrap()
+        };
+        // Head on, and with the requester running away
+        assert!((closing_speed(0.) - 900.).abs() < 0.1);
+        assert!((closing_speed(180.) - 100.).abs() < 0.1);
+    }
+
+    #[test]
+    fn braa_is_bearing_range_altitude_aspect() {
+        assert_eq!(
+            format_braa(&report(), style(VerbosityLevel::Standard)),
+            "0 9 0, 25, 20 thousands, hot"
+        );
+        let report = DopeReport {
+            aspect: Aspect::Flank,
+            heading: 10.,
+            speed: Some("fast"),
+            ..report()
+        };
+        assert_eq!(
+            format_braa(
+                &report,
+                DopeStyle {
+                    spell_range_digits: true,
+                    ..style(VerbosityLevel::Standard)
+                }
+            ),
+            "0 9 0, 2 5, 20 thousands, flank north, fast"
+        );
+    }
+
+    #[test]
+    fn dope_labels_singles_and_groups() {
+        assert_eq!(
+            format_dope(&report(), style(VerbosityLevel::Standard)),
+            "single braa 0 9 0, 25, 20 thousands, hot, hostile, flanker"
+        );
+        let report = DopeReport {
+            group_size: 2,
+            ..report()
+        };
+        assert_eq!(
+            format_dope(&report, style(VerbosityLevel::Standard)),
+            "lead group braa 0 9 0, 25, 20 thousands, hot, hostile, flanker"
+        );
+    }
+
+    #[test]
+    fn dope_follows_verbosity() {
+        assert_eq!(
+            format_dope(&report(), style(VerbosityLevel::Terse)),
+            "0 9 0, 25, 20 thousands, hot"
+        );
+        let report = DopeReport {
+            closing_speed: Some(600.),
+            ..report()
+        };
+        assert_eq!(
+            format_dope(&report, style(VerbosityLevel::Detailed)),
+            "single braa 0 9 0, 25, 20 thousands, hot, heading 2 7 0, hostile, flanker, Su-27, \
+             closing, 3 minutes"
+        );
+        // Barely closing is not called
+        let report = DopeReport {
+            closing_speed: Some(5.),
+            ..report
+        };
+        assert!(
+            !format_dope(&report, style(VerbosityLevel::Detailed)).contains("closing"),
+            "{}",
+            format_dope(&report, style(VerbosityLevel::Detailed))
+        );
+    }
+}
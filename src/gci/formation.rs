@@ -0,0 +1,47 @@
+//! Detection of tightly grouped friendlies flying as a single element
+
+use crate::{
+    config::CommonConfig,
+    state::{TacviewObject, TacviewState},
+};
+
+use super::{get_angle_off, get_range, picture::cluster_by_proximity};
+
+/// Headings of aircraft in formation differ by at most this many degrees
+const FORMATION_HEADING_TOLERANCE_DEG: f64 = 30.;
+
+fn has_similar_heading(object1: &TacviewObject, object2: &TacviewObject) -> bool {
+    match (object1.coords.heading, object2.coords.heading) {
+        (Some(heading1), Some(heading2)) => {
+            get_angle_off(heading1, heading2) <= FORMATION_HEADING_TOLERANCE_DEG
+        }
+        _ => false,
+    }
+}
+
+/// Whether the two aircraft fly in formation
+pub fn is_in_formation(
+    common_config: &CommonConfig,
+    (object1, latlng1): (&TacviewObject, (f64, f64)),
+    (object2, latlng2): (&TacviewObject, (f64, f64)),
+) -> bool {
+    get_range(latlng1, latlng2) <= common_config.formation_radius_nm
+        && has_similar_heading(object1, object2)
+}
+
+/// Number of elements of the coalition's aircraft, counting each formation once
+pub fn count_elements(
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    coalition: Option<&str>,
+) -> usize {
+    let aircraft = state
+        .list_air_object_by_coalition(coalition)
+        .filter_map(|object| Some((object, state.get_latlng(object)?)));
+    cluster_by_proximity(
+        aircraft,
+        common_config.formation_radius_nm,
+        has_similar_heading,
+    )
+    .len()
+}
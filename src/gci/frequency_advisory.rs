@@ -0,0 +1,209 @@
+//! Frequency advisories for supporting assets (tankers, packages, FACs, ...)
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    config::{CommonConfig, FrequencyEntry, MessageCategory},
+    recognition::{IncomingTransmission, Intent},
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::IntentHandler;
+
+/// Handles both frequency requests and reports, sharing a runtime frequency DB seeded from config
+pub struct FrequencyHandler {
+    frequency_db: Mutex<HashMap<String, FrequencyEntry>>,
+}
+
+impl FrequencyHandler {
+    pub fn new(frequency_db: HashMap<String, FrequencyEntry>) -> Self {
+        Self {
+            frequency_db: Mutex::new(frequency_db),
+        }
+    }
+}
+
+impl IntentHandler for FrequencyHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        _state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let mut frequency_db = self.frequency_db.lock().unwrap();
+        match incoming_transmission.intent {
+            Intent::RequestFrequency { asset } => handle_frequency(
+                incoming_transmission.from_callsign,
+                &asset,
+                &frequency_db,
+                common_config,
+                transmission_tx,
+            ),
+            Intent::ReportFrequency {
+                asset,
+                frequency_mhz,
+                modulation,
+            } => handle_report_frequency(
+                incoming_transmission.from_callsign,
+                asset,
+                frequency_mhz,
+                modulation,
+                &mut frequency_db,
+                common_config,
+                transmission_tx,
+            ),
+            _ => {}
+        }
+    }
+}
+
+fn normalize_asset_name(name: &str) -> String {
+    name.trim().to_lowercase().replace(['-', ' '], "")
+}
+
+fn asset_name_words(name: &str) -> Vec<String> {
+    name.split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Exact match on the normalized name, or else the longest stored name the requested asset starts
+/// with, e.g. "Texaco 1-1" finds "Texaco"
+fn find_frequency<'a>(
+    frequency_db: &'a HashMap<String, FrequencyEntry>,
+    asset: &str,
+) -> Option<(&'a str, &'a FrequencyEntry)> {
+    let normalized_asset = normalize_asset_name(asset);
+    if normalized_asset.is_empty() {
+        return None;
+    }
+    let asset_words = asset_name_words(asset);
+    frequency_db
+        .iter()
+        .find(|(name, _)| normalize_asset_name(name) == normalized_asset)
+        .or_else(|| {
+            frequency_db
+                .iter()
+                .filter_map(|(name, entry)| {
+                    let name_words = asset_name_words(name);
+                    (!name_words.is_empty() && asset_words.starts_with(&name_words)).then_some((
+                        name_words.len(),
+                        name,
+                        entry,
+                    ))
+                })
+                .max_by(|(a_len, a_name, _), (b_len, b_name, _)| {
+                    a_len.cmp(b_len).then_with(|| b_name.cmp(a_name))
+                })
+                .map(|(_, name, entry)| (name, entry))
+        })
+        .map(|(name, entry)| (name.as_str(), entry))
+}
+
+fn handle_frequency(
+    from_callsign: String,
+    asset: &str,
+    frequency_db: &HashMap<String, FrequencyEntry>,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let message = if let Some((name, entry)) = find_frequency(frequency_db, asset) {
+        tracing::debug!(%name, description = %entry.description, "found frequency entry");
+        format!(
+            "{} is on {} MHz, {}",
+            name, entry.frequency_mhz, entry.modulation
+        )
+    } else {
+        format!("No frequency information for {}", asset)
+    };
+    let _ = transmission_tx.send(OutgoingTransmission {
+        to_callsign: from_callsign,
+        from_callsign: common_config.callsign.clone(),
+        message,
+        category: MessageCategory::Response,
+        commit_started_at: None,
+    });
+}
+
+fn handle_report_frequency(
+    from_callsign: String,
+    asset: String,
+    frequency_mhz: f64,
+    modulation: Option<String>,
+    frequency_db: &mut HashMap<String, FrequencyEntry>,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let modulation = modulation
+        .map(|modulation| modulation.to_uppercase())
+        .unwrap_or_else(|| "AM".to_string());
+    tracing::info!(%asset, frequency_mhz, %modulation, reported_by = %from_callsign, "frequency reported");
+
+    let _ = transmission_tx.send(OutgoingTransmission {
+        to_callsign: super::BROADCAST_CALLSIGN.to_string(),
+        from_callsign: common_config.callsign.clone(),
+        message: format!("{} is on {} MHz, {}", asset, frequency_mhz, modulation),
+        category: MessageCategory::Broadcast,
+        commit_started_at: None,
+    });
+
+    frequency_db.insert(
+        asset,
+        FrequencyEntry {
+            frequency_mhz,
+            modulation,
+            description: format!("reported by {}", from_callsign),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frequency_db(names: &[&str]) -> HashMap<String, FrequencyEntry> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                (
+                    name.to_string(),
+                    FrequencyEntry {
+                        frequency_mhz: 250. + i as f64,
+                        modulation: "AM".to_string(),
+                        description: String::new(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn found(db: &HashMap<String, FrequencyEntry>, asset: &str) -> Option<String> {
+        find_frequency(db, asset).map(|(name, _)| name.to_string())
+    }
+
+    #[test]
+    fn exact_match_ignores_case_and_separators() {
+        let db = frequency_db(&["Texaco 1-1", "Arco"]);
+        assert_eq!(found(&db, "texaco 11").as_deref(), Some("Texaco 1-1"));
+        assert_eq!(found(&db, "ARCO").as_deref(), Some("Arco"));
+    }
+
+    #[test]
+    fn stored_name_prefix_matches() {
+        let db = frequency_db(&["Texaco", "Texaco 2", "Arco"]);
+        assert_eq!(found(&db, "Texaco 1-1").as_deref(), Some("Texaco"));
+        assert_eq!(found(&db, "Texaco 2-1").as_deref(), Some("Texaco 2"));
+    }
+
+    #[test]
+    fn substrings_do_not_match() {
+        let db = frequency_db(&["Texaco 1-1", "Co"]);
+        assert_eq!(found(&db, "Texaco"), None);
+        assert_eq!(found(&db, "Taco"), None);
+        assert_eq!(found(&db, ""), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Announcing friendly pilots joining the session
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use stopper::Stopper;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+const NEW_PILOT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Pilots already on scope this long after start are not announced, as Tacview sends its initial state first
+const INITIAL_STATE_DELAY: Duration = Duration::from_secs(10);
+
+/// Friendly pilots on scope not in `known_pilots`, adding them to it
+fn find_new_pilots<'a>(
+    state: &'a TacviewState,
+    common_config: &CommonConfig,
+    known_pilots: &mut HashSet<String>,
+) -> Vec<&'a str> {
+    state
+        .list_air_object_by_coalition(common_config.friendly_tacview_coalition())
+        .filter_map(|object| object.pilot.as_deref())
+        .filter(|pilot| known_pilots.insert(pilot.to_string()))
+        .collect()
+}
+
+pub async fn friendly_callsign_broadcast_loop(
+    common_config: CommonConfig,
+    state: Arc<RwLock<TacviewState>>,
+    transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    stopper: Stopper,
+) {
+    let Some(to_callsign) = common_config.new_pilot_broadcast_callsign.clone() else {
+        return;
+    };
+    if stopper
+        .stop_future(tokio::time::sleep(INITIAL_STATE_DELAY))
+        .await
+        .is_none()
+    {
+        return;
+    }
+
+    // Pilots seen this session, so rejoining pilots are not announced again
+    let mut known_pilots = HashSet::new();
+    find_new_pilots(&*state.read().await, &common_config, &mut known_pilots);
+    let mut interval = tokio::time::interval(NEW_PILOT_CHECK_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let state = state.read().await;
+        for pilot in find_new_pilots(&state, &common_config, &mut known_pilots) {
+            let _ = transmission_tx.send(OutgoingTransmission {
+                to_callsign: to_callsign.clone(),
+                from_callsign: common_config.callsign.clone(),
+                message: format!("{pilot} is now on scope"),
+                category: MessageCategory::Broadcast,
+                commit_started_at: None,
+            });
+        }
+    }
+    tracing::info!("exiting friendly callsign broadcast loop");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gci::tests::{air_object, common_config, state_with, FRIENDLY};
+
+    use super::*;
+
+    #[test]
+    fn only_friendly_pilots_joining_are_new() {
+        let common_config = common_config("");
+        let mut known_pilots = HashSet::new();
+        let mut state = state_with([air_object(
+            1,
+            Some("Chevy 1-1"),
+            FRIENDLY,
+            (0., 0.),
+            20000.,
+            0.,
+        )]);
+        assert_eq!(
+            find_new_pilots(&state, &common_config, &mut known_pilots),
+            ["Chevy 1-1"]
+        );
+        assert!(find_new_pilots(&state, &common_config, &mut known_pilots).is_empty());
+
+        for object in [
+            air_object(2, Some("Enfield 2-1"), FRIENDLY, (0., 0.), 20000., 0.),
+            air_object(3, Some("Bandit 1"), "Allies", (0., 0.), 20000., 0.),
+            air_object(4, None, FRIENDLY, (0., 0.), 20000., 0.),
+        ] {
+            state.objects.insert(object.id, object);
+        }
+        assert_eq!(
+            find_new_pilots(&state, &common_config, &mut known_pilots),
+            ["Enfield 2-1"]
+        );
+    }
+
+    #[test]
+    fn rejoining_pilots_are_not_new() {
+        let common_config = common_config("");
+        let mut known_pilots = HashSet::new();
+        let mut state = state_with([air_object(
+            1,
+            Some("Chevy 1-1"),
+            FRIENDLY,
+            (0., 0.),
+            20000.,
+            0.,
+        )]);
+        find_new_pilots(&state, &common_config, &mut known_pilots);
+
+        // Respawned with a new object ID
+        state.objects.clear();
+        let object = air_object(5, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.);
+        state.objects.insert(object.id, object);
+        assert!(find_new_pilots(&state, &common_config, &mut known_pilots).is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+//! "Knock it off" broadcast from authorized callsigns, stopping all activity
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    recognition::IncomingTransmission,
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::{normalize_callsign, IntentHandler, BROADCAST_CALLSIGN};
+
+pub struct KnockItOffHandler {
+    /// Transmitted before anything queued on the regular channel
+    priority_transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+}
+
+impl KnockItOffHandler {
+    pub fn new(
+        priority_transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) -> Self {
+        Self {
+            priority_transmission_tx,
+        }
+    }
+}
+
+impl IntentHandler for KnockItOffHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        _state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let from_callsign = normalize_callsign(&incoming_transmission.from_callsign);
+        let authorized = common_config
+            .knock_it_off_callsigns
+            .iter()
+            .any(|callsign| normalize_callsign(callsign) == from_callsign);
+        if !authorized {
+            tracing::warn!(from_callsign = %incoming_transmission.from_callsign, "knock it off from unauthorized callsign");
+            let _ = transmission_tx.send(OutgoingTransmission {
+                to_callsign: incoming_transmission.from_callsign,
+                from_callsign: common_config.callsign.clone(),
+                message: "unable, not authorized".to_string(),
+                category: MessageCategory::Response,
+                commit_started_at: None,
+            });
+            return;
+        }
+
+        tracing::info!(from_callsign = %incoming_transmission.from_callsign, "knock it off");
+        let _ = self.priority_transmission_tx.send(OutgoingTransmission {
+            to_callsign: BROADCAST_CALLSIGN.to_string(),
+            from_callsign: common_config.callsign.clone(),
+            message: "knock it off, knock it off".to_string(),
+            category: MessageCategory::Warning,
+            commit_started_at: None,
+        });
+    }
+}
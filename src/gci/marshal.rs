@@ -0,0 +1,340 @@
+//! Carrier recovery marshal stack
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use stopper::Stopper;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{CommonConfig, MarshalConfig, MarshalPoint, MessageCategory},
+    recognition::IncomingTransmission,
+    state::{normalize_callsign, TacviewState},
+    transmission::OutgoingTransmission,
+};
+
+use super::{spell_digits, GciState, IntentHandler};
+
+/// How often the stack is checked for pilots no longer on scope
+const MARSHAL_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+struct Assignment {
+    callsign: String,
+    /// Index of the marshal point in the config
+    point: usize,
+    /// Fixed when the pilot is assigned, so compressing the stack doesn't move it
+    expected_approach: Instant,
+}
+
+/// Pilots holding at marshal points, in approach order
+#[derive(Default)]
+pub struct MarshalStack {
+    assignments: Vec<Assignment>,
+}
+
+/// Whether the point is reserved for another pilot with `callsign_slot`
+fn is_reserved_for_other(point: &MarshalPoint, callsign: &str) -> bool {
+    point
+        .callsign_slot
+        .as_deref()
+        .is_some_and(|slot| !normalize_callsign(callsign).starts_with(&normalize_callsign(slot)))
+}
+
+impl MarshalStack {
+    fn find(&self, callsign: &str) -> Option<&Assignment> {
+        self.assignments
+            .iter()
+            .find(|assignment| assignment.callsign.eq_ignore_ascii_case(callsign))
+    }
+
+    fn is_point_free(&self, point: usize) -> bool {
+        self.assignments
+            .iter()
+            .all(|assignment| assignment.point != point)
+    }
+
+    /// Removes pilots for whom `on_scope` is false, and moves the remaining pilots down to the first
+    /// free marshal point they may hold at. Returns the callsigns of the moved pilots.
+    fn compress(
+        &mut self,
+        config: &MarshalConfig,
+        mut on_scope: impl FnMut(&str) -> bool,
+    ) -> Vec<String> {
+        self.assignments.retain(|assignment| {
+            let retain = on_scope(&assignment.callsign);
+            if !retain {
+                tracing::info!(callsign = %assignment.callsign, "removing lost pilot from marshal stack");
+            }
+            retain
+        });
+
+        let mut moved = Vec::new();
+        for i in 0..self.assignments.len() {
+            let assignment = &self.assignments[i];
+            if let Some(point) = (0..assignment.point).find(|&point| {
+                self.is_point_free(point)
+                    && !is_reserved_for_other(&config.marshal_radials[point], &assignment.callsign)
+            }) {
+                let assignment = &mut self.assignments[i];
+                assignment.point = point;
+                moved.push(assignment.callsign.clone());
+            }
+        }
+        moved
+    }
+
+    /// Marshal point of the pilot, assigning the first free one if the pilot has none.
+    /// A point reserved for the pilot with `callsign_slot` is preferred.
+    fn assign(
+        &mut self,
+        config: &MarshalConfig,
+        callsign: &str,
+        now: Instant,
+    ) -> Option<&Assignment> {
+        if let Some(index) = self
+            .assignments
+            .iter()
+            .position(|assignment| assignment.callsign.eq_ignore_ascii_case(callsign))
+        {
+            return Some(&self.assignments[index]);
+        }
+
+        let free_points = (0..config.marshal_radials.len())
+            .filter(|&point| self.is_point_free(point))
+            .collect::<Vec<_>>();
+        let point = free_points
+            .iter()
+            .find(|&&point| {
+                config.marshal_radials[point].callsign_slot.is_some()
+                    && !is_reserved_for_other(&config.marshal_radials[point], callsign)
+            })
+            .or_else(|| {
+                free_points
+                    .iter()
+                    .find(|&&point| config.marshal_radials[point].callsign_slot.is_none())
+            })
+            .copied()?;
+
+        let interval = Duration::from_secs(config.approach_interval_minutes * 60);
+        let last_approach = self
+            .assignments
+            .iter()
+            .map(|assignment| assignment.expected_approach)
+            .max()
+            .map_or(now, |last_approach| last_approach.max(now));
+        self.assignments.push(Assignment {
+            callsign: callsign.to_string(),
+            point,
+            expected_approach: last_approach + interval,
+        });
+        self.assignments.last()
+    }
+}
+
+fn format_marshal(config: &MarshalConfig, assignment: &Assignment, now: Instant) -> String {
+    let point = &config.marshal_radials[assignment.point];
+    format!(
+        "marshal {} for {}, angels {}, expected approach time in {} minutes",
+        spell_digits((point.radial_deg as usize + 360) % 360, 3),
+        point.distance_nm as usize,
+        (point.altitude_ft / 1000.) as usize,
+        assignment
+            .expected_approach
+            .saturating_duration_since(now)
+            .as_secs()
+            / 60,
+    )
+}
+
+/// Compresses the stack against the state, telling the moved pilots their new marshal point
+fn compress_stack(
+    stack: &mut MarshalStack,
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let moved = stack.compress(&common_config.marshal, |callsign| {
+        state
+            .find_air_object_by_callsign(callsign, common_config.friendly_tacview_coalition())
+            .is_some()
+    });
+    let now = Instant::now();
+    for callsign in moved {
+        let Some(assignment) = stack.find(&callsign) else {
+            continue;
+        };
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: callsign.clone(),
+            from_callsign: common_config.callsign.clone(),
+            message: format_marshal(&common_config.marshal, assignment, now),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+pub struct MarshalHandler {
+    gci_state: Arc<GciState>,
+}
+
+impl MarshalHandler {
+    pub fn new(gci_state: Arc<GciState>) -> Self {
+        Self { gci_state }
+    }
+}
+
+impl IntentHandler for MarshalHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let mut stack = self.gci_state.marshal_stack.lock().unwrap();
+        compress_stack(&mut stack, state, common_config, transmission_tx);
+
+        let now = Instant::now();
+        let message = match stack.assign(
+            &common_config.marshal,
+            &incoming_transmission.from_callsign,
+            now,
+        ) {
+            Some(assignment) => format_marshal(&common_config.marshal, assignment, now),
+            None => "marshal stack is full, standby".to_string(),
+        };
+
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message,
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+/// Vacates the marshal points of pilots who checked out or are lost, moving the pilots above them down
+pub async fn marshal_stack_loop(
+    common_config: CommonConfig,
+    gci_state: Arc<GciState>,
+    state: Arc<RwLock<TacviewState>>,
+    transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    stopper: Stopper,
+) {
+    if common_config.marshal.marshal_radials.is_empty() {
+        return;
+    }
+    let mut interval = tokio::time::interval(MARSHAL_CHECK_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let state = state.read().await;
+        let mut stack = gci_state.marshal_stack.lock().unwrap();
+        compress_stack(&mut stack, &state, &common_config, &transmission_tx);
+    }
+    tracing::info!("exiting marshal stack loop");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(distance_nm: f64, callsign_slot: Option<&str>) -> MarshalPoint {
+        MarshalPoint {
+            radial_deg: 150.,
+            distance_nm,
+            altitude_ft: (distance_nm - 15.) * 1000.,
+            callsign_slot: callsign_slot.map(str::to_string),
+        }
+    }
+
+    fn config(points: Vec<MarshalPoint>) -> MarshalConfig {
+        MarshalConfig {
+            marshal_radials: points,
+            approach_interval_minutes: 2,
+        }
+    }
+
+    fn assigned_point(stack: &MarshalStack, callsign: &str) -> Option<usize> {
+        stack.find(callsign).map(|assignment| assignment.point)
+    }
+
+    #[test]
+    fn pilots_are_assigned_in_order_until_full() {
+        let config = config(vec![point(21., None), point(22., None)]);
+        let mut stack = MarshalStack::default();
+        let now = Instant::now();
+        let first = stack.assign(&config, "Chevy 1-1", now).unwrap();
+        assert_eq!(
+            format_marshal(&config, first, now),
+            "marshal 1 5 0 for 21, angels 6, expected approach time in 2 minutes"
+        );
+        let second = stack.assign(&config, "Enfield 1-1", now).unwrap();
+        assert_eq!(
+            format_marshal(&config, second, now),
+            "marshal 1 5 0 for 22, angels 7, expected approach time in 4 minutes"
+        );
+        assert!(stack.assign(&config, "Colt 1-1", now).is_none());
+        // Asking again keeps the assignment
+        assert_eq!(
+            stack
+                .assign(&config, "chevy 1-1", now)
+                .map(|assignment| assignment.point),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn compression_keeps_the_expected_approach_time() {
+        let config = config(vec![point(21., None), point(22., None), point(23., None)]);
+        let mut stack = MarshalStack::default();
+        let now = Instant::now();
+        for callsign in ["Chevy 1-1", "Enfield 1-1", "Colt 1-1"] {
+            stack.assign(&config, callsign, now);
+        }
+
+        let moved = stack.compress(&config, |callsign| callsign != "Chevy 1-1");
+        assert_eq!(moved, ["Enfield 1-1", "Colt 1-1"]);
+        assert_eq!(assigned_point(&stack, "Chevy 1-1"), None);
+        assert_eq!(assigned_point(&stack, "Enfield 1-1"), Some(0));
+        assert_eq!(assigned_point(&stack, "Colt 1-1"), Some(1));
+        assert_eq!(
+            format_marshal(&config, stack.find("Colt 1-1").unwrap(), now),
+            "marshal 1 5 0 for 22, angels 7, expected approach time in 6 minutes"
+        );
+
+        // A new pilot is behind everyone already in the stack
+        let new = stack.assign(&config, "Dodge 1-1", now).unwrap();
+        assert_eq!(new.point, 2);
+        assert_eq!(
+            format_marshal(&config, new, now),
+            "marshal 1 5 0 for 23, angels 8, expected approach time in 8 minutes"
+        );
+    }
+
+    #[test]
+    fn reserved_points_are_held_for_their_callsign() {
+        let config = config(vec![point(21., Some("Chevy 1")), point(22., None)]);
+        let mut stack = MarshalStack::default();
+        let now = Instant::now();
+        assert_eq!(
+            stack
+                .assign(&config, "Enfield 1-1", now)
+                .map(|assignment| assignment.point),
+            Some(1)
+        );
+        assert!(stack.assign(&config, "Colt 1-1", now).is_none());
+        assert_eq!(
+            stack
+                .assign(&config, "Chevy 1-2", now)
+                .map(|assignment| assignment.point),
+            Some(0)
+        );
+
+        // Other pilots don't move down into a reserved point
+        let moved = stack.compress(&config, |callsign| callsign != "Chevy 1-2");
+        assert!(moved.is_empty());
+        assert_eq!(assigned_point(&stack, "Enfield 1-1"), Some(1));
+    }
+}
@@ -0,0 +1,193 @@
+//! Throttling of requests when more arrive than the frequency can handle
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{config::OverloadConfig, recognition::IncomingTransmission};
+
+pub enum Admission {
+    Handle,
+    /// Handle at the given time, one throttle interval after the previous throttled request.
+    /// `announce` is set on the first request of an overload.
+    Throttle {
+        announce: bool,
+        at: Instant,
+    },
+    /// Duplicate of a request recently handled while overloaded
+    Drop,
+}
+
+pub struct OverloadGuard {
+    config: OverloadConfig,
+    recent_requests: VecDeque<Instant>,
+    /// Keyed by requester callsign and intent name
+    last_handled: HashMap<(String, String), Instant>,
+    overloaded: bool,
+    /// Earliest time the next throttled request is handled
+    next_throttled: Option<Instant>,
+}
+
+impl OverloadGuard {
+    pub fn new(config: OverloadConfig) -> Self {
+        Self {
+            config,
+            recent_requests: VecDeque::new(),
+            last_handled: HashMap::new(),
+            overloaded: false,
+            next_throttled: None,
+        }
+    }
+
+    fn throttle_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.config.throttle_interval_secs.max(0.))
+    }
+
+    pub fn admit(&mut self, incoming_transmission: &IncomingTransmission) -> Admission {
+        self.admit_at(incoming_transmission, Instant::now())
+    }
+
+    fn admit_at(
+        &mut self,
+        incoming_transmission: &IncomingTransmission,
+        now: Instant,
+    ) -> Admission {
+        if !self.config.enabled {
+            return Admission::Handle;
+        }
+
+        let window = Duration::from_secs_f64(self.config.window_secs.max(0.));
+        self.recent_requests.push_back(now);
+        while self
+            .recent_requests
+            .front()
+            .is_some_and(|requested| now.duration_since(*requested) > window)
+        {
+            self.recent_requests.pop_front();
+        }
+        self.last_handled
+            .retain(|_, handled| now.duration_since(*handled) <= window);
+
+        let was_overloaded = self.overloaded;
+        self.overloaded = self.recent_requests.len() > self.config.max_requests;
+        if was_overloaded && !self.overloaded {
+            tracing::info!("request rate is back to normal, leaving overload mode");
+        }
+
+        let key = (
+            incoming_transmission.from_callsign.to_lowercase(),
+            incoming_transmission.intent.name().to_string(),
+        );
+        if self.overloaded && self.last_handled.contains_key(&key) {
+            return Admission::Drop;
+        }
+        self.last_handled.insert(key, now);
+
+        if self.overloaded {
+            if !was_overloaded {
+                tracing::warn!(
+                    requests = self.recent_requests.len(),
+                    "request rate exceeded, entering overload mode"
+                );
+            }
+            let at = self
+                .next_throttled
+                .map_or(now, |next_throttled| next_throttled.max(now));
+            self.next_throttled = Some(at + self.throttle_interval());
+            Admission::Throttle {
+                announce: !was_overloaded,
+                at,
+            }
+        } else {
+            Admission::Handle
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gci::tests::request, recognition::Intent};
+
+    fn guard() -> OverloadGuard {
+        OverloadGuard::new(OverloadConfig {
+            enabled: true,
+            max_requests: 2,
+            window_secs: 30.,
+            throttle_interval_secs: 3.,
+        })
+    }
+
+    #[test]
+    fn requests_are_handled_below_the_rate() {
+        let mut guard = guard();
+        let now = Instant::now();
+        for callsign in ["Chevy 1-1", "Enfield 1-1"] {
+            assert!(matches!(
+                guard.admit_at(&request(callsign, Intent::RequestBogeyDope), now),
+                Admission::Handle
+            ));
+        }
+    }
+
+    #[test]
+    fn overload_throttles_without_blocking() {
+        let mut guard = guard();
+        let now = Instant::now();
+        guard.admit_at(&request("Chevy 1-1", Intent::RequestBogeyDope), now);
+        guard.admit_at(&request("Enfield 1-1", Intent::RequestBogeyDope), now);
+
+        let Admission::Throttle { announce, at } =
+            guard.admit_at(&request("Colt 1-1", Intent::RequestBogeyDope), now)
+        else {
+            panic!("not throttled");
+        };
+        assert!(announce);
+        assert_eq!(at, now);
+        let Admission::Throttle { announce, at } =
+            guard.admit_at(&request("Dodge 1-1", Intent::RequestBogeyDope), now)
+        else {
+            panic!("not throttled");
+        };
+        assert!(!announce);
+        assert_eq!(at, now + Duration::from_secs(3));
+
+        // Spaced from the previous throttled request, or handled right away once it is due
+        let later = now + Duration::from_secs(10);
+        let Admission::Throttle { at, .. } =
+            guard.admit_at(&request("Ford 1-1", Intent::RequestBogeyDope), later)
+        else {
+            panic!("not throttled");
+        };
+        assert_eq!(at, later);
+    }
+
+    #[test]
+    fn duplicates_are_dropped_while_overloaded() {
+        let mut guard = guard();
+        let now = Instant::now();
+        guard.admit_at(&request("Chevy 1-1", Intent::RequestBogeyDope), now);
+        guard.admit_at(&request("Enfield 1-1", Intent::RequestBogeyDope), now);
+        assert!(matches!(
+            guard.admit_at(&request("Chevy 1-1", Intent::RequestBogeyDope), now),
+            Admission::Drop
+        ));
+        assert!(matches!(
+            guard.admit_at(&request("Chevy 1-1", Intent::RadioCheck), now),
+            Admission::Throttle { .. }
+        ));
+    }
+
+    #[test]
+    fn disabled_guard_handles_everything() {
+        let mut guard = OverloadGuard::new(OverloadConfig::default());
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert!(matches!(
+                guard.admit_at(&request("Chevy 1-1", Intent::RequestBogeyDope), now),
+                Admission::Handle
+            ));
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Grouping of bandits into a picture call
+
+use itertools::Itertools;
+
+use crate::{
+    config::{AltitudeLayerConfig, BearingPronunciation, CommonConfig},
+    state::{TacviewObject, TacviewState},
+};
+
+use super::{
+    format_altitude, format_bullseye, get_aircraft_ty, get_bandit_latlng, get_cardinal_point,
+    get_range, meters_to_feet,
+    picture_brevity::{group_separation_brevity, group_size_brevity},
+};
+
+pub struct PictureGroup<'a> {
+    /// First contact is the lead of the group
+    pub contacts: Vec<(&'a TacviewObject, (f64, f64))>,
+}
+
+impl<'a> PictureGroup<'a> {
+    pub fn lead(&self) -> (&'a TacviewObject, (f64, f64)) {
+        self.contacts[0]
+    }
+
+    /// Largest range between two contacts of the group in nautical miles
+    pub fn spread(&self) -> f64 {
+        self.contacts
+            .iter()
+            .tuple_combinations()
+            .map(|((_, latlng1), (_, latlng2))| get_range(*latlng1, *latlng2))
+            .fold(0., f64::max)
+    }
+}
+
+/// Air objects of the hostile coalition with a known position, altitude, and heading, clustered by proximity
+pub fn get_picture_groups<'a>(
+    state: &'a TacviewState,
+    common_config: &CommonConfig,
+    hostile_coalition: Option<&'a str>,
+) -> Vec<PictureGroup<'a>> {
+    let bandits = state
+        .list_air_object_by_coalition(hostile_coalition)
+        .filter(|bandit| bandit.coords.altitude.is_some() && bandit.coords.heading.is_some())
+        .filter_map(|bandit| Some((bandit, get_bandit_latlng(state, common_config, bandit)?)));
+    cluster_by_proximity(bandits, common_config.group_radius_nm, |_, _| true)
+}
+
+/// Clusters contacts within the given range in nautical miles of another contact of the group,
+/// if `joins` also holds for the two contacts
+pub fn cluster_by_proximity<'a>(
+    contacts: impl IntoIterator<Item = (&'a TacviewObject, (f64, f64))>,
+    radius_nm: f64,
+    joins: impl Fn(&TacviewObject, &TacviewObject) -> bool,
+) -> Vec<PictureGroup<'a>> {
+    let mut groups: Vec<PictureGroup<'a>> = Vec::new();
+    for (contact, contact_latlng) in contacts {
+        let group = groups.iter_mut().find(|group| {
+            group.contacts.iter().any(|(other, latlng)| {
+                get_range(*latlng, contact_latlng) <= radius_nm && joins(other, contact)
+            })
+        });
+        match group {
+            Some(group) => group.contacts.push((contact, contact_latlng)),
+            None => groups.push(PictureGroup {
+                contacts: vec![(contact, contact_latlng)],
+            }),
+        }
+    }
+    groups
+}
+
+/// Whether every group is within the given range in nautical miles of a group in the other picture
+pub fn is_same_picture(
+    groups: &[PictureGroup<'_>],
+    previous: &[(f64, f64)],
+    radius_nm: f64,
+) -> bool {
+    groups.len() == previous.len()
+        && groups.iter().all(|group| {
+            let (_, latlng) = group.lead();
+            previous
+                .iter()
+                .any(|previous_latlng| get_range(latlng, *previous_latlng) <= radius_nm)
+        })
+}
+
+/// "high ", "medium ", or "low " for the altitude in meters, or nothing when layering is disabled
+fn get_altitude_layer(meters: f64, config: &AltitudeLayerConfig) -> &'static str {
+    if !config.enabled {
+        return "";
+    }
+    let feet = meters_to_feet(meters);
+    if feet >= config.high_min_ft {
+        "high "
+    } else if feet < config.low_max_ft {
+        "low "
+    } else {
+        "medium "
+    }
+}
+
+/// Picture call of the groups, located from the bullseye if there is one
+pub fn format_picture(
+    groups: &[PictureGroup<'_>],
+    bullseye_latlng: Option<(f64, f64)>,
+    altitude_layers: &AltitudeLayerConfig,
+    bearing_pronunciation: BearingPronunciation,
+) -> String {
+    if groups.is_empty() {
+        return "picture clean".to_string();
+    }
+
+    let mut message = match groups.len() {
+        1 => "picture, single group".to_string(),
+        count => format!("picture, {count} groups"),
+    };
+    for group in groups {
+        let (lead, lead_latlng) = group.lead();
+        let altitude = lead.coords.altitude.unwrap_or_default();
+        let layer = get_altitude_layer(altitude, altitude_layers);
+        let location = match bullseye_latlng {
+            Some(bullseye_latlng) => {
+                format!(
+                    "{layer}group {}, ",
+                    format_bullseye(bullseye_latlng, lead_latlng, bearing_pronunciation)
+                )
+            }
+            None => format!("{layer}group, "),
+        };
+        message.push_str(&format!(
+            ". {}{}, track {}, hostile, {}",
+            location,
+            format_altitude(altitude, false),
+            get_cardinal_point(lead.coords.heading.unwrap_or_default()),
+            get_aircraft_ty(lead.name.as_deref()),
+        ));
+        message.push_str(&format!(", {}", group_size_brevity(group.contacts.len())));
+        if group.contacts.len() > 1 {
+            message.push_str(&format!(", {}", group_separation_brevity(group.spread())));
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gci::tests::air_object;
+
+    #[test]
+    fn picture_describes_group_size_and_separation() {
+        // A trio spread over about 5 miles, and a singleton far from it
+        let objects = [
+            air_object(1, None, "Allies", (0., 0.), 20000., 270.),
+            air_object(2, None, "Allies", (0.04, 0.), 20000., 270.),
+            air_object(3, None, "Allies", (0.08, 0.), 20000., 270.),
+            air_object(4, None, "Allies", (1., 0.), 20000., 270.),
+        ];
+        let contacts = objects
+            .iter()
+            .map(|object| (object, (object.coords.latitude.unwrap(), 0.)));
+        let groups = cluster_by_proximity(contacts, 3., |_, _| true);
+        assert_eq!(groups.len(), 2);
+
+        let picture = format_picture(
+            &groups,
+            None,
+            &AltitudeLayerConfig::default(),
+            BearingPronunciation::default(),
+        );
+        assert!(
+            picture.starts_with("picture, 2 groups. group, "),
+            "{picture}"
+        );
+        assert!(picture.contains(", trio, spread. group, "), "{picture}");
+        assert!(picture.ends_with(", singleton"), "{picture}");
+    }
+
+    #[test]
+    fn empty_picture_is_clean() {
+        assert_eq!(
+            format_picture(
+                &[],
+                None,
+                &AltitudeLayerConfig::default(),
+                BearingPronunciation::default()
+            ),
+            "picture clean"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Brevity terms describing the contacts of a group
+
+/// "singleton", "element", "trio", or "package" for 1, 2, 3, or 4 and more contacts
+pub fn group_size_brevity(count: usize) -> &'static str {
+    match count {
+        0 | 1 => "singleton",
+        2 => "element",
+        3 => "trio",
+        _ => "package",
+    }
+}
+
+/// "tight" under 3 miles, "spread" up to 10 miles, or "wide" beyond, of lateral spread within a group
+pub fn group_separation_brevity(range_nm: f64) -> &'static str {
+    if range_nm < 3. {
+        "tight"
+    } else if range_nm <= 10. {
+        "spread"
+    } else {
+        "wide"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_size_terms() {
+        assert_eq!(group_size_brevity(1), "singleton");
+        assert_eq!(group_size_brevity(2), "element");
+        assert_eq!(group_size_brevity(3), "trio");
+        assert_eq!(group_size_brevity(4), "package");
+        assert_eq!(group_size_brevity(12), "package");
+    }
+
+    #[test]
+    fn group_separation_terms() {
+        assert_eq!(group_separation_brevity(0.), "tight");
+        assert_eq!(group_separation_brevity(2.9), "tight");
+        assert_eq!(group_separation_brevity(3.), "spread");
+        assert_eq!(group_separation_brevity(10.), "spread");
+        assert_eq!(group_separation_brevity(10.1), "wide");
+    }
+}
@@ -0,0 +1,132 @@
+//! Unsolicited picture calls on a fixed interval, or when the picture changes
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use stopper::Stopper;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::{
+    get_range,
+    picture::{format_picture, get_picture_groups, is_same_picture, PictureGroup},
+    BROADCAST_CALLSIGN,
+};
+
+/// How often the picture is checked for changes
+const PICTURE_CHANGE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of range thresholds each group is within of the nearest friendly, sorted
+fn get_range_bands(
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    groups: &[PictureGroup<'_>],
+) -> Vec<usize> {
+    let friendlies = state
+        .list_air_object_by_coalition(common_config.friendly_tacview_coalition())
+        .filter_map(|friendly| state.get_latlng(friendly))
+        .collect::<Vec<_>>();
+    let mut bands = groups
+        .iter()
+        .map(|group| {
+            let (_, latlng) = group.lead();
+            let range = friendlies
+                .iter()
+                .map(|friendly_latlng| get_range(*friendly_latlng, latlng))
+                .fold(f64::INFINITY, f64::min);
+            common_config
+                .picture_on_change
+                .range_thresholds_nm
+                .iter()
+                .filter(|threshold| range <= **threshold)
+                .count()
+        })
+        .collect::<Vec<_>>();
+    bands.sort_unstable();
+    bands
+}
+
+pub async fn picture_timer_loop(
+    common_config: CommonConfig,
+    state: Arc<RwLock<TacviewState>>,
+    transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    stopper: Stopper,
+) {
+    let on_change = common_config.picture_on_change.enabled;
+    let interval_duration = if on_change {
+        PICTURE_CHANGE_CHECK_INTERVAL
+    } else {
+        let Some(interval_secs) = common_config.auto_picture_interval_secs else {
+            return;
+        };
+        Duration::from_secs(interval_secs.max(1))
+    };
+    let min_interval = Duration::from_secs(common_config.picture_on_change.min_interval_secs);
+    let to_callsign = common_config
+        .auto_picture_to_callsign
+        .clone()
+        .unwrap_or_else(|| BROADCAST_CALLSIGN.to_string());
+
+    let mut interval = tokio::time::interval(interval_duration);
+    // The first tick completes immediately, when there is no picture yet
+    interval.tick().await;
+
+    let mut previous_picture: Option<Vec<(f64, f64)>> = None;
+    let mut previous_range_bands = Vec::new();
+    let mut last_broadcast: Option<Instant> = None;
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        if on_change && last_broadcast.is_some_and(|last| last.elapsed() < min_interval) {
+            continue;
+        }
+
+        let state = state.read().await;
+        let groups = get_picture_groups(
+            &state,
+            &common_config,
+            common_config.hostile_tacview_coalition(),
+        );
+        let range_bands = if on_change {
+            get_range_bands(&state, &common_config, &groups)
+        } else {
+            Vec::new()
+        };
+        if let Some(previous_picture) = &previous_picture {
+            if is_same_picture(
+                &groups,
+                previous_picture,
+                common_config.picture_dedup_radius_nm,
+            ) && range_bands == previous_range_bands
+            {
+                tracing::debug!("picture is unchanged, skipping automatic picture");
+                continue;
+            }
+        }
+
+        let bullseye_latlng = state
+            .find_bullseye(common_config.friendly_tacview_coalition())
+            .and_then(|bullseye| state.get_latlng(bullseye));
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: to_callsign.clone(),
+            from_callsign: common_config.callsign.clone(),
+            message: format_picture(
+                &groups,
+                bullseye_latlng,
+                &common_config.altitude_layers,
+                common_config.bearing_pronunciation,
+            ),
+            category: MessageCategory::Broadcast,
+            commit_started_at: None,
+        });
+        previous_picture = Some(groups.iter().map(|group| group.lead().1).collect());
+        previous_range_bands = range_bands;
+        last_broadcast = Some(Instant::now());
+    }
+    tracing::info!("exiting picture timer loop");
+}
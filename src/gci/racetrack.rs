@@ -0,0 +1,231 @@
+//! Racetrack orbits of tankers and other support aircraft, for advisories of their estimated position
+
+use std::f64::consts::PI;
+
+use crate::{
+    config::{CommonConfig, MessageCategory, OrbitTrack},
+    recognition::{IncomingTransmission, Intent},
+    state::{normalize_callsign, TacviewState},
+    transmission::OutgoingTransmission,
+};
+
+use super::{
+    feet_to_meters, format_altitude, get_bearing, get_range, locate_requester, spell_digits,
+    IntentHandler,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrbitLeg {
+    /// Flying the orbit heading away from the anchor, or in the turn after it
+    Outbound,
+    /// Flying back to the anchor, or in the turn after it
+    Inbound,
+}
+
+impl OrbitLeg {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Outbound => "outbound",
+            Self::Inbound => "inbound",
+        }
+    }
+}
+
+/// Radius of a standard rate turn of 3 degrees per second in nautical miles
+fn get_turn_radius(speed_knots: f64) -> f64 {
+    speed_knots / (60. * PI)
+}
+
+/// Position relative to the anchor in nautical miles east and north, and the leg being flown,
+/// assuming the orbit started at the anchor at mission time 0 and turns right
+fn get_orbit_offset(track: &OrbitTrack, mission_time: f64) -> ((f64, f64), OrbitLeg) {
+    let leg = track.leg_nm;
+    let radius = get_turn_radius(track.speed_knots);
+    let turn = PI * radius;
+    let distance = (mission_time * track.speed_knots / 3600.).rem_euclid(2. * (leg + turn));
+
+    let heading = track.heading_deg.to_radians();
+    let forward = (heading.sin(), heading.cos());
+    let right = (heading.cos(), -heading.sin());
+    let offset = |f: f64, r: f64| (forward.0 * f + right.0 * r, forward.1 * f + right.1 * r);
+
+    if distance < leg {
+        (offset(distance, 0.), OrbitLeg::Outbound)
+    } else if distance < leg + turn {
+        let angle = (distance - leg) / radius;
+        (
+            offset(leg + radius * angle.sin(), radius - radius * angle.cos()),
+            OrbitLeg::Outbound,
+        )
+    } else if distance < 2. * leg + turn {
+        (
+            offset(2. * leg + turn - distance, 2. * radius),
+            OrbitLeg::Inbound,
+        )
+    } else {
+        let angle = (distance - 2. * leg - turn) / radius;
+        (
+            offset(-radius * angle.sin(), radius + radius * angle.cos()),
+            OrbitLeg::Inbound,
+        )
+    }
+}
+
+/// Estimated latitude and longitude along the orbit at the mission time in seconds
+pub fn compute_orbit_position(track: &OrbitTrack, mission_time: f64) -> (f64, f64) {
+    let ((east, north), _) = get_orbit_offset(track, mission_time);
+    (
+        track.anchor_lat + north / 60.,
+        track.anchor_lon + east / (60. * track.anchor_lat.to_radians().cos()),
+    )
+}
+
+pub fn get_orbit_leg(track: &OrbitTrack, mission_time: f64) -> OrbitLeg {
+    get_orbit_offset(track, mission_time).1
+}
+
+/// Whether the tanker name is the orbit's callsign, or one of them a prefix of the other,
+/// so "Texaco" matches "Texaco 1-1" and the other way around
+fn matches_tanker(track: &OrbitTrack, tanker: &str) -> bool {
+    let callsign = normalize_callsign(&track.callsign);
+    callsign.starts_with(tanker) || tanker.starts_with(&callsign)
+}
+
+/// The named tanker, or the closest one without a name or several matching it.
+/// An exact name is preferred over prefix matches.
+fn find_orbit_track<'a>(
+    orbit_tracks: &'a [OrbitTrack],
+    tanker: Option<&str>,
+    from_latlng: (f64, f64),
+    mission_time: f64,
+) -> Option<&'a OrbitTrack> {
+    let candidates = match tanker.map(normalize_callsign) {
+        Some(tanker) => {
+            let exact = orbit_tracks
+                .iter()
+                .filter(|track| normalize_callsign(&track.callsign) == tanker)
+                .collect::<Vec<_>>();
+            if exact.is_empty() {
+                orbit_tracks
+                    .iter()
+                    .filter(|track| matches_tanker(track, &tanker))
+                    .collect()
+            } else {
+                exact
+            }
+        }
+        None => orbit_tracks.iter().collect(),
+    };
+    let range =
+        |track: &OrbitTrack| get_range(from_latlng, compute_orbit_position(track, mission_time));
+    candidates
+        .into_iter()
+        .min_by(|track1, track2| range(track1).total_cmp(&range(track2)))
+}
+
+pub struct TankerHandler;
+
+impl IntentHandler for TankerHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        handle_tanker(incoming_transmission, state, common_config, transmission_tx);
+    }
+}
+
+fn handle_tanker(
+    incoming_transmission: IncomingTransmission,
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let Intent::RequestTanker { tanker } = &incoming_transmission.intent else {
+        return;
+    };
+    let Some((_, from_object_latlng)) = locate_requester(
+        &incoming_transmission,
+        state,
+        common_config,
+        transmission_tx,
+    ) else {
+        return;
+    };
+    let mission_time = state.simulation_time.unwrap_or_default();
+
+    let message = match find_orbit_track(
+        &common_config.orbit_tracks,
+        tanker.as_deref(),
+        from_object_latlng,
+        mission_time,
+    ) {
+        Some(track) => {
+            let tanker_latlng = compute_orbit_position(track, mission_time);
+            let bearing = get_bearing(from_object_latlng, tanker_latlng);
+            format!(
+                "{} {}, braa {}, {}, {}",
+                track.callsign,
+                get_orbit_leg(track, mission_time).as_str(),
+                spell_digits((bearing as isize).rem_euclid(360) as usize, 3),
+                get_range(from_object_latlng, tanker_latlng) as usize,
+                format_altitude(feet_to_meters(track.altitude_ft), true),
+            )
+        }
+        None => "no tanker on station".to_string(),
+    };
+    let _ = transmission_tx.send(OutgoingTransmission {
+        to_callsign: incoming_transmission.from_callsign,
+        from_callsign: common_config.callsign.clone(),
+        message,
+        category: MessageCategory::Response,
+        commit_started_at: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(callsign: &str, anchor_lat: f64) -> OrbitTrack {
+        OrbitTrack {
+            callsign: callsign.to_string(),
+            anchor_lat,
+            anchor_lon: 0.,
+            heading_deg: 90.,
+            leg_nm: 20.,
+            altitude_ft: 22000.,
+            speed_knots: 420.,
+        }
+    }
+
+    fn found<'a>(orbit_tracks: &'a [OrbitTrack], tanker: Option<&str>) -> Option<&'a str> {
+        find_orbit_track(orbit_tracks, tanker, (0., 0.), 0.).map(|track| track.callsign.as_str())
+    }
+
+    #[test]
+    fn tanker_names_match_by_prefix() {
+        let orbit_tracks = [track("Texaco 1-1", 1.), track("Arco", 2.)];
+        assert_eq!(found(&orbit_tracks, Some("Texaco")), Some("Texaco 1-1"));
+        assert_eq!(found(&orbit_tracks, Some("texaco 11")), Some("Texaco 1-1"));
+        assert_eq!(found(&orbit_tracks, Some("Arco 2-1")), Some("Arco"));
+        assert_eq!(found(&orbit_tracks, Some("Shell")), None);
+    }
+
+    #[test]
+    fn exact_tanker_name_is_preferred_then_the_closest() {
+        let orbit_tracks = [
+            track("Texaco 1-1", 3.),
+            track("Texaco 2-1", 1.),
+            track("Texaco", 5.),
+        ];
+        assert_eq!(found(&orbit_tracks, Some("Texaco")), Some("Texaco"));
+        assert_eq!(found(&orbit_tracks, Some("Texaco 1")), Some("Texaco 1-1"));
+        // Several prefix matches go to the closest
+        let orbit_tracks = [track("Texaco 1-1", 3.), track("Texaco 2-1", 1.)];
+        assert_eq!(found(&orbit_tracks, Some("Texaco")), Some("Texaco 2-1"));
+        assert_eq!(found(&orbit_tracks, None), Some("Texaco 2-1"));
+    }
+}
@@ -0,0 +1,47 @@
+//! Dispatching incoming transmissions to intent handlers
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    config::CommonConfig,
+    recognition::{IncomingTransmission, Intent},
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+pub trait IntentHandler: Send + Sync {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    );
+}
+
+#[derive(Default)]
+pub struct HandlerRegistry {
+    /// Keyed by intent name
+    handlers: HashMap<String, Arc<dyn IntentHandler>>,
+}
+
+impl HandlerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` for every transmission with the same variant as `intent`.
+    /// Fields of `intent` are ignored.
+    pub fn register(&mut self, intent: &Intent, handler: Arc<dyn IntentHandler>) {
+        self.register_name(intent.name(), handler);
+    }
+
+    /// Registers `handler` for every transmission with the given intent name
+    pub fn register_name(&mut self, name: &str, handler: Arc<dyn IntentHandler>) {
+        self.handlers.insert(name.to_lowercase(), handler);
+    }
+
+    pub fn get(&self, intent: &Intent) -> Option<&Arc<dyn IntentHandler>> {
+        self.handlers.get(&intent.name().to_lowercase())
+    }
+}
@@ -0,0 +1,60 @@
+//! SNAP: an immediate heading to the nearest threat, without a full BRAA
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    recognition::IncomingTransmission,
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::{
+    find_closest_bandit, get_bandit_latlng, get_bearing, locate_requester, spell_digits,
+    IntentHandler,
+};
+
+pub struct SnapHandler;
+
+impl IntentHandler for SnapHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let Some((from_object, from_object_latlng)) = locate_requester(
+            &incoming_transmission,
+            state,
+            common_config,
+            transmission_tx,
+        ) else {
+            return;
+        };
+
+        let message =
+            match find_closest_bandit(state, common_config, from_object, from_object_latlng) {
+                Some((bandit, range)) if range <= common_config.snap_max_range_nm => {
+                    // Pure pursuit, no lead
+                    let bearing = get_bearing(
+                        from_object_latlng,
+                        get_bandit_latlng(state, common_config, bandit).unwrap(),
+                    );
+                    let bearing = ((bearing as isize) + 360) % 360;
+                    format!(
+                        "snap {}, {} miles",
+                        spell_digits(bearing as usize, 3),
+                        range as usize
+                    )
+                }
+                _ => "snap unavailable, no contact within range".to_string(),
+            };
+
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message,
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
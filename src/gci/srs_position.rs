@@ -0,0 +1,52 @@
+//! Reporting the controller's position to SRS, so the server places it on the map
+
+use std::{sync::Arc, time::Duration};
+
+use stopper::Stopper;
+use tokio::sync::RwLock;
+
+use crate::{api::srs::SrsClientState, config::CommonConfig, state::TacviewState};
+
+use super::{feet_to_meters, get_range, get_self_position};
+
+const SRS_POSITION_INTERVAL: Duration = Duration::from_secs(10);
+/// The position is only sent again once it moved this many nautical miles
+const SRS_POSITION_MIN_MOVE_NM: f64 = 0.5;
+
+pub async fn srs_position_loop(
+    common_config: CommonConfig,
+    state: Arc<RwLock<TacviewState>>,
+    srs_client: SrsClientState,
+    stopper: Stopper,
+) {
+    if !srs_client.config().report_position {
+        return;
+    }
+
+    let mut last_latlng = None;
+    let mut interval = tokio::time::interval(SRS_POSITION_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let position = {
+            let state = state.read().await;
+            get_self_position(&state, &common_config).or_else(|| {
+                let bullseye = state.find_bullseye(common_config.friendly_tacview_coalition())?;
+                Some((
+                    state.get_latlng(bullseye)?,
+                    feet_to_meters(srs_client.config().bullseye_altitude_ft),
+                ))
+            })
+        };
+        let Some((latlng, altitude)) = position else {
+            continue;
+        };
+        if last_latlng
+            .is_some_and(|last_latlng| get_range(last_latlng, latlng) < SRS_POSITION_MIN_MOVE_NM)
+        {
+            continue;
+        }
+        tracing::debug!(?latlng, altitude, "reporting position to SRS");
+        srs_client.update_position(latlng, altitude);
+        last_latlng = Some(latlng);
+    }
+    tracing::info!("exiting SRS position loop");
+}
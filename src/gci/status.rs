@@ -0,0 +1,85 @@
+//! Status report of the controller itself
+
+use std::time::Duration;
+
+use crate::{
+    api::tacview::Heartbeat,
+    config::{CommonConfig, MessageCategory},
+    recognition::IncomingTransmission,
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::{formation::count_elements, IntentHandler};
+
+/// Tacview data older than this is reported as degraded
+const TACVIEW_STALE_AFTER: Duration = Duration::from_secs(30);
+
+pub struct StatusHandler {
+    tacview_heartbeat: Heartbeat,
+}
+
+impl StatusHandler {
+    pub fn new(tacview_heartbeat: Heartbeat) -> Self {
+        Self { tacview_heartbeat }
+    }
+}
+
+impl IntentHandler for StatusHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let statistics = state.statistics();
+        tracing::debug!(
+            total_objects = statistics.total_objects,
+            air_objects = ?statistics.air_objects_by_coalition,
+            ground_objects = ?statistics.ground_objects_by_coalition,
+            naval_objects = ?statistics.naval_objects_by_coalition,
+            "airspace statistics for status check"
+        );
+        let count_of = |coalition: Option<&str>| {
+            coalition
+                .and_then(|coalition| statistics.air_objects_by_coalition.get(coalition))
+                .copied()
+                .unwrap_or_default()
+        };
+        let air_contacts = statistics.air_objects_by_coalition.values().sum::<usize>();
+        let last_update = self.tacview_heartbeat.elapsed();
+
+        let mut message = format!(
+            "{} is online, tracking {} air contacts",
+            common_config.callsign, air_contacts
+        );
+        if !common_config.open_mode {
+            message.push_str(&format!(
+                ", {} friendly in {} elements and {} hostile",
+                count_of(common_config.friendly_tacview_coalition()),
+                count_elements(
+                    state,
+                    common_config,
+                    common_config.friendly_tacview_coalition()
+                ),
+                count_of(common_config.hostile_tacview_coalition()),
+            ));
+        }
+        message.push_str(&format!(
+            ", last update {} seconds ago",
+            last_update.as_secs()
+        ));
+        if last_update > TACVIEW_STALE_AFTER {
+            message.push_str(". NOTE: Tacview degraded");
+        }
+
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message,
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
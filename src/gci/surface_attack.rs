@@ -0,0 +1,198 @@
+//! Warning pilots heading into the engagement zone of a hostile air defense
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use stopper::Stopper;
+use tacview_realtime_client::acmi::record::object_property::Tag;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    state::{TacviewObject, TacviewState},
+    transmission::OutgoingTransmission,
+};
+
+use super::{get_bearing, get_range, meters_per_second_to_knots, spell_digits};
+
+const SURFACE_ATTACK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Configured engagement range in nautical miles of the air defense, matched by name.
+/// The largest range is used when several names match.
+fn get_engagement_range(common_config: &CommonConfig, object: &TacviewObject) -> Option<f64> {
+    if !object.ty.contains(&Tag::AntiAircraft) {
+        return None;
+    }
+    let name = object.name.as_deref()?.to_lowercase();
+    common_config
+        .sam_ranges
+        .iter()
+        .filter(|(ty, _)| name.contains(&ty.to_lowercase()))
+        .map(|(_, range)| *range)
+        .max_by(f64::total_cmp)
+}
+
+/// Time in hours until an aircraft holding its heading and speed enters the circle
+/// of `radius` nautical miles, at the given bearing and range from the aircraft
+fn get_time_to_envelope(
+    range: f64,
+    bearing: f64,
+    heading: f64,
+    speed_kts: f64,
+    radius: f64,
+) -> Option<f64> {
+    if range <= radius || speed_kts <= 0. {
+        return None;
+    }
+    // Flat east/north frame centered on the aircraft, projecting its track onto the center
+    let along_track = range * (bearing - heading).to_radians().cos();
+    let cross_track = range * (bearing - heading).to_radians().sin();
+    if along_track <= 0. || cross_track.abs() >= radius {
+        return None;
+    }
+    let distance = along_track - (radius * radius - cross_track * cross_track).sqrt();
+    Some(distance / speed_kts)
+}
+
+pub async fn surface_attack_loop(
+    common_config: CommonConfig,
+    state: Arc<RwLock<TacviewState>>,
+    transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    stopper: Stopper,
+) {
+    if common_config.sam_ranges.is_empty() {
+        return;
+    }
+
+    // Pairs of friendly and air defense IDs already warned about
+    let mut warned = HashSet::<(u64, u64)>::new();
+    let mut interval = tokio::time::interval(SURFACE_ATTACK_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let state = state.read().await;
+
+        let sams = state
+            .objects
+            .values()
+            .filter(|object| {
+                common_config
+                    .friendly_tacview_coalition()
+                    .is_none_or(|coalition| object.coalition.as_deref() != Some(coalition))
+            })
+            .filter_map(|sam| {
+                Some((
+                    sam,
+                    state.get_latlng(sam)?,
+                    get_engagement_range(&common_config, sam)?,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        let mut still_warned = HashSet::new();
+        for friendly in
+            state.list_air_object_by_coalition(common_config.friendly_tacview_coalition())
+        {
+            let (Some(pilot), Some(heading), Some(latlng)) = (
+                friendly.pilot.as_deref(),
+                friendly.coords.heading,
+                state.get_latlng(friendly),
+            ) else {
+                continue;
+            };
+            let Some(speed_kts) = friendly
+                .tas
+                .map(meters_per_second_to_knots)
+                .or(friendly.ground_speed)
+            else {
+                continue;
+            };
+
+            for (sam, sam_latlng, engagement_range) in &sams {
+                if sam.id == friendly.id {
+                    continue;
+                }
+                let Some(hours) = get_time_to_envelope(
+                    get_range(latlng, *sam_latlng),
+                    get_bearing(latlng, *sam_latlng),
+                    heading,
+                    speed_kts,
+                    *engagement_range,
+                ) else {
+                    continue;
+                };
+                let minutes = hours * 60.;
+                if minutes > common_config.sam_warning_time_minutes {
+                    continue;
+                }
+
+                let key = (friendly.id, sam.id);
+                still_warned.insert(key);
+                if warned.contains(&key) {
+                    continue;
+                }
+
+                let escape_heading = (get_bearing(*sam_latlng, latlng) + 360.) % 360.;
+                let ty = sam.name.as_deref().unwrap_or("SAM");
+                let _ = transmission_tx.send(OutgoingTransmission {
+                    to_callsign: pilot.to_string(),
+                    from_callsign: common_config.callsign.clone(),
+                    message: format!(
+                        "defend, entering {ty} envelope in {} minutes, recommend heading {}",
+                        minutes.round().max(1.) as usize,
+                        spell_digits(escape_heading.round() as usize % 360, 3),
+                    ),
+                    category: MessageCategory::Warning,
+                    commit_started_at: None,
+                });
+            }
+        }
+        // Warn again if a pilot turns away and later back toward the same air defense
+        warned = still_warned;
+    }
+    tracing::info!("exiting surface attack loop");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::gci::tests::common_config;
+
+    use super::*;
+
+    fn air_defense(name: &str) -> TacviewObject {
+        TacviewObject {
+            id: 1,
+            ty: HashSet::from([Tag::Ground, Tag::AntiAircraft]),
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn largest_matching_engagement_range_is_used() {
+        let common_config = common_config(
+            "sam_ranges = { \"S-300\" = 90.0, \"S-300PS 40B6MD\" = 20.0, \"Buk\" = 25.0 }",
+        );
+        assert_eq!(
+            get_engagement_range(&common_config, &air_defense("S-300PS 40B6MD sr")),
+            Some(90.)
+        );
+        assert_eq!(
+            get_engagement_range(&common_config, &air_defense("SA-11 Buk LN 9A310M1")),
+            Some(25.)
+        );
+        assert_eq!(
+            get_engagement_range(&common_config, &air_defense("Hawk ln")),
+            None
+        );
+    }
+
+    #[test]
+    fn only_air_defenses_have_an_engagement_range() {
+        let common_config = common_config("sam_ranges = { \"Buk\" = 25.0 }");
+        let object = TacviewObject {
+            ty: HashSet::from([Tag::Ground, Tag::Vehicle]),
+            ..air_defense("Buk transport")
+        };
+        assert_eq!(get_engagement_range(&common_config, &object), None);
+    }
+}
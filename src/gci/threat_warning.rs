@@ -0,0 +1,145 @@
+//! Unprompted threat calls for hostile air defenses close to friendly aircraft
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use stopper::Stopper;
+use tacview_realtime_client::acmi::record::object_property::Tag;
+use tokio::sync::RwLock;
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    state::{TacviewObject, TacviewState},
+    timeline::{MissionEvent, TimelineRecorder},
+    transmission::OutgoingTransmission,
+};
+
+use super::{format_bullseye, get_range, get_self_position, meters_to_feet, BROADCAST_CALLSIGN};
+
+const THREAT_WARNING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Range in nautical miles within which the controller can see a ground threat,
+/// the lesser of the radar range and the radar horizon
+fn get_detection_range(common_config: &CommonConfig, altitude_meters: f64) -> f64 {
+    let radar_horizon = 1.23 * meters_to_feet(altitude_meters.max(0.)).sqrt();
+    common_config.self_radar_range_nm.min(radar_horizon)
+}
+
+fn is_warned_threat(common_config: &CommonConfig, object: &TacviewObject) -> bool {
+    if !object.ty.contains(&Tag::AntiAircraft) {
+        return false;
+    }
+    if common_config.threat_warning_types.is_empty() {
+        return true;
+    }
+    let Some(name) = object.name.as_deref() else {
+        return false;
+    };
+    let name = name.to_lowercase();
+    common_config
+        .threat_warning_types
+        .iter()
+        .any(|ty| name.contains(&ty.to_lowercase()))
+}
+
+pub async fn threat_warning_loop(
+    common_config: CommonConfig,
+    state: Arc<RwLock<TacviewState>>,
+    transmission_tx: tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    timeline: TimelineRecorder,
+    stopper: Stopper,
+) {
+    if !common_config.enable_threat_warnings {
+        return;
+    }
+
+    // Announced threat IDs, with their names for the off scope call
+    let mut announced_threats = HashMap::<u64, String>::new();
+    let mut interval = tokio::time::interval(THREAT_WARNING_INTERVAL);
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let state = state.read().await;
+
+        announced_threats.retain(|id, ty| {
+            if state.objects.contains_key(id) {
+                return true;
+            }
+            let _ = transmission_tx.send(OutgoingTransmission {
+                to_callsign: BROADCAST_CALLSIGN.to_string(),
+                from_callsign: common_config.callsign.clone(),
+                message: format!("{ty} off scope"),
+                category: MessageCategory::Warning,
+                commit_started_at: None,
+            });
+            timeline.record(MissionEvent::threat_disappeared(*id, ty));
+            false
+        });
+
+        let friendlies = state
+            .list_air_object_by_coalition(common_config.friendly_tacview_coalition())
+            .filter_map(|friendly| Some((friendly, state.get_latlng(friendly)?)))
+            .collect::<Vec<_>>();
+        let bullseye_latlng = state
+            .find_bullseye(common_config.friendly_tacview_coalition())
+            .and_then(|bullseye| state.get_latlng(bullseye));
+        let self_position = get_self_position(&state, &common_config);
+
+        for threat in state.objects.values() {
+            if announced_threats.contains_key(&threat.id)
+                || common_config
+                    .friendly_tacview_coalition()
+                    .is_some_and(|coalition| threat.coalition.as_deref() == Some(coalition))
+                || !is_warned_threat(&common_config, threat)
+            {
+                continue;
+            }
+            let Some(threat_latlng) = state.get_latlng(threat) else {
+                continue;
+            };
+            if let Some((self_latlng, self_altitude)) = self_position {
+                if get_range(self_latlng, threat_latlng)
+                    > get_detection_range(&common_config, self_altitude)
+                {
+                    continue;
+                }
+            }
+            let Some((nearest_friendly, range)) = friendlies
+                .iter()
+                .map(|(friendly, latlng)| (friendly, get_range(*latlng, threat_latlng)))
+                .min_by(|(_, range1), (_, range2)| range1.partial_cmp(range2).unwrap())
+            else {
+                continue;
+            };
+            if range > common_config.threat_warning_range_nm {
+                continue;
+            }
+
+            let ty = threat.name.clone().unwrap_or_else(|| "SAM".to_string());
+            let location = match bullseye_latlng {
+                Some(bullseye_latlng) => {
+                    format!(
+                        " {},",
+                        format_bullseye(
+                            bullseye_latlng,
+                            threat_latlng,
+                            common_config.bearing_pronunciation,
+                        )
+                    )
+                }
+                None => String::new(),
+            };
+            let _ = transmission_tx.send(OutgoingTransmission {
+                to_callsign: BROADCAST_CALLSIGN.to_string(),
+                from_callsign: common_config.callsign.clone(),
+                message: format!(
+                    "threat, {ty},{location} {} miles from {}",
+                    range as usize,
+                    nearest_friendly.pilot.as_deref().unwrap_or("friendly"),
+                ),
+                category: MessageCategory::Warning,
+                commit_started_at: None,
+            });
+            timeline.record(MissionEvent::threat_appeared(threat.id, &ty));
+            announced_threats.insert(threat.id, ty);
+        }
+    }
+    tracing::info!("exiting threat warning loop");
+}
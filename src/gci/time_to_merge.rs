@@ -0,0 +1,89 @@
+//! Time until the requester and a bandit merge, from the closure of both velocities
+
+/// Below this closure in knots, the aircraft are not considered closing
+const MIN_CLOSURE_KNOTS: f64 = 10.;
+
+/// Ground speed in knots and ground track in degrees
+pub type Velocity = (f64, f64);
+
+/// Rate in knots the range shrinks at, negative when opening
+pub fn get_closure(bearing_to_bandit: f64, requester: Velocity, bandit: Velocity) -> f64 {
+    let (requester_speed, requester_track) = requester;
+    let (bandit_speed, bandit_track) = bandit;
+    let bearing_to_requester = bearing_to_bandit + 180.;
+    requester_speed * (requester_track - bearing_to_bandit).to_radians().cos()
+        + bandit_speed * (bandit_track - bearing_to_requester).to_radians().cos()
+}
+
+/// Whether the closure is fast enough to call the aircraft closing
+pub fn is_closing(closure: f64) -> bool {
+    closure >= MIN_CLOSURE_KNOTS
+}
+
+/// Seconds until the range closes at the current closure, `None` when not closing
+pub fn time_to_merge(
+    range_nm: f64,
+    bearing_to_bandit: f64,
+    requester: Velocity,
+    bandit: Velocity,
+) -> Option<f64> {
+    let closure = get_closure(bearing_to_bandit, requester, bandit);
+    if !is_closing(closure) {
+        return None;
+    }
+    Some(range_nm / closure * 3600.)
+}
+
+/// e.g. "merge in 45 seconds", or "merge in 3 minutes" from 2 minutes on
+pub fn format_time_to_merge(seconds: f64) -> String {
+    if seconds < 120. {
+        let seconds = ((seconds / 5.).round() * 5.).max(5.) as usize;
+        format!("merge in {seconds} seconds")
+    } else {
+        let minutes = (seconds / 60.).round() as usize;
+        format!("merge in {minutes} minutes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head_on_closure_adds_both_speeds() {
+        // Bandit due north flying south, requester flying north
+        let closure = get_closure(0., (400., 0.), (500., 180.));
+        assert!((closure - 900.).abs() < 1e-9, "{closure}");
+        let seconds = time_to_merge(30., 0., (400., 0.), (500., 180.)).unwrap();
+        assert!((seconds - 120.).abs() < 1e-9, "{seconds}");
+    }
+
+    #[test]
+    fn opening_closure_is_negative() {
+        // Bandit due north flying north, faster than the requester chasing it
+        let closure = get_closure(0., (400., 0.), (500., 0.));
+        assert!((closure + 100.).abs() < 1e-9, "{closure}");
+        assert!(!is_closing(closure));
+        assert_eq!(time_to_merge(30., 0., (400., 0.), (500., 0.)), None);
+    }
+
+    #[test]
+    fn beaming_bandit_closes_only_by_the_requester() {
+        // Bandit due east flying north, requester flying east
+        let closure = get_closure(90., (400., 90.), (500., 0.));
+        assert!((closure - 400.).abs() < 1e-9, "{closure}");
+        // Both beaming each other
+        let closure = get_closure(90., (400., 0.), (500., 180.));
+        assert!(closure.abs() < 1e-9, "{closure}");
+        assert_eq!(time_to_merge(30., 90., (400., 0.), (500., 180.)), None);
+    }
+
+    #[test]
+    fn closure_below_10_knots_is_not_closing() {
+        assert!(!is_closing(9.9));
+        assert!(is_closing(10.));
+        // Chasing the bandit 9 knots faster
+        assert_eq!(time_to_merge(30., 0., (409., 0.), (400., 0.)), None);
+        assert!(time_to_merge(30., 0., (411., 0.), (400., 0.)).is_some());
+    }
+}
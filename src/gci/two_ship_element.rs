@@ -0,0 +1,196 @@
+//! Bogey dope for a two-ship element, from the midpoint between lead and wingman
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    recognition::{IncomingTransmission, Intent},
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::{
+    context_awareness,
+    dope::{format_dope, get_dope_report, DopeStyle},
+    find_closest_bandit, get_range, locate_requester, IntentHandler,
+};
+
+/// Lead and wingman further apart than this many nautical miles are called separated
+const ELEMENT_SEPARATED_NM: f64 = 5.;
+
+/// Great circle midpoint of two positions, with the longitude between -180 and 180
+fn get_midpoint((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> (f64, f64) {
+    let (lat1, lon1, lat2) = (lat1.to_radians(), lon1.to_radians(), lat2.to_radians());
+    let d_lon = lon2.to_radians() - lon1;
+    let bx = lat2.cos() * d_lon.cos();
+    let by = lat2.cos() * d_lon.sin();
+    let lat = (lat1.sin() + lat2.sin()).atan2((lat1.cos() + bx).hypot(by));
+    let lon = lon1 + by.atan2(lat1.cos() + bx);
+    (lat.to_degrees(), (lon.to_degrees() + 540.) % 360. - 180.)
+}
+
+pub struct ElementBogeyDopeHandler;
+
+impl IntentHandler for ElementBogeyDopeHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        handle_element_bogey_dope(incoming_transmission, state, common_config, transmission_tx);
+    }
+}
+
+fn handle_element_bogey_dope(
+    incoming_transmission: IncomingTransmission,
+    state: &TacviewState,
+    common_config: &CommonConfig,
+    transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+) {
+    let Intent::RequestElementBogeyDope { wingman_callsign } = &incoming_transmission.intent else {
+        return;
+    };
+    let Some((lead_object, lead_latlng)) = locate_requester(
+        &incoming_transmission,
+        state,
+        common_config,
+        transmission_tx,
+    ) else {
+        return;
+    };
+
+    // Without the wingman on scope, the element is called from the lead alone
+    let wingman_latlng = state
+        .find_air_object_by_callsign(wingman_callsign, common_config.friendly_tacview_coalition())
+        .filter(|wingman_object| wingman_object.id != lead_object.id)
+        .and_then(|wingman_object| state.get_latlng(wingman_object));
+    let (element_latlng, separated) = match wingman_latlng {
+        Some(wingman_latlng) => (
+            get_midpoint(lead_latlng, wingman_latlng),
+            get_range(lead_latlng, wingman_latlng) > ELEMENT_SEPARATED_NM,
+        ),
+        None => (lead_latlng, false),
+    };
+
+    let message = match find_closest_bandit(state, common_config, lead_object, element_latlng)
+        .and_then(|(bandit, _)| {
+            get_dope_report(state, common_config, lead_object, element_latlng, bandit)
+        }) {
+        Some(report) => {
+            let dope = format_dope(
+                &report,
+                DopeStyle {
+                    verbosity: context_awareness::get_verbosity(state, common_config),
+                    ..DopeStyle::new(common_config)
+                },
+            );
+            if separated {
+                format!("element is separated, {dope}")
+            } else {
+                dope
+            }
+        }
+        None if state.has_other_air_objects(lead_object) => "Scope is currently clear".to_string(),
+        None => common_config.no_picture_response.clone(),
+    };
+
+    // The same call for both aircraft of the element, or the lead alone if the wingman is not on scope
+    let to_callsigns = if wingman_latlng.is_some() {
+        vec![&incoming_transmission.from_callsign, wingman_callsign]
+    } else {
+        vec![&incoming_transmission.from_callsign]
+    };
+    for to_callsign in to_callsigns {
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: to_callsign.clone(),
+            from_callsign: common_config.callsign.clone(),
+            message: message.clone(),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gci::tests::{air_object, common_config, request, state_with, FRIENDLY};
+
+    use super::*;
+
+    fn assert_latlng_eq((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) {
+        assert!(
+            (lat1 - lat2).abs() < 1e-9 && (lon1 - lon2).abs() < 1e-9,
+            "{lat1}, {lon1} != {lat2}, {lon2}"
+        );
+    }
+
+    #[test]
+    fn midpoint_of_the_same_point_is_the_point() {
+        assert_latlng_eq(get_midpoint((42.5, 41.2), (42.5, 41.2)), (42.5, 41.2));
+        assert_latlng_eq(get_midpoint((0., 0.), (0., 0.)), (0., 0.));
+    }
+
+    #[test]
+    fn midpoint_is_halfway_along_the_great_circle() {
+        assert_latlng_eq(get_midpoint((0., 0.), (0., 2.)), (0., 1.));
+        assert_latlng_eq(get_midpoint((-1., 10.), (1., 10.)), (0., 10.));
+    }
+
+    #[test]
+    fn midpoint_across_the_antimeridian_is_on_it() {
+        let (lat, lon) = get_midpoint((0., 179.), (0., -179.));
+        assert!(lat.abs() < 1e-9, "{lat}");
+        assert!((lon.abs() - 180.).abs() < 1e-9, "{lon}");
+
+        assert_latlng_eq(
+            get_midpoint((10., 178.), (10., -176.)),
+            (10.013_445_902_640_294, -179.),
+        );
+    }
+
+    fn element_calls(state: &TacviewState) -> Vec<(String, String)> {
+        let (transmission_tx, mut transmission_rx) = tokio::sync::mpsc::unbounded_channel();
+        handle_element_bogey_dope(
+            request(
+                "Chevy 1-1",
+                Intent::RequestElementBogeyDope {
+                    wingman_callsign: "Chevy 1-2".to_string(),
+                },
+            ),
+            state,
+            &common_config(""),
+            &transmission_tx,
+        );
+        std::iter::from_fn(|| transmission_rx.try_recv().ok())
+            .map(|transmission| (transmission.to_callsign, transmission.message))
+            .collect()
+    }
+
+    #[test]
+    fn element_call_goes_to_both_aircraft() {
+        let state = state_with([
+            air_object(1, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.),
+            air_object(2, Some("Chevy 1-2"), FRIENDLY, (0., 0.02), 20000., 0.),
+            air_object(3, None, "Allies", (0.5, 0.01), 20000., 180.),
+        ]);
+        let calls = element_calls(&state);
+        assert_eq!(
+            calls.iter().map(|(to, _)| to.as_str()).collect::<Vec<_>>(),
+            ["Chevy 1-1", "Chevy 1-2"]
+        );
+        assert_eq!(calls[0].1, calls[1].1);
+    }
+
+    #[test]
+    fn element_call_without_the_wingman_on_scope_goes_to_the_lead_only() {
+        let state = state_with([
+            air_object(1, Some("Chevy 1-1"), FRIENDLY, (0., 0.), 20000., 0.),
+            air_object(3, None, "Allies", (0.5, 0.), 20000., 180.),
+        ]);
+        let calls = element_calls(&state);
+        assert_eq!(
+            calls.iter().map(|(to, _)| to.as_str()).collect::<Vec<_>>(),
+            ["Chevy 1-1"]
+        );
+    }
+}
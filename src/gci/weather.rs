@@ -0,0 +1,49 @@
+//! Weather readback from the Tacview global properties
+
+use crate::{
+    config::{CommonConfig, MessageCategory},
+    recognition::IncomingTransmission,
+    state::TacviewState,
+    transmission::OutgoingTransmission,
+};
+
+use super::{meters_per_second_to_knots, spell_digits, IntentHandler};
+
+pub struct WeatherHandler;
+
+impl IntentHandler for WeatherHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let mut parts = Vec::new();
+        if let (Some(direction), Some(speed)) =
+            (state.weather.wind_direction, state.weather.wind_speed)
+        {
+            parts.push(format!(
+                "wind {} at {}",
+                spell_digits((direction as usize + 360) % 360, 3),
+                meters_per_second_to_knots(speed).round() as usize
+            ));
+        }
+        if let Some(qnh) = state.weather.qnh {
+            parts.push(format!("QNH {}", spell_digits(qnh.round() as usize, 3)));
+        }
+
+        let message = if parts.is_empty() {
+            "weather unavailable".to_string()
+        } else {
+            parts.join(", ")
+        };
+        let _ = transmission_tx.send(OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message,
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        });
+    }
+}
@@ -0,0 +1,164 @@
+//! Abbreviated ATIS from the real-world weather of the nearest theater airport
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    api::weather::MetarData,
+    config::{CommonConfig, MessageCategory, TheaterAirport, WeatherApiConfig},
+    recognition::IncomingTransmission,
+    state::TacviewState,
+    transmission::{OutgoingTransmission, NATO_ALPHABET},
+};
+
+use super::{get_range, locate_requester, spell_digits, IntentHandler};
+
+const CACHE_DURATION: Duration = Duration::from_secs(30 * 60);
+
+struct CachedMetar {
+    fetched_at: Instant,
+    metar: MetarData,
+    /// Index of the ATIS information letter, advanced on every new observation
+    information: usize,
+}
+
+fn format_atis(airport: &TheaterAirport, metar: &MetarData, information: usize) -> String {
+    let mut parts = vec![
+        airport.name.clone(),
+        format!(
+            "information {}",
+            NATO_ALPHABET[information % NATO_ALPHABET.len()]
+        ),
+    ];
+    if let (Some(direction), Some(speed)) = (metar.wind_direction, metar.wind_speed_kts) {
+        parts.push(format!(
+            "wind {} at {}",
+            spell_digits((direction.round() as usize) % 360, 3),
+            speed.round() as usize
+        ));
+    }
+    if let Some(visibility) = metar.visibility_miles {
+        parts.push(format!(
+            "visibility {} miles",
+            visibility.min(10.).round() as usize
+        ));
+    }
+    if metar.has_clouds {
+        parts.push(match metar.ceiling_ft {
+            Some(ceiling) => format!("ceiling {}", ceiling.round() as usize),
+            None => "ceiling clear".to_string(),
+        });
+    }
+    if let Some(temperature) = metar.temperature_c {
+        parts.push(format!("temperature {}", temperature.round() as isize));
+    }
+    if let Some(dew_point) = metar.dew_point_c {
+        parts.push(format!("dew point {}", dew_point.round() as isize));
+    }
+    if let Some(altimeter) = metar.altimeter_inhg {
+        parts.push(format!(
+            "altimeter {}",
+            spell_digits((altimeter * 100.).round() as usize, 4)
+        ));
+    }
+    parts.join(", ")
+}
+
+pub struct WeatherRelayHandler {
+    config: WeatherApiConfig,
+    /// Keyed by ICAO code
+    cache: Arc<Mutex<HashMap<String, CachedMetar>>>,
+}
+
+impl WeatherRelayHandler {
+    pub fn new(config: WeatherApiConfig) -> Self {
+        Self {
+            config,
+            cache: Arc::default(),
+        }
+    }
+}
+
+impl IntentHandler for WeatherRelayHandler {
+    fn handle(
+        &self,
+        incoming_transmission: IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+        transmission_tx: &tokio::sync::mpsc::UnboundedSender<OutgoingTransmission>,
+    ) {
+        let Some((_, from_object_latlng)) = locate_requester(
+            &incoming_transmission,
+            state,
+            common_config,
+            transmission_tx,
+        ) else {
+            return;
+        };
+
+        let reply = OutgoingTransmission {
+            to_callsign: incoming_transmission.from_callsign,
+            from_callsign: common_config.callsign.clone(),
+            message: String::new(),
+            category: MessageCategory::Response,
+            commit_started_at: None,
+        };
+        let Some(airport) = self
+            .config
+            .theater_airports
+            .iter()
+            .min_by(|airport1, airport2| {
+                let range1 = get_range(from_object_latlng, (airport1.latitude, airport1.longitude));
+                let range2 = get_range(from_object_latlng, (airport2.latitude, airport2.longitude));
+                range1.partial_cmp(&range2).unwrap()
+            })
+            .cloned()
+        else {
+            let _ = transmission_tx.send(OutgoingTransmission {
+                message: "weather unavailable".to_string(),
+                ..reply
+            });
+            return;
+        };
+
+        // Fetching is async, so the reply is sent from a task instead of blocking the GCI loop
+        let config = self.config.clone();
+        let cache = self.cache.clone();
+        let transmission_tx = transmission_tx.clone();
+        tokio::spawn(async move {
+            let cached = cache
+                .lock()
+                .unwrap()
+                .get(&airport.icao)
+                .map(|cached| (cached.fetched_at, cached.metar.clone(), cached.information));
+            let message = match cached {
+                Some((fetched_at, metar, information)) if fetched_at.elapsed() < CACHE_DURATION => {
+                    format_atis(&airport, &metar, information)
+                }
+                _ => match crate::api::weather::get_metar(&airport, &config).await {
+                    Ok(metar) => {
+                        let information = cached.map_or(0, |(_, _, information)| information + 1);
+                        let message = format_atis(&airport, &metar, information);
+                        cache.lock().unwrap().insert(
+                            airport.icao.clone(),
+                            CachedMetar {
+                                fetched_at: Instant::now(),
+                                metar,
+                                information,
+                            },
+                        );
+                        message
+                    }
+                    Err(error) => {
+                        tracing::error!(icao = %airport.icao, error = format!("{error:#}"), "failed to fetch weather");
+                        "weather unavailable".to_string()
+                    }
+                },
+            };
+            let _ = transmission_tx.send(OutgoingTransmission { message, ..reply });
+        });
+    }
+}
@@ -1,22 +1,27 @@
 use std::sync::Arc;
 
 use anyhow::Context;
-use audiopus::{Channels, SampleRate};
 use clap::Parser;
-use futures_util::StreamExt;
 use stopper::Stopper;
-use tokio::sync::RwLock;
+use tokio::{sync::RwLock, task::JoinHandle};
 
-use crate::config::{CliConfig, Config};
+use crate::{
+    config::{CliConfig, Coalition, CoalitionConfig, Config, OpenAiConfig, WeatherApiConfig},
+    state::TacviewState,
+    timeline::TimelineRecorder,
+};
 
 mod api;
+mod audio;
 mod config;
 mod gci;
 mod recognition;
+mod self_test;
 mod state;
+mod timeline;
 mod transmission;
 
-async fn shutdown_signal(stopper: Stopper, stop_tx: tokio::sync::oneshot::Sender<()>) {
+async fn shutdown_signal(stopper: Stopper) {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
             .await
@@ -40,76 +45,256 @@ async fn shutdown_signal(stopper: Stopper, stop_tx: tokio::sync::oneshot::Sender
     }
 
     tracing::info!("signal received, starting graceful shutdown");
-    let _ = stop_tx.send(());
     stopper.stop();
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
-        )
-        .init();
-
-    // Get config
-    let cli_config = CliConfig::parse();
-    tracing::info!("using config file `{}`", cli_config.config.display());
-    let config = Config::from_path(&cli_config.config).await?;
+/// State shared by every controller instance
+#[derive(Clone)]
+struct SharedState {
+    tacview_state: Arc<RwLock<TacviewState>>,
+    tacview_heartbeat: crate::api::tacview::Heartbeat,
+    /// A MAYDAY is relayed on every frequency of the coalition
+    mayday_relay_tx: tokio::sync::broadcast::Sender<(Coalition, String)>,
+    timeline: TimelineRecorder,
+}
 
-    // Init shutdown signal
-    let stopper = Stopper::new();
-    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
-    tokio::spawn(shutdown_signal(stopper.clone(), stop_tx));
+/// Connects to SRS and spawns the loops of a single controller instance
+async fn spawn_controller(
+    coalition_config: CoalitionConfig,
+    openai_config: OpenAiConfig,
+    weather_api_config: Option<WeatherApiConfig>,
+    shared_state: SharedState,
+    stopper: Stopper,
+) -> anyhow::Result<Vec<JoinHandle<()>>> {
+    let SharedState {
+        tacview_state,
+        tacview_heartbeat,
+        mayday_relay_tx,
+        timeline,
+    } = shared_state;
+    let CoalitionConfig { common, srs } = coalition_config;
+    if common.open_mode {
+        tracing::warn!(callsign = %common.callsign, "open mode is enabled, coalitions are ignored and every other air object is treated as a threat");
+    }
 
     // Init APIs
-    let tacview_reader = crate::api::tacview::connect(&config.tacview).await?;
-    let (srs_sink, srs_stream) = crate::api::srs::connect(&config.srs, stop_rx)
-        .await?
-        .split::<Vec<u8>>();
-    let opus_srs_decoder = audiopus::coder::Decoder::new(SampleRate::Hz16000, Channels::Mono)
-        .context("failed to initialize Opus decoder")?;
+    let srs_client = crate::api::srs::SrsClientState::connect(srs, stopper.clone()).await?;
+    let opus_srs_decoder =
+        crate::recognition::new_opus_decoder().context("failed to initialize Opus decoder")?;
 
     // Init channels
     let (recognition_tx, recognition_rx) = tokio::sync::mpsc::unbounded_channel();
     let (transmission_tx, transmission_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (priority_transmission_tx, priority_transmission_rx) =
+        tokio::sync::mpsc::unbounded_channel();
 
-    // Init state
-    let tacview_state = Arc::new(RwLock::new(crate::state::TacviewState::new()));
+    // Init intent handlers
+    let gci_state = Arc::new(crate::gci::GciState {
+        timeline: timeline.clone(),
+        ..Default::default()
+    });
+    let mut handler_registry = crate::gci::HandlerRegistry::new();
+    crate::gci::register_builtin_handlers(
+        &mut handler_registry,
+        &common,
+        weather_api_config.as_ref(),
+        gci_state.clone(),
+        tacview_heartbeat,
+        priority_transmission_tx.clone(),
+        mayday_relay_tx.clone(),
+    );
 
     // Init main logic loops
     let recognition_handle = tokio::spawn(crate::recognition::recognition_loop(
-        config.common.clone(),
-        config.openai.clone(),
+        common.clone(),
+        openai_config.clone(),
         tacview_state.clone(),
-        srs_stream,
+        srs_client.clone(),
         opus_srs_decoder,
         recognition_tx,
         stopper.clone(),
     ));
-    let state_handle = tokio::spawn(crate::state::state_loop(
-        tacview_reader,
+    tokio::spawn(crate::state::check_coalition(
+        common.clone(),
+        tacview_state.clone(),
+        stopper.clone(),
+    ));
+    let marshal_stack_handle = tokio::spawn(crate::gci::marshal_stack_loop(
+        common.clone(),
+        gci_state.clone(),
+        tacview_state.clone(),
+        transmission_tx.clone(),
+        stopper.clone(),
+    ));
+    let aborted_commits = gci_state.aborted_commits.clone();
+    let commit_update_handle = tokio::spawn(crate::gci::commit_update_loop(
+        common.clone(),
+        gci_state,
+        tacview_state.clone(),
+        priority_transmission_tx.clone(),
+        stopper.clone(),
+    ));
+    let picture_timer_handle = tokio::spawn(crate::gci::picture_timer_loop(
+        common.clone(),
+        tacview_state.clone(),
+        transmission_tx.clone(),
+        stopper.clone(),
+    ));
+    let threat_warning_handle = tokio::spawn(crate::gci::threat_warning_loop(
+        common.clone(),
+        tacview_state.clone(),
+        transmission_tx.clone(),
+        timeline,
+        stopper.clone(),
+    ));
+    let surface_attack_handle = tokio::spawn(crate::gci::surface_attack_loop(
+        common.clone(),
+        tacview_state.clone(),
+        transmission_tx.clone(),
+        stopper.clone(),
+    ));
+    let friendly_callsign_broadcast_handle =
+        tokio::spawn(crate::gci::friendly_callsign_broadcast_loop(
+            common.clone(),
+            tacview_state.clone(),
+            transmission_tx.clone(),
+            stopper.clone(),
+        ));
+    let srs_position_handle = tokio::spawn(crate::gci::srs_position_loop(
+        common.clone(),
         tacview_state.clone(),
+        srs_client.clone(),
+        stopper.clone(),
+    ));
+    let mayday_relay_handle = tokio::spawn(crate::gci::mayday_relay_loop(
+        common.clone(),
+        mayday_relay_tx.subscribe(),
+        priority_transmission_tx.clone(),
         stopper.clone(),
     ));
+    let phonetic_callsigns = common.phonetic_callsigns;
     let gci_handle = tokio::spawn(crate::gci::gci_loop(
-        config.common.clone(),
+        common,
         tacview_state,
+        handler_registry,
         recognition_rx,
         transmission_tx,
         stopper.clone(),
     ));
     let transmission_handle = tokio::spawn(crate::transmission::transmission_loop(
-        config.openai.clone(),
-        srs_sink,
+        openai_config,
+        phonetic_callsigns,
+        srs_client,
         transmission_rx,
+        priority_transmission_rx,
+        aborted_commits,
         stopper,
     ));
 
-    recognition_handle.await?;
-    state_handle.await?;
-    gci_handle.await?;
-    transmission_handle.await?;
+    Ok(vec![
+        recognition_handle,
+        commit_update_handle,
+        marshal_stack_handle,
+        picture_timer_handle,
+        threat_warning_handle,
+        surface_attack_handle,
+        friendly_callsign_broadcast_handle,
+        srs_position_handle,
+        mayday_relay_handle,
+        gci_handle,
+        transmission_handle,
+    ])
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .init();
+
+    // Get config
+    let cli_config = CliConfig::parse();
+    tracing::info!("using config file `{}`", cli_config.config.display());
+    let config = Config::from_path(&cli_config.config).await?;
+    if cli_config.check {
+        return crate::self_test::run(&config).await;
+    }
+    let coalition_configs = config.coalition_configs();
+
+    // Init shutdown signal
+    let stopper = Stopper::new();
+    tokio::spawn(shutdown_signal(stopper.clone()));
+
+    // Init APIs
+    let tacview_reader = crate::api::tacview::connect(&config.tacview).await?;
+
+    // Init state
+    let tacview_state = Arc::new(RwLock::new(TacviewState::new(config.tacview.spatial_index)));
+
+    // Init main logic loops
+    let tacview_heartbeat = crate::api::tacview::Heartbeat::new();
+    let tacview_hung = Arc::new(tokio::sync::Notify::new());
+    let timeline = TimelineRecorder::default();
+    let mut handles = vec![
+        tokio::spawn(crate::state::state_loop(
+            config.tacview.clone(),
+            tacview_reader,
+            tacview_state.clone(),
+            tacview_heartbeat.clone(),
+            tacview_hung.clone(),
+            timeline.clone(),
+            stopper.clone(),
+        )),
+        tokio::spawn(crate::api::tacview::health_loop(
+            config.tacview.clone(),
+            tacview_heartbeat.clone(),
+            tacview_hung,
+            stopper.clone(),
+        )),
+        tokio::spawn(crate::state::housekeeping_loop(
+            config.tacview.clone(),
+            tacview_state.clone(),
+            stopper.clone(),
+        )),
+    ];
+    let (mayday_relay_tx, _) = tokio::sync::broadcast::channel(16);
+    let shared_state = SharedState {
+        tacview_state,
+        tacview_heartbeat,
+        mayday_relay_tx,
+        timeline: timeline.clone(),
+    };
+    for coalition_config in coalition_configs {
+        handles.extend(
+            spawn_controller(
+                coalition_config,
+                config.openai.clone(),
+                config.weather_api.clone(),
+                shared_state.clone(),
+                stopper.clone(),
+            )
+            .await?,
+        );
+    }
 
-    Ok(())
+    // A panicked task is reported after the timeline is exported, so the debrief is not lost
+    let mut join_error = None;
+    for handle in handles {
+        if let Err(error) = handle.await {
+            tracing::error!(error = format!("{error:#}"), "task failed");
+            join_error.get_or_insert(error);
+        }
+    }
+
+    if let Some(path) = &cli_config.export_timeline {
+        timeline.export(path).await?;
+        tracing::info!("exported mission timeline to `{}`", path.display());
+    }
+
+    match join_error {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
 }
@@ -1,95 +1,399 @@
 //! recognizing incoming SRS transmission
 
-use std::{io::Cursor, sync::Arc, time::Duration};
+use std::{
+    io::Cursor,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use futures_util::{stream::SplitStream, StreamExt};
+use anyhow::Context;
+use futures_util::StreamExt;
+use itertools::Itertools;
 use serde::Deserialize;
-use srs::VoiceStream;
 use stopper::Stopper;
 use tokio::sync::RwLock;
 
 use crate::{
+    api::{
+        openai::{whisper_language_detect, OpenAiError, Transcription, TRANSCRIBE_MODEL},
+        srs::SrsClientState,
+    },
     config::{CommonConfig, OpenAiConfig},
     state::TacviewState,
 };
 
+mod audio_vad;
+mod brevity_correction;
+mod opus_decoding;
+mod pilot_languages;
+mod radio_discipline;
+mod segmentation;
+mod sender_check;
+mod transmission_merge;
+
+pub use opus_decoding::new_opus_decoder;
+
 #[derive(Debug, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[serde(tag = "intent", rename_all = "snake_case")]
 pub enum Intent {
     RadioCheck,
+    /// Only parsed when distinguished from a radio check by `common.distinguish_comms_check`
+    CommsCheck,
     RequestBogeyDope,
-    #[serde(other)]
-    Unknown,
+    /// Bogey dope for a two-ship element, requested by the lead
+    RequestElementBogeyDope {
+        wingman_callsign: String,
+    },
+    RequestSnap,
+    RequestCommit {
+        /// Bearing of the target from the pilot, if the pilot described one
+        #[serde(default)]
+        bearing: Option<f64>,
+        /// Range of the target from the pilot in nautical miles, if the pilot described one
+        #[serde(default)]
+        range: Option<f64>,
+    },
+    RequestAbort,
+    RequestWeather,
+    RequestAltimeter,
+    RequestMarshal,
+    RequestStatus,
+    RequestTanker {
+        /// Callsign of the tanker, if the pilot named one
+        #[serde(default)]
+        tanker: Option<String>,
+    },
+    KnockItOff,
+    /// Never parsed, but set for transcripts of distress calls
+    #[serde(skip)]
+    Mayday {
+        transcript: String,
+    },
+    RequestFrequency {
+        asset: String,
+    },
+    ReportFrequency {
+        asset: String,
+        frequency_mhz: f64,
+        #[serde(default)]
+        modulation: Option<String>,
+    },
+    /// Any other intent, including "unknown" and configured custom intents
+    #[serde(untagged)]
+    Custom {
+        #[serde(rename = "intent")]
+        name: String,
+    },
+}
+
+impl Intent {
+    /// Name of the intent, as in the parsed JSON
+    pub fn name(&self) -> &str {
+        match self {
+            Self::RadioCheck => "radio_check",
+            Self::CommsCheck => "comms_check",
+            Self::RequestBogeyDope => "request_bogey_dope",
+            Self::RequestElementBogeyDope { .. } => "request_element_bogey_dope",
+            Self::RequestSnap => "request_snap",
+            Self::RequestCommit { .. } => "request_commit",
+            Self::RequestAbort => "request_abort",
+            Self::RequestWeather => "request_weather",
+            Self::RequestAltimeter => "request_altimeter",
+            Self::RequestMarshal => "request_marshal",
+            Self::RequestStatus => "request_status",
+            Self::RequestTanker { .. } => "request_tanker",
+            Self::KnockItOff => "knock_it_off",
+            Self::Mayday { .. } => "mayday",
+            Self::RequestFrequency { .. } => "request_frequency",
+            Self::ReportFrequency { .. } => "report_frequency",
+            Self::Custom { name } => name,
+        }
+    }
+}
+
+/// SRS client that transmitted, from the voice packets
+#[derive(Debug, Clone)]
+pub struct SrsSender {
+    pub guid: String,
+    pub unit_id: u32,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct IncomingTransmission {
     pub to_callsign: String,
     pub from_callsign: String,
+    #[serde(flatten)]
     pub intent: Intent,
+    /// Not parsed, but filled in from the voice packets.
+    /// `None` if the transmission was stepped on by multiple clients.
+    #[serde(skip)]
+    pub sender: Option<SrsSender>,
+}
+
+/// Digits as spoken in callsigns, e.g. "Enfield two one"
+pub const NUMBER_WORDS: [&str; 11] = [
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "niner",
+];
+
+/// Splits into lowercase words of letters and digits, so punctuation doesn't prevent a match
+pub fn to_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Removes Whisper's bracketed or parenthesized non-speech annotations, e.g. "[BLANK_AUDIO]" or "(wind blowing)"
+fn strip_non_speech_annotations(transcript: &str) -> String {
+    let mut stripped = String::with_capacity(transcript.len());
+    let mut closing = None;
+    for c in transcript.chars() {
+        match (closing, c) {
+            (None, '[') => closing = Some(']'),
+            (None, '(') => closing = Some(')'),
+            (None, c) => stripped.push(c),
+            (Some(close), c) if c == close => closing = None,
+            (Some(_), _) => {}
+        }
+    }
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether the transcript is a distress call, with "mayday" spoken as a whole word at least twice in a row,
+/// so "maydays" or mentioning a past mayday are not
+fn is_mayday_call(transcript: &str) -> bool {
+    transcript
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .tuple_windows()
+        .any(|(word1, word2)| {
+            word1.eq_ignore_ascii_case("mayday") && word2.eq_ignore_ascii_case("mayday")
+        })
+}
+
+/// Encodes 16kHz mono PCM samples as a WAV file
+fn encode_wav(samples: Vec<i16>) -> anyhow::Result<Vec<u8>> {
+    let mut voice_buf = Cursor::new(Vec::new());
+    wav::write(
+        wav::Header::new(wav::WAV_FORMAT_PCM, 1, 16000, 16),
+        &wav::BitDepth::Sixteen(samples),
+        &mut voice_buf,
+    )
+    .context("failed to write WAV")?;
+    Ok(voice_buf.into_inner())
+}
+
+/// Transcribes with Whisper, and again with the escalation model if configured and Whisper is not confident
+async fn transcribe(
+    openai_config: &OpenAiConfig,
+    known_language: Option<&str>,
+    self_callsign: &str,
+    possible_callsigns: &[String],
+    distinguish_comms_check: bool,
+    voice_wav: Vec<u8>,
+) -> Result<Transcription, OpenAiError> {
+    let escalation = openai_config
+        .transcribe_escalation_model
+        .as_deref()
+        .map(|model| (model, voice_wav.clone()));
+    let transcription = if openai_config.language_detect_enabled {
+        whisper_language_detect::transcribe_detecting_language(
+            openai_config,
+            known_language,
+            self_callsign,
+            possible_callsigns,
+            distinguish_comms_check,
+            voice_wav,
+        )
+        .await?
+    } else {
+        crate::api::openai::transcribe(
+            openai_config,
+            TRANSCRIBE_MODEL,
+            Some(&openai_config.transcribe_language),
+            self_callsign,
+            possible_callsigns,
+            distinguish_comms_check,
+            voice_wav,
+        )
+        .await?
+    };
+    let (Some((model, voice_wav)), Some(confidence)) = (escalation, transcription.confidence)
+    else {
+        return Ok(transcription);
+    };
+    if confidence >= openai_config.transcribe_escalation_min_confidence {
+        return Ok(transcription);
+    }
+
+    tracing::info!(
+        transcript = %transcription.text,
+        confidence,
+        model,
+        "low transcription confidence, transcribing again"
+    );
+    let language = transcription
+        .language
+        .as_deref()
+        .unwrap_or(&openai_config.transcribe_language);
+    match crate::api::openai::transcribe(
+        openai_config,
+        model,
+        Some(language),
+        self_callsign,
+        possible_callsigns,
+        distinguish_comms_check,
+        voice_wav,
+    )
+    .await
+    {
+        Ok(escalated) => Ok(Transcription {
+            text: escalated.text,
+            ..transcription
+        }),
+        Err(error) => {
+            tracing::warn!(
+                error = format!("{error:#}"),
+                "escalated transcription failed, using the original transcript"
+            );
+            Ok(transcription)
+        }
+    }
 }
 
 pub async fn recognition_loop(
     common_config: CommonConfig,
     openai_config: OpenAiConfig,
     state: Arc<RwLock<TacviewState>>,
-    mut srs_stream: SplitStream<VoiceStream>,
+    srs_client: SrsClientState,
     mut opus_srs_decoder: audiopus::coder::Decoder,
     recognition_tx: tokio::sync::mpsc::UnboundedSender<IncomingTransmission>,
     stopper: Stopper,
 ) {
+    let mut last_transcript: Option<(u64, Instant)> = None;
+    let mut sender_tracker = sender_check::SenderTracker::default();
+    let mut decode_errors =
+        opus_decoding::DecodeErrorCounter::new(common_config.opus_decoder_reset_after_errors);
+    let brevity_corrector = brevity_correction::BrevityCorrector::new(
+        common_config.brevity_correction,
+        &common_config.brevity_corrections,
+    );
+    let mut pilot_languages = pilot_languages::PilotLanguages::default();
+    let mut transmission_merger = transmission_merge::TransmissionMerger::new(
+        common_config.transmission_merge_window_secs,
+        common_config.max_merged_transmission_secs,
+    );
+
     'outer: loop {
         let mut buf = Vec::new();
+        let mut senders = Vec::<SrsSender>::new();
 
         'inner: loop {
-            let res = tokio::time::timeout(
-                Duration::from_millis(500),
-                stopper.stop_future(srs_stream.next()),
-            )
-            .await;
+            let res = {
+                let mut srs_stream = srs_client.stream().lock().await;
+                tokio::time::timeout(
+                    Duration::from_millis(500),
+                    stopper.stop_future(srs_stream.next()),
+                )
+                .await
+            };
 
             match res {
                 Ok(Some(Some(Ok(packet)))) => {
+                    let guid = String::from_utf8_lossy(&packet.sg).to_string();
+                    if !senders.iter().any(|sender| sender.guid == guid) {
+                        senders.push(SrsSender {
+                            guid,
+                            unit_id: packet.unit_id,
+                        });
+                    }
                     let mut decode_buf = [0i16; 5760];
                     match opus_srs_decoder.decode(
                         Some(&packet.audio_part),
                         &mut decode_buf[..],
                         false,
                     ) {
-                        Ok(len) => buf.extend_from_slice(&decode_buf[0..len]),
+                        Ok(len) => {
+                            decode_errors.record_success();
+                            buf.extend_from_slice(&decode_buf[0..len]);
+                        }
                         Err(error) => {
-                            tracing::error!(%error, "Opus decoder error");
+                            let consecutive_decode_errors = decode_errors.record_error();
+                            // Only the first of a run, so a desynced stream doesn't flood the log
+                            if consecutive_decode_errors == 1 {
+                                tracing::error!(error = format!("{error:#}"), "Opus decoder error");
+                            } else {
+                                tracing::debug!(
+                                    error = format!("{error:#}"),
+                                    consecutive_decode_errors,
+                                    "Opus decoder error"
+                                );
+                            }
+                            if decode_errors.take_reset() {
+                                match new_opus_decoder() {
+                                    Ok(decoder) => {
+                                        opus_srs_decoder = decoder;
+                                        tracing::warn!(
+                                            consecutive_decode_errors,
+                                            "reset Opus decoder after consecutive errors"
+                                        );
+                                    }
+                                    Err(error) => {
+                                        tracing::error!(
+                                            error = format!("{error:#}"),
+                                            "failed to reset Opus decoder"
+                                        );
+                                    }
+                                }
+                            }
                         }
                     }
                 }
                 Ok(Some(Some(Err(error)))) => {
-                    tracing::error!(%error, "SRS stream error");
+                    tracing::error!(error = format!("{error:#}"), "SRS stream error");
                 }
-                Ok(None) | Ok(Some(None)) => {
+                Ok(None) => {
                     break 'outer;
                 }
+                Ok(Some(None)) => {
+                    tracing::warn!("SRS stream ended, reconnecting");
+                    if !srs_client.reconnect(&stopper).await {
+                        break 'outer;
+                    }
+                    break 'inner;
+                }
                 Err(_) => {
                     break 'inner;
                 }
             }
         }
 
-        if buf.is_empty() {
+        let Some((buf, senders)) = transmission_merger.push(buf, senders) else {
             continue;
-        }
+        };
 
-        let mut voice_buf = Cursor::new(Vec::new());
-        wav::write(
-            wav::Header::new(wav::WAV_FORMAT_PCM, 1, 16000, 16),
-            &wav::BitDepth::Sixteen(buf),
-            &mut voice_buf,
-        )
-        .unwrap();
+        let buf = if common_config.use_webrtc_vad {
+            let Some(voice) = audio_vad::filter_voice(&buf) else {
+                tracing::debug!(samples = buf.len(), "skipping audio with too little voice");
+                continue;
+            };
+            voice
+        } else {
+            buf
+        };
+
+        let voice_wav = match encode_wav(buf) {
+            Ok(voice_wav) => voice_wav,
+            Err(error) => {
+                tracing::error!(error = format!("{error:#}"), "WAV encoding error");
+                continue;
+            }
+        };
 
         let possible_callsigns = {
             let state = state.read().await;
             state
-                .list_air_callsigns_by_coalition(common_config.coalition.as_tacview_coalition())
+                .list_air_callsigns_by_coalition(common_config.friendly_tacview_coalition())
                 .flat_map(|callsign| {
                     callsign
                         .split('|')
@@ -99,40 +403,202 @@ pub async fn recognition_loop(
                 .map(|callsign| callsign.trim().to_string())
                 .collect::<Vec<_>>()
         };
-        match crate::api::openai::transcribe(
+        let known_language = match senders.as_slice() {
+            [sender] => pilot_languages.get(&sender.guid, Instant::now()),
+            _ => None,
+        };
+        // A transcription hinted with the known language returns the hint, rather than a detection
+        let detects_language = known_language.is_none();
+        match transcribe(
             &openai_config,
+            known_language,
             &common_config.callsign,
             &possible_callsigns,
-            voice_buf.into_inner(),
+            common_config.distinguish_comms_check,
+            voice_wav,
         )
         .await
         {
-            Ok(transcript) => {
-                if transcript.is_empty() {
+            Ok(Transcription {
+                text: transcript,
+                language,
+                ..
+            }) => {
+                let transcript = if common_config.strip_non_speech_annotations {
+                    strip_non_speech_annotations(&transcript)
+                } else {
+                    transcript
+                };
+                let corrected = brevity_corrector.correct(&transcript);
+                if corrected != transcript {
+                    tracing::debug!(%transcript, %corrected, "corrected misheard brevity terms");
+                }
+                let transcript = corrected;
+                if !transcript.chars().any(char::is_alphanumeric) {
                     continue;
                 }
 
-                tracing::info!(%transcript, "parsing transcript");
-                match crate::api::openai::parse_transmission(
-                    &openai_config,
-                    &common_config.callsign,
-                    transcript.clone(),
-                )
-                .await
-                {
-                    Ok(incoming_transmission) => {
-                        tracing::info!(?incoming_transmission, "incoming transmission");
-                        let _ = recognition_tx.send(incoming_transmission);
+                let transcript_hash = fxhash::hash64(transcript.as_bytes());
+                let now = Instant::now();
+                if let Some((last_hash, last_time)) = last_transcript {
+                    if last_hash == transcript_hash
+                        && now.duration_since(last_time).as_secs_f64()
+                            < common_config.transcript_dedup_window_secs
+                    {
+                        tracing::debug!(%transcript, "skipping duplicate transcript");
+                        continue;
                     }
-                    Err(error) => {
-                        tracing::error!(%transcript, %error, "failed to parse incoming transmission");
+                }
+                last_transcript = Some((transcript_hash, now));
+
+                // Distress calls skip radio discipline and parsing, as they may not address the AWACS
+                let transcript_lowercase = transcript.to_lowercase();
+                if common_config.enable_mayday_relay && is_mayday_call(&transcript) {
+                    let mut incoming_transmission = IncomingTransmission {
+                        to_callsign: common_config.callsign.clone(),
+                        from_callsign: possible_callsigns
+                            .iter()
+                            .find(|callsign| {
+                                transcript_lowercase.contains(&callsign.to_lowercase())
+                            })
+                            .cloned()
+                            .unwrap_or_default(),
+                        intent: Intent::Mayday {
+                            transcript: transcript.clone(),
+                        },
+                        sender: None,
+                    };
+                    if let [sender] = senders.as_slice() {
+                        incoming_transmission.sender = Some(sender.clone());
+                    }
+                    sender_tracker.check(
+                        &mut incoming_transmission,
+                        &*state.read().await,
+                        &common_config,
+                    );
+                    tracing::warn!(?incoming_transmission, "MAYDAY transmission");
+                    let _ = recognition_tx.send(incoming_transmission);
+                    continue;
+                }
+
+                if !radio_discipline::passes_radio_discipline(
+                    &transcript,
+                    &common_config.callsign,
+                    &possible_callsigns,
+                    &common_config.radio_discipline,
+                ) {
+                    tracing::info!(%transcript, "ignoring transcript failing radio discipline");
+                    continue;
+                }
+
+                let segments = segmentation::split_transmissions(
+                    &transcript,
+                    &common_config.callsign,
+                    common_config.max_transcript_segments,
+                );
+                if segments.len() > 1 {
+                    tracing::info!(%transcript, segments = segments.len(), "splitting transcript of multiple transmissions");
+                }
+                for transcript in segments {
+                    tracing::info!(%transcript, "parsing transcript");
+                    match crate::api::openai::parse_transmission::<IncomingTransmission>(
+                        &openai_config,
+                        &common_config.callsign,
+                        &common_config.custom_intents,
+                        common_config.distinguish_comms_check,
+                        transcript.to_string(),
+                    )
+                    .await
+                    {
+                        Ok(mut incoming_transmission) => {
+                            if let [sender] = senders.as_slice() {
+                                incoming_transmission.sender = Some(sender.clone());
+                            }
+                            sender_tracker.check(
+                                &mut incoming_transmission,
+                                &*state.read().await,
+                                &common_config,
+                            );
+                            if let (true, true, Some(language), [sender]) = (
+                                openai_config.language_detect_enabled,
+                                detects_language,
+                                &language,
+                                senders.as_slice(),
+                            ) {
+                                pilot_languages.record_detection(
+                                    &sender.guid,
+                                    language,
+                                    Instant::now(),
+                                );
+                            }
+                            tracing::info!(?incoming_transmission, "incoming transmission");
+                            let _ = recognition_tx.send(incoming_transmission);
+                        }
+                        Err(error) => {
+                            tracing::error!(%transcript, error = format!("{error:#}"), "failed to parse incoming transmission");
+                        }
                     }
                 }
             }
             Err(error) => {
-                tracing::error!(%error, "OpenAI transcribe error");
+                tracing::error!(error = format!("{error:#}"), "OpenAI transcribe error");
             }
         }
     }
     tracing::info!("exiting recognition loop");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_speech_annotations_are_stripped() {
+        assert_eq!(strip_non_speech_annotations("[BLANK_AUDIO]"), "");
+        assert_eq!(strip_non_speech_annotations("(wind blowing)"), "");
+        assert_eq!(
+            strip_non_speech_annotations("[static] Magic, Chevy 1-1, (engine noise) bogey dope"),
+            "Magic, Chevy 1-1, bogey dope"
+        );
+        assert_eq!(
+            strip_non_speech_annotations("  [MUSIC]  (coughs) [BLANK_AUDIO] "),
+            ""
+        );
+    }
+
+    #[test]
+    fn speech_without_annotations_is_kept() {
+        assert_eq!(
+            strip_non_speech_annotations("Magic, Chevy 1-1, request picture"),
+            "Magic, Chevy 1-1, request picture"
+        );
+    }
+
+    #[test]
+    fn repeated_mayday_is_a_distress_call() {
+        assert!(is_mayday_call(
+            "Mayday, mayday, mayday, Chevy 1-1, engine failure"
+        ));
+        assert!(is_mayday_call("MAYDAY MAYDAY Chevy 1-1 ejecting"));
+    }
+
+    #[test]
+    fn mentioning_mayday_is_not_a_distress_call() {
+        assert!(!is_mayday_call("Magic, Chevy 1-1, mayday, engine failure"));
+        assert!(!is_mayday_call(
+            "Magic, Chevy 1-1, Enfield 2-1 called mayday earlier"
+        ));
+        assert!(!is_mayday_call(
+            "Magic, Chevy 1-1, no more maydays mayday drills today"
+        ));
+        assert!(!is_mayday_call("Magic, Chevy 1-1, bogey dope"));
+    }
+
+    #[test]
+    fn unclosed_annotation_strips_to_the_end() {
+        assert_eq!(
+            strip_non_speech_annotations("Magic, Chevy 1-1 [inaudible"),
+            "Magic, Chevy 1-1"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Voice activity detection with WebRTC VAD
+
+use webrtc_vad::{SampleRate, Vad, VadMode};
+
+/// 20ms at 16kHz
+const FRAME_LEN: usize = 320;
+
+/// Audio with a smaller ratio of voice frames than this is skipped
+const MIN_VOICE_RATIO: f64 = 0.2;
+
+/// Voice frames of 16kHz mono PCM samples, or `None` if there is too little voice
+pub fn filter_voice(samples: &[i16]) -> Option<Vec<i16>> {
+    // `Vad` is not `Send`, so it is created for each buffer instead of living in the recognition loop
+    let mut vad = Vad::new_with_rate_and_mode(SampleRate::Rate16kHz, VadMode::Quality);
+
+    let mut voice = Vec::with_capacity(samples.len());
+    let mut frame_count = 0;
+    let mut voice_frame_count = 0;
+    for frame in samples.chunks_exact(FRAME_LEN) {
+        frame_count += 1;
+        if vad.is_voice_segment(frame).unwrap_or(true) {
+            voice_frame_count += 1;
+            voice.extend_from_slice(frame);
+        }
+    }
+
+    if frame_count == 0 || (voice_frame_count as f64) < frame_count as f64 * MIN_VOICE_RATIO {
+        return None;
+    }
+    Some(voice)
+}
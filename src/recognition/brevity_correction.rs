@@ -0,0 +1,98 @@
+//! Correcting brevity terms Whisper commonly mishears, like "Bogota" for "bogey dope"
+
+use std::collections::HashMap;
+
+use super::to_words;
+
+/// Mishearings corrected unless overridden by `common.brevity_corrections`
+const DEFAULT_CORRECTIONS: &[(&str, &str)] = &[
+    ("bogota", "bogey dope"),
+    ("bogey dopes", "bogey dope"),
+    ("bogie dope", "bogey dope"),
+    ("boogie dope", "bogey dope"),
+    ("bogie", "bogey"),
+    ("boogie", "bogey"),
+    ("bra", "BRAA"),
+    ("brah", "BRAA"),
+    ("bulls eye", "bullseye"),
+    ("bull's eye", "bullseye"),
+    ("pitcher", "picture"),
+    ("snaplock", "snap lock"),
+    ("tally hoe", "tally ho"),
+];
+
+pub struct BrevityCorrector {
+    /// Misheard words and their correction, longest first so longer phrases take precedence
+    corrections: Vec<(Vec<String>, String)>,
+}
+
+impl BrevityCorrector {
+    /// Default corrections with the configured ones added, replacing defaults of the same mishearing
+    pub fn new(enable_defaults: bool, additions: &HashMap<String, String>) -> Self {
+        let mut corrections = HashMap::new();
+        if enable_defaults {
+            for (misheard, correct) in DEFAULT_CORRECTIONS {
+                corrections.insert(to_words(misheard), correct.to_string());
+            }
+        }
+        for (misheard, correct) in additions {
+            corrections.insert(to_words(misheard), correct.clone());
+        }
+        let mut corrections = corrections
+            .into_iter()
+            .filter(|(misheard, _)| !misheard.is_empty())
+            .collect::<Vec<_>>();
+        corrections.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        Self { corrections }
+    }
+
+    /// Replaces whole-word mishearings case-insensitively
+    pub fn correct(&self, transcript: &str) -> String {
+        if self.corrections.is_empty() {
+            return transcript.to_string();
+        }
+
+        // Byte ranges of the words in the transcript
+        let mut spans = Vec::new();
+        let mut start = None;
+        for (index, c) in transcript.char_indices() {
+            match (start, c.is_alphanumeric()) {
+                (None, true) => start = Some(index),
+                (Some(word_start), false) => {
+                    spans.push(word_start..index);
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(word_start) = start {
+            spans.push(word_start..transcript.len());
+        }
+        let words = spans
+            .iter()
+            .map(|span| transcript[span.clone()].to_lowercase())
+            .collect::<Vec<_>>();
+
+        let mut corrected = String::with_capacity(transcript.len());
+        let mut copied_until = 0;
+        let mut index = 0;
+        while index < words.len() {
+            let matched = self.corrections.iter().find(|(misheard, _)| {
+                words
+                    .get(index..index + misheard.len())
+                    .is_some_and(|candidate| candidate == misheard.as_slice())
+            });
+            match matched {
+                Some((misheard, correct)) => {
+                    corrected.push_str(&transcript[copied_until..spans[index].start]);
+                    corrected.push_str(correct);
+                    index += misheard.len();
+                    copied_until = spans[index - 1].end;
+                }
+                None => index += 1,
+            }
+        }
+        corrected.push_str(&transcript[copied_until..]);
+        corrected
+    }
+}
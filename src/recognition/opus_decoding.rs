@@ -0,0 +1,81 @@
+//! Decoding SRS audio, and recovering a decoder desynced from the stream
+
+use audiopus::{coder::Decoder, Channels, SampleRate};
+
+/// Decoder of the 16 kHz mono Opus audio SRS sends
+pub fn new_opus_decoder() -> Result<Decoder, audiopus::Error> {
+    Decoder::new(SampleRate::Hz16000, Channels::Mono)
+}
+
+/// Counts consecutive decode errors, to reset the decoder after `reset_after_errors` of them
+pub struct DecodeErrorCounter {
+    /// 0 to never reset
+    reset_after_errors: usize,
+    consecutive_errors: usize,
+}
+
+impl DecodeErrorCounter {
+    pub fn new(reset_after_errors: usize) -> Self {
+        Self {
+            reset_after_errors,
+            consecutive_errors: 0,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.consecutive_errors = 0;
+    }
+
+    /// Returns the number of consecutive errors so far, including this one
+    pub fn record_error(&mut self) -> usize {
+        self.consecutive_errors += 1;
+        self.consecutive_errors
+    }
+
+    /// Whether the decoder should be reset now, starting a new count if so
+    pub fn take_reset(&mut self) -> bool {
+        let reset =
+            self.reset_after_errors > 0 && self.consecutive_errors >= self.reset_after_errors;
+        if reset {
+            self.consecutive_errors = 0;
+        }
+        reset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decoder_is_reset_after_consecutive_errors() {
+        let mut counter = DecodeErrorCounter::new(3);
+        assert_eq!(counter.record_error(), 1);
+        assert!(!counter.take_reset());
+        assert_eq!(counter.record_error(), 2);
+        assert!(!counter.take_reset());
+        assert_eq!(counter.record_error(), 3);
+        assert!(counter.take_reset());
+        // The count starts over after a reset
+        assert_eq!(counter.record_error(), 1);
+        assert!(!counter.take_reset());
+    }
+
+    #[test]
+    fn successful_decode_breaks_the_run() {
+        let mut counter = DecodeErrorCounter::new(2);
+        counter.record_error();
+        counter.record_success();
+        assert_eq!(counter.record_error(), 1);
+        assert!(!counter.take_reset());
+    }
+
+    #[test]
+    fn decoder_is_never_reset_with_zero() {
+        let mut counter = DecodeErrorCounter::new(0);
+        for _ in 0..100 {
+            counter.record_error();
+            assert!(!counter.take_reset());
+        }
+    }
+}
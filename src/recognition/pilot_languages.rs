@@ -0,0 +1,108 @@
+//! Remembering the language detected for each SRS client, so later transmissions skip detection
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// A language is remembered once detected this many times in a row, so a single misdetection of a short
+/// or noisy transmission doesn't stick
+const CONFIRMING_DETECTIONS: usize = 2;
+/// Remembered languages are detected again after this long, e.g. for another pilot in the same slot
+const LANGUAGE_TTL: Duration = Duration::from_secs(30 * 60);
+
+enum PilotLanguage {
+    Detected { language: String, detections: usize },
+    Confirmed { language: String, at: Instant },
+}
+
+/// ISO-639-1 codes of the languages detected for pilots, keyed by SRS client GUID.
+/// Keyed by client rather than by callsign, as the client is known whether or not the spoken callsign is.
+#[derive(Default)]
+pub struct PilotLanguages {
+    languages: HashMap<String, PilotLanguage>,
+}
+
+impl PilotLanguages {
+    /// Confirmed language of the SRS client, if not expired
+    pub fn get(&self, guid: &str, now: Instant) -> Option<&str> {
+        match self.languages.get(guid)? {
+            PilotLanguage::Confirmed { language, at } if now.duration_since(*at) < LANGUAGE_TTL => {
+                Some(language)
+            }
+            _ => None,
+        }
+    }
+
+    /// Records a language detected without a hint, confirming it once detected often enough in a row
+    pub fn record_detection(&mut self, guid: &str, language: &str, now: Instant) {
+        self.languages
+            .retain(|_, pilot_language| match pilot_language {
+                PilotLanguage::Confirmed { at, .. } => now.duration_since(*at) < LANGUAGE_TTL,
+                PilotLanguage::Detected { .. } => true,
+            });
+        let detections = match self.languages.get(guid) {
+            Some(PilotLanguage::Detected {
+                language: detected,
+                detections,
+            }) if detected == language => detections + 1,
+            _ => 1,
+        };
+        let pilot_language = if detections >= CONFIRMING_DETECTIONS {
+            tracing::info!(guid, language, "remembering the language of SRS client");
+            PilotLanguage::Confirmed {
+                language: language.to_string(),
+                at: now,
+            }
+        } else {
+            PilotLanguage::Detected {
+                language: language.to_string(),
+                detections,
+            }
+        };
+        self.languages.insert(guid.to_string(), pilot_language);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_is_remembered_after_repeated_detections() {
+        let now = Instant::now();
+        let mut pilot_languages = PilotLanguages::default();
+        pilot_languages.record_detection("guid", "ru", now);
+        assert_eq!(pilot_languages.get("guid", now), None);
+        pilot_languages.record_detection("guid", "ru", now);
+        assert_eq!(pilot_languages.get("guid", now), Some("ru"));
+        assert_eq!(pilot_languages.get("other", now), None);
+    }
+
+    #[test]
+    fn differing_detection_starts_over() {
+        let now = Instant::now();
+        let mut pilot_languages = PilotLanguages::default();
+        pilot_languages.record_detection("guid", "ru", now);
+        pilot_languages.record_detection("guid", "uk", now);
+        assert_eq!(pilot_languages.get("guid", now), None);
+        pilot_languages.record_detection("guid", "uk", now);
+        assert_eq!(pilot_languages.get("guid", now), Some("uk"));
+    }
+
+    #[test]
+    fn remembered_language_expires() {
+        let now = Instant::now();
+        let mut pilot_languages = PilotLanguages::default();
+        pilot_languages.record_detection("guid", "ru", now);
+        pilot_languages.record_detection("guid", "ru", now);
+        let later = now + LANGUAGE_TTL;
+        assert_eq!(pilot_languages.get("guid", later), None);
+
+        // Expired languages are dropped, and detected anew
+        pilot_languages.record_detection("other", "de", later);
+        assert!(!pilot_languages.languages.contains_key("guid"));
+        pilot_languages.record_detection("guid", "en", later);
+        assert_eq!(pilot_languages.get("guid", later), None);
+    }
+}
@@ -0,0 +1,49 @@
+//! Rejecting garbled transcripts before they reach the parser
+
+use crate::{config::RadioDisciplineConfig, state::normalize_callsign};
+
+fn normalize(s: &str) -> String {
+    normalize_callsign(s)
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+pub fn passes_radio_discipline(
+    transcript: &str,
+    bot_callsign: &str,
+    known_callsigns: &[String],
+    config: &RadioDisciplineConfig,
+) -> bool {
+    let words = transcript.split_whitespace().collect::<Vec<_>>();
+    if words.len() < config.min_word_count || words.len() > config.max_word_count {
+        tracing::debug!(%transcript, word_count = words.len(), "transcript word count out of range");
+        return false;
+    }
+
+    let leading_words = normalize(
+        &words
+            .iter()
+            .take(config.requires_callsign_in_first_n_words)
+            .copied()
+            .collect::<String>(),
+    );
+    if !leading_words.contains(&normalize(bot_callsign)) {
+        tracing::debug!(%transcript, "transcript does not start by addressing the AWACS");
+        return false;
+    }
+
+    if config.reject_if_no_known_callsign {
+        let normalized_transcript = normalize(transcript);
+        let has_known_callsign = known_callsigns
+            .iter()
+            .map(|callsign| normalize(callsign))
+            .any(|callsign| !callsign.is_empty() && normalized_transcript.contains(&callsign));
+        if !has_known_callsign {
+            tracing::debug!(%transcript, "transcript has no known callsign");
+            return false;
+        }
+    }
+
+    true
+}
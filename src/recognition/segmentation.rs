@@ -0,0 +1,170 @@
+//! Splitting a transcript of several transmissions run together
+
+use super::NUMBER_WORDS;
+
+const CLAUSE_SEPARATORS: [char; 5] = [',', '.', '?', '!', ';'];
+
+/// Whether the clause could be a pilot's callsign, e.g. "Chevy 1-1" or "Enfield two one"
+fn looks_like_callsign(clause: &str) -> bool {
+    let words = clause.split_whitespace().collect::<Vec<_>>();
+    (1..=4).contains(&words.len())
+        && words.last().is_some_and(|word| {
+            word.chars().any(|c| c.is_ascii_digit())
+                || NUMBER_WORDS.contains(&word.to_ascii_lowercase().as_str())
+        })
+}
+
+/// Start of the transmission in which the controller's callsign at `index` is spoken,
+/// moved back to the pilot's callsign if it was spoken first, as in "Chevy 1-1, Magic, bogey dope"
+fn transmission_start(transcript: &str, index: usize) -> usize {
+    let Some(before) = transcript[..index].trim_end().strip_suffix(',') else {
+        return index;
+    };
+    let clause_start = before
+        .rfind(CLAUSE_SEPARATORS)
+        .map_or(0, |separator| separator + 1);
+    if looks_like_callsign(&before[clause_start..]) {
+        clause_start
+    } else {
+        index
+    }
+}
+
+/// Splits the transcript at the start of each transmission the controller's callsign is spoken in,
+/// into at most `max_segments` segments. Text before the first callsign stays with the first segment.
+pub fn split_transmissions<'a>(
+    transcript: &'a str,
+    bot_callsign: &str,
+    max_segments: usize,
+) -> Vec<&'a str> {
+    let lowercase_transcript = transcript.to_ascii_lowercase();
+    let lowercase_callsign = bot_callsign.trim().to_ascii_lowercase();
+    if lowercase_callsign.is_empty() || max_segments <= 1 {
+        return vec![transcript];
+    }
+
+    // Only callsigns spoken as whole words, skipping the first one as it starts the first segment
+    let boundaries = lowercase_transcript
+        .match_indices(&lowercase_callsign)
+        .map(|(index, _)| index)
+        .filter(|index| {
+            lowercase_transcript[..*index]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !c.is_alphanumeric())
+                && lowercase_transcript[index + lowercase_callsign.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| !c.is_alphanumeric())
+        })
+        .skip(1)
+        .take(max_segments - 1)
+        .collect::<Vec<_>>();
+
+    let mut segments = Vec::with_capacity(boundaries.len() + 1);
+    let mut start = 0;
+    for boundary in boundaries {
+        let boundary = Some(transmission_start(transcript, boundary))
+            .filter(|pilot_callsign_start| *pilot_callsign_start > start)
+            .unwrap_or(boundary);
+        segments.push(transcript[start..boundary].trim());
+        start = boundary;
+    }
+    segments.push(transcript[start..].trim());
+    segments.retain(|segment| !segment.is_empty());
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transmissions_addressing_the_controller_first_are_split_at_its_callsign() {
+        assert_eq!(
+            split_transmissions(
+                "Magic, Chevy 1-1, bogey dope. Magic, Enfield 2-1, picture.",
+                "Magic",
+                3
+            ),
+            [
+                "Magic, Chevy 1-1, bogey dope.",
+                "Magic, Enfield 2-1, picture."
+            ]
+        );
+    }
+
+    #[test]
+    fn pilot_callsign_spoken_first_stays_with_its_transmission() {
+        assert_eq!(
+            split_transmissions(
+                "Chevy 1-1, Magic, bogey dope. Enfield 2-1, Magic, picture.",
+                "Magic",
+                3
+            ),
+            [
+                "Chevy 1-1, Magic, bogey dope.",
+                "Enfield 2-1, Magic, picture."
+            ]
+        );
+        assert_eq!(
+            split_transmissions(
+                "Chevy 1-1, Magic, bogey dope, Enfield two one, Magic, picture",
+                "Magic",
+                3
+            ),
+            [
+                "Chevy 1-1, Magic, bogey dope,",
+                "Enfield two one, Magic, picture"
+            ]
+        );
+        assert_eq!(
+            split_transmissions(
+                "Magic, Chevy 1-1, bogey dope. Enfield 2-1, Magic, picture.",
+                "Magic",
+                3
+            ),
+            [
+                "Magic, Chevy 1-1, bogey dope.",
+                "Enfield 2-1, Magic, picture."
+            ]
+        );
+    }
+
+    #[test]
+    fn request_before_the_controller_callsign_is_not_taken_for_a_callsign() {
+        assert_eq!(
+            split_transmissions(
+                "Magic, Chevy 1-1, bogey dope, Magic, Enfield 2-1, picture",
+                "Magic",
+                3
+            ),
+            [
+                "Magic, Chevy 1-1, bogey dope,",
+                "Magic, Enfield 2-1, picture"
+            ]
+        );
+    }
+
+    #[test]
+    fn segments_are_bounded() {
+        let transcript = "Magic, Chevy 1-1, radio check. Magic, Enfield 2-1, radio check. \
+                          Magic, Colt 3-1, radio check.";
+        assert_eq!(split_transmissions(transcript, "Magic", 1), [transcript]);
+        assert_eq!(
+            split_transmissions(transcript, "Magic", 2),
+            [
+                "Magic, Chevy 1-1, radio check.",
+                "Magic, Enfield 2-1, radio check. Magic, Colt 3-1, radio check."
+            ]
+        );
+    }
+
+    #[test]
+    fn callsign_inside_a_word_is_not_a_boundary() {
+        assert_eq!(
+            split_transmissions("Chevy 1-1, Magic, that was magical", "Magic", 3),
+            ["Chevy 1-1, Magic, that was magical"]
+        );
+    }
+}
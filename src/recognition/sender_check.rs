@@ -0,0 +1,79 @@
+//! Cross-checking the spoken callsign against the SRS client that actually transmitted
+
+use std::collections::HashMap;
+
+use crate::{
+    config::{CommonConfig, SenderCheck},
+    state::TacviewState,
+};
+
+use super::IncomingTransmission;
+
+/// Remembers the callsign each SRS client was last confirmed as
+#[derive(Default)]
+pub struct SenderTracker {
+    /// Callsigns keyed by SRS client GUID
+    known_senders: HashMap<String, String>,
+}
+
+impl SenderTracker {
+    pub fn check(
+        &mut self,
+        incoming_transmission: &mut IncomingTransmission,
+        state: &TacviewState,
+        common_config: &CommonConfig,
+    ) {
+        if matches!(common_config.sender_check, SenderCheck::Off)
+            && !common_config.infer_missing_callsign
+        {
+            return;
+        }
+        let Some(sender) = &incoming_transmission.sender else {
+            return;
+        };
+
+        if incoming_transmission.from_callsign.trim().is_empty() {
+            if !common_config.infer_missing_callsign {
+                return;
+            }
+            if let Some(known_callsign) = self.known_senders.get(&sender.guid) {
+                tracing::info!(guid = %sender.guid, %known_callsign, "inferring missing callsign from SRS client");
+                incoming_transmission.from_callsign = known_callsign.clone();
+            }
+            return;
+        }
+
+        let on_scope = state
+            .find_air_object_by_callsign(
+                &incoming_transmission.from_callsign,
+                common_config.friendly_tacview_coalition(),
+            )
+            .is_some();
+        match self.known_senders.get(&sender.guid) {
+            Some(known_callsign)
+                if !matches!(common_config.sender_check, SenderCheck::Off)
+                    && !known_callsign
+                        .eq_ignore_ascii_case(&incoming_transmission.from_callsign) =>
+            {
+                tracing::warn!(
+                    guid = %sender.guid,
+                    unit_id = sender.unit_id,
+                    %known_callsign,
+                    from_callsign = %incoming_transmission.from_callsign,
+                    "spoken callsign does not match the callsign previously used by this SRS client"
+                );
+                if !on_scope && matches!(common_config.sender_check, SenderCheck::Correct) {
+                    incoming_transmission.from_callsign = known_callsign.clone();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        if on_scope {
+            self.known_senders.insert(
+                sender.guid.clone(),
+                incoming_transmission.from_callsign.clone(),
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Merging transmissions a pilot keys up in quick succession, e.g. when stuttering
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use super::SrsSender;
+
+/// Sample rate of the decoded audio
+const SAMPLE_RATE: f64 = 16000.;
+
+struct PendingTransmission {
+    buf: Vec<i16>,
+    senders: Vec<SrsSender>,
+    ended_at: Instant,
+}
+
+impl PendingTransmission {
+    fn is_only_from(&self, sender: &SrsSender) -> bool {
+        matches!(self.senders.as_slice(), [only] if only.guid == sender.guid)
+    }
+}
+
+/// Holds each transmission of a single SRS client for the merge window,
+/// appending the next one if the same client keys up again within it
+pub struct TransmissionMerger {
+    /// `None` to pass every transmission through immediately
+    window: Option<Duration>,
+    max_samples: usize,
+    pending: VecDeque<PendingTransmission>,
+}
+
+impl TransmissionMerger {
+    pub fn new(window_secs: Option<f64>, max_merged_secs: f64) -> Self {
+        Self {
+            window: window_secs
+                .filter(|window_secs| *window_secs > 0.)
+                .map(Duration::from_secs_f64),
+            max_samples: (max_merged_secs * SAMPLE_RATE) as usize,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Takes the audio of every listening period, empty if nothing was received.
+    /// Returns the audio and senders of a transmission that can no longer be continued.
+    pub fn push(
+        &mut self,
+        buf: Vec<i16>,
+        senders: Vec<SrsSender>,
+    ) -> Option<(Vec<i16>, Vec<SrsSender>)> {
+        let Some(window) = self.window else {
+            return (!buf.is_empty()).then_some((buf, senders));
+        };
+        let now = Instant::now();
+
+        if !buf.is_empty() {
+            let continues_last = match (self.pending.back(), senders.as_slice()) {
+                (Some(last), [sender]) => {
+                    last.is_only_from(sender)
+                        && now.duration_since(last.ended_at) < window
+                        && last.buf.len() + buf.len() <= self.max_samples
+                }
+                _ => false,
+            };
+            if continues_last {
+                let last = self.pending.back_mut().unwrap();
+                tracing::debug!(
+                    guid = %senders[0].guid,
+                    samples = buf.len(),
+                    "merging consecutive transmissions of the same sender"
+                );
+                last.buf.extend(buf);
+                last.ended_at = now;
+            } else {
+                self.pending.push_back(PendingTransmission {
+                    buf,
+                    senders,
+                    ended_at: now,
+                });
+            }
+        }
+
+        // Stepped on transmissions are never merged, and others only until the window passes
+        // or another transmission follows
+        let first = self.pending.front()?;
+        if self.pending.len() > 1
+            || first.senders.len() != 1
+            || now.duration_since(first.ended_at) >= window
+        {
+            self.pending
+                .pop_front()
+                .map(|pending| (pending.buf, pending.senders))
+        } else {
+            None
+        }
+    }
+}
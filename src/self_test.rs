@@ -0,0 +1,53 @@
+//! Connectivity checks of every external service, for `--check`
+
+use crate::config::Config;
+
+fn report(service: &str, result: anyhow::Result<()>) -> bool {
+    match result {
+        Ok(()) => {
+            tracing::info!("{service}: pass");
+            true
+        }
+        Err(error) => {
+            tracing::error!("{service}: fail, {error:#}");
+            false
+        }
+    }
+}
+
+pub async fn run(config: &Config) -> anyhow::Result<()> {
+    let mut passed = report(
+        "Tacview",
+        crate::api::tacview::connect(&config.tacview)
+            .await
+            .map(|_| ()),
+    );
+
+    for coalition_config in config.coalition_configs() {
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+        passed &= report(
+            &format!("SRS ({})", coalition_config.common.callsign),
+            crate::api::srs::connect(
+                &coalition_config.srs,
+                futures_channel::mpsc::unbounded().1,
+                stop_rx,
+            )
+            .await
+            .map(|_| ()),
+        );
+        let _ = stop_tx.send(());
+    }
+
+    passed &= report(
+        "OpenAI",
+        crate::api::openai::list_models(&config.openai)
+            .await
+            .map_err(anyhow::Error::from),
+    );
+
+    if !passed {
+        anyhow::bail!("self test failed");
+    }
+    tracing::info!("self test passed");
+    Ok(())
+}
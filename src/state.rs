@@ -1,105 +1,589 @@
 //! airspace state management
 
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{btree_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
+use caseless::Caseless;
 use stopper::Stopper;
 use tacview_realtime_client::acmi::{
     record::{
+        event::EventKind,
         global_property::GlobalProperty,
         object_property::{Coords, ObjectProperty, Tag},
         Record,
     },
     RealTimeReader,
 };
-use tokio::{io::BufStream, net::TcpStream, sync::RwLock};
+use tokio::{
+    io::BufStream,
+    net::TcpStream,
+    sync::{Notify, RwLock},
+};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::{
+    api::tacview::Heartbeat,
+    config::{CommonConfig, TacviewConfig},
+    timeline::{MissionEvent, TimelineRecorder},
+};
+
+mod ludicrous_speed_filter;
+mod spatial_index;
+
+use spatial_index::SpatialIndex;
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Objects updated within this are never evicted to make room for new objects
+const RECENTLY_ACTIVE: Duration = Duration::from_secs(30);
+
+/// Below this many objects, a linear scan is as fast as the spatial index
+const SPATIAL_INDEX_MIN_OBJECTS: usize = 200;
+
+/// Number of headings kept for smoothing
+const MAX_HEADING_HISTORY: usize = 16;
 
 #[derive(Debug, Default)]
 pub struct TacviewObject {
+    pub id: u64,
     pub coords: Coords,
     pub ty: HashSet<Tag>,
     pub name: Option<String>,
     pub pilot: Option<String>,
     pub coalition: Option<String>,
+    /// True airspeed in meters per second
+    pub tas: Option<f64>,
+    pub mach: Option<f64>,
+    pub created_at: Option<Instant>,
+    /// Simulation time when the object was created, in seconds
+    pub created_sim_time: Option<f64>,
+    pub last_updated: Option<Instant>,
+    /// When the position was last updated
+    pub position_updated: Option<Instant>,
+    /// Simulation time when the position was last updated, in seconds
+    pub position_sim_time: Option<f64>,
+    /// Ground speed in knots, derived from successive positions
+    pub ground_speed: Option<f64>,
+    /// Ground track in degrees, derived from successive positions
+    pub ground_track: Option<f64>,
+    /// Most recent headings, newest last
+    pub heading_history: VecDeque<f64>,
+}
+
+/// Case folded callsign without whitespace, dashes, or diacritics, so "Söldner 1-1" matches "soldner 11"
+/// and "СОКОЛ 1" matches "сокол1".
+/// DCS pilot names may carry non-breaking or zero-width spaces, which are dropped as well.
+pub fn normalize_callsign(callsign: &str) -> String {
+    callsign
+        .nfkd()
+        .filter(|c| {
+            !is_combining_mark(*c)
+                && !c.is_whitespace()
+                && !matches!(c, '-' | '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}')
+        })
+        .default_case_fold()
+        .collect()
+}
+
+/// Applies a `T` record to the tracked coordinates.
+/// Tacview only sends the fields that changed, and some feeds omit fields like altitude on some updates,
+/// so fields missing from the record keep their last known value.
+fn merge_coords(coords: &mut Coords, update: &Coords) {
+    let previous = coords.clone();
+    coords.update(update);
+    coords.longitude = update.longitude.or(previous.longitude);
+    coords.latitude = update.latitude.or(previous.latitude);
+    coords.altitude = update.altitude.or(previous.altitude);
+    coords.roll = update.roll.or(previous.roll);
+    coords.pitch = update.pitch.or(previous.pitch);
+    coords.yaw = update.yaw.or(previous.yaw);
+    coords.u = update.u.or(previous.u);
+    coords.v = update.v.or(previous.v);
+    coords.heading = update.heading.or(previous.heading);
+}
+
+/// Objects slower than this many knots are not moving, and may not receive position updates
+const STATIONARY_MAX_GROUND_SPEED_KNOTS: f64 = 30.;
+
+impl TacviewObject {
+    /// How long ago the position of a moving object was last updated, or `None` if not moving
+    pub fn get_position_age(&self) -> Option<Duration> {
+        if self.ground_speed? < STATIONARY_MAX_GROUND_SPEED_KNOTS {
+            return None;
+        }
+        Some(self.position_updated?.elapsed())
+    }
+
+    /// Updates the position, deriving ground speed and track from the previous one.
+    /// Coordinates are relative to `reference_latitude`, and `simulation_time` is the time of the frame.
+    /// Speeds above `max_speed_knots` are glitches, and keep the last plausible speed and track.
+    fn update_position(
+        &mut self,
+        coords: &Coords,
+        reference_latitude: Option<f64>,
+        simulation_time: Option<f64>,
+        max_speed_knots: f64,
+    ) {
+        let previous = (
+            self.coords.latitude,
+            self.coords.longitude,
+            self.position_sim_time,
+        );
+        merge_coords(&mut self.coords, coords);
+
+        if let Some(heading) = coords.heading {
+            if self.heading_history.len() >= MAX_HEADING_HISTORY {
+                self.heading_history.pop_front();
+            }
+            self.heading_history.push_back(heading);
+        }
+
+        if let (
+            Some(lat1),
+            Some(lon1),
+            Some(previous_sim_time),
+            Some(lat2),
+            Some(lon2),
+            Some(reference_latitude),
+            Some(sim_time),
+        ) = (
+            previous.0,
+            previous.1,
+            previous.2,
+            self.coords.latitude,
+            self.coords.longitude,
+            reference_latitude,
+            simulation_time,
+        ) {
+            let elapsed = sim_time - previous_sim_time;
+            if elapsed > 0.1 {
+                // Flat earth approximation, fine for the distances between two updates
+                let dy = (lat2 - lat1) * 60.;
+                let latitude = reference_latitude + (lat1 + lat2) / 2.;
+                let dx = (lon2 - lon1) * 60. * latitude.to_radians().cos();
+                let ground_speed = dx.hypot(dy) / elapsed * 3600.;
+                if !ludicrous_speed_filter::is_velocity_plausible(ground_speed, max_speed_knots) {
+                    // A glitched position, keep the last plausible speed and track
+                    tracing::debug!(
+                        id = self.id,
+                        ground_speed,
+                        "discarded implausible ground speed"
+                    );
+                } else {
+                    self.ground_speed = Some(ground_speed);
+                    if dx != 0. || dy != 0. {
+                        self.ground_track = Some((dx.atan2(dy).to_degrees() + 360.) % 360.);
+                    }
+                }
+            }
+        }
+        self.position_updated = Some(Instant::now());
+        self.position_sim_time = simulation_time;
+    }
+
+    /// Circular mean of the last `window` headings, so a maneuvering object doesn't flicker between aspects
+    pub fn smoothed_heading(&self, window: usize) -> Option<f64> {
+        if window <= 1 || self.heading_history.is_empty() {
+            return self.coords.heading;
+        }
+        let (sin, cos) =
+            self.heading_history
+                .iter()
+                .rev()
+                .take(window)
+                .fold((0., 0.), |(sin, cos), heading| {
+                    (
+                        sin + heading.to_radians().sin(),
+                        cos + heading.to_radians().cos(),
+                    )
+                });
+        Some((sin.atan2(cos).to_degrees() + 360.) % 360.)
+    }
+
+    fn is_in_coalition(&self, coalition: Option<&str>) -> bool {
+        coalition.is_none() || self.coalition.as_deref() == coalition
+    }
+}
+
+/// Weather from global properties.
+/// These are not part of the ACMI specification, so they are only present if the exporter adds them.
+#[derive(Debug, Default)]
+pub struct Weather {
+    /// Direction the wind blows from in degrees, from `WindDirection`
+    pub wind_direction: Option<f64>,
+    /// Wind speed in meters per second, from `WindSpeed`
+    pub wind_speed: Option<f64>,
+    /// Sea level pressure in hectopascals, from `QNH`
+    pub qnh: Option<f64>,
 }
 
 #[derive(Debug, Default)]
 pub struct TacviewState {
     pub reference_longitude: Option<f64>,
     pub reference_latitude: Option<f64>,
+    pub weather: Weather,
+    /// Time of the last frame in seconds since the reference time
+    pub simulation_time: Option<f64>,
+    /// Reference time in seconds since midnight UTC
+    pub reference_time_of_day: Option<f64>,
     pub objects: BTreeMap<u64, TacviewObject>,
+    /// Positioned objects by location, kept in sync with `objects` if enabled
+    spatial_index: Option<SpatialIndex>,
+}
+
+/// Object counts of the airspace, computed in a single pass
+#[derive(Debug, Clone, Default)]
+pub struct TacviewStatistics {
+    pub total_objects: usize,
+    pub air_objects_by_coalition: HashMap<String, usize>,
+    pub ground_objects_by_coalition: HashMap<String, usize>,
+    pub naval_objects_by_coalition: HashMap<String, usize>,
 }
 
 impl TacviewState {
+    pub fn statistics(&self) -> TacviewStatistics {
+        let mut statistics = TacviewStatistics {
+            total_objects: self.objects.len(),
+            ..Default::default()
+        };
+        for object in self.objects.values() {
+            let counts = if object.ty.contains(&Tag::Air) {
+                &mut statistics.air_objects_by_coalition
+            } else if object.ty.contains(&Tag::Ground) {
+                &mut statistics.ground_objects_by_coalition
+            } else if object.ty.contains(&Tag::Sea) {
+                &mut statistics.naval_objects_by_coalition
+            } else {
+                continue;
+            };
+            let coalition = object.coalition.clone().unwrap_or_default();
+            *counts.entry(coalition).or_default() += 1;
+        }
+        statistics
+    }
+
+    /// Removes objects that never received a position within `timeout` of their creation.
+    /// Returns the number of removed objects.
+    pub fn compact(&mut self, timeout: Duration) -> usize {
+        let before = self.objects.len();
+        self.objects.retain(|_, object| {
+            let retain = object.coords.latitude.is_some()
+                || object.coords.longitude.is_some()
+                || object
+                    .created_at
+                    .map(|created_at| created_at.elapsed() < timeout)
+                    .unwrap_or(false);
+            if !retain {
+                if let Some(spatial_index) = &mut self.spatial_index {
+                    spatial_index.remove(object.id);
+                }
+            }
+            retain
+        });
+        before - self.objects.len()
+    }
+
+    pub fn remove_object(&mut self, id: u64) -> Option<TacviewObject> {
+        if let Some(spatial_index) = &mut self.spatial_index {
+            spatial_index.remove(id);
+        }
+        self.objects.remove(&id)
+    }
+
+    pub fn clear_objects(&mut self) {
+        if let Some(spatial_index) = &mut self.spatial_index {
+            spatial_index.clear();
+        }
+        self.objects.clear();
+    }
+
+    /// Inserts the object as is, indexing its position
+    #[cfg(test)]
+    pub(crate) fn insert_object(&mut self, object: TacviewObject) {
+        let id = object.id;
+        self.objects.insert(id, object);
+        self.reindex_object(id);
+    }
+
+    /// Updates the spatial index after the position of an object changed
+    fn reindex_object(&mut self, id: u64) {
+        let Some(spatial_index) = &mut self.spatial_index else {
+            return;
+        };
+        match self
+            .objects
+            .get(&id)
+            .and_then(|object| Some((object.coords.latitude?, object.coords.longitude?)))
+        {
+            Some((lat, lon)) => spatial_index.update(id, lat, lon),
+            None => spatial_index.remove(id),
+        }
+    }
+
+    /// Makes room for a new object when `max_objects` objects are tracked, by removing the least recently
+    /// updated aircraft or weapon. Tacview only sends changes, so other objects like bullseyes or air defenses
+    /// are never removed, as they may never be sent again. Neither are recently active objects,
+    /// so the cap may be exceeded. Returns the removed object.
+    pub fn evict_least_recently_updated(&mut self, max_objects: usize) -> Option<TacviewObject> {
+        if self.objects.len() < max_objects {
+            return None;
+        }
+        let id = self
+            .objects
+            .values()
+            .filter(|object| object.ty.contains(&Tag::Air) || object.ty.contains(&Tag::Weapon))
+            .filter(|object| {
+                object
+                    .last_updated
+                    .is_none_or(|last_updated| last_updated.elapsed() >= RECENTLY_ACTIVE)
+            })
+            .min_by_key(|object| object.last_updated)?
+            .id;
+        self.remove_object(id)
+    }
+
+    /// Absolute latitude and longitude of the object
+    pub fn get_latlng(&self, object: &TacviewObject) -> Option<(f64, f64)> {
+        Some((
+            self.reference_latitude? + object.coords.latitude?,
+            self.reference_longitude? + object.coords.longitude?,
+        ))
+    }
+
+    /// Absolute latitude and longitude of the object, extrapolated from its ground speed and
+    /// track to the current time. Extrapolation is capped at `max_extrapolation`.
+    pub fn get_extrapolated_latlng(
+        &self,
+        object: &TacviewObject,
+        max_extrapolation: Duration,
+    ) -> Option<(f64, f64)> {
+        let (lat, lon) = self.get_latlng(object)?;
+        let (Some(position_updated), Some(ground_speed), Some(ground_track)) = (
+            object.position_updated,
+            object.ground_speed,
+            object.ground_track,
+        ) else {
+            return Some((lat, lon));
+        };
+        let elapsed = position_updated.elapsed().min(max_extrapolation);
+        let distance = ground_speed * elapsed.as_secs_f64() / 3600.;
+        let track = ground_track.to_radians();
+        Some((
+            lat + distance * track.cos() / 60.,
+            lon + distance * track.sin() / (60. * lat.to_radians().cos()),
+        ))
+    }
+
+    /// `coalition` of `None` matches objects of any coalition
     pub fn find_air_object_by_callsign(
         &self,
         callsign: &str,
-        coalition: &str,
+        coalition: Option<&str>,
     ) -> Option<&TacviewObject> {
-        self.objects.values().find(|object| {
-            object.ty.contains(&Tag::Air)
-                && object.coalition.as_deref() == Some(coalition)
-                && object
-                    .pilot
-                    .as_ref()
-                    .map(|pilot| {
-                        pilot
-                            .trim()
-                            .to_lowercase()
-                            .replace(['-', ' '], "")
-                            .contains(&callsign.trim().to_lowercase().replace(['-', ' '], ""))
-                    })
-                    .unwrap_or(false)
-        })
+        let callsign = normalize_callsign(callsign);
+
+        // Prefer exact matches over partial ones, then the most recently updated
+        self.objects
+            .values()
+            .filter(|object| object.ty.contains(&Tag::Air) && object.is_in_coalition(coalition))
+            .filter_map(|object| {
+                let pilot = normalize_callsign(object.pilot.as_deref()?);
+                if !pilot.contains(&callsign) {
+                    return None;
+                }
+                let exact = pilot == callsign || pilot.split('|').any(|part| part == callsign);
+                Some((object, exact))
+            })
+            .max_by_key(|(object, exact)| (*exact, object.last_updated))
+            .map(|(object, _)| object)
+    }
+
+    /// Mission time in seconds since midnight UTC, if Tacview sent the reference time
+    pub fn mission_time_of_day(&self) -> Option<f64> {
+        Some((self.reference_time_of_day? + self.simulation_time.unwrap_or_default()) % 86400.)
+    }
+
+    /// Aircraft of the controller itself, by pilot or by name
+    pub fn find_own_aircraft(&self, callsign: &str) -> Option<&TacviewObject> {
+        self.find_air_object_by_callsign(callsign, None)
+            .or_else(|| {
+                self.objects.values().find(|object| {
+                    object.ty.contains(&Tag::Air)
+                        && object
+                            .name
+                            .as_deref()
+                            .is_some_and(|name| name.eq_ignore_ascii_case(callsign.trim()))
+                })
+            })
     }
 
     pub fn list_air_object_by_coalition<'a>(
         &'a self,
-        coalition: &'a str,
+        coalition: Option<&'a str>,
     ) -> impl Iterator<Item = &TacviewObject> + 'a {
-        self.objects.values().filter(|object| {
-            object.ty.contains(&Tag::Air) && object.coalition.as_deref() == Some(coalition)
+        self.objects.values().filter(move |object| {
+            object.ty.contains(&Tag::Air) && object.is_in_coalition(coalition)
         })
     }
 
-    pub fn list_air_callsigns_by_coalition<'a>(
+    /// Air objects within `range_nm` of the absolute position, and possibly some farther ones,
+    /// or `None` when there are too few objects for the spatial index to pay off
+    pub fn list_air_object_by_coalition_near<'a>(
         &'a self,
-        coalition: &'a str,
-    ) -> impl Iterator<Item = String> + 'a {
+        coalition: Option<&'a str>,
+        (lat, lon): (f64, f64),
+        range_nm: f64,
+    ) -> Option<impl Iterator<Item = &'a TacviewObject> + 'a> {
+        let spatial_index = self.spatial_index.as_ref()?;
+        if self.objects.len() < SPATIAL_INDEX_MIN_OBJECTS {
+            return None;
+        }
+        let relative = (
+            lat - self.reference_latitude?,
+            lon - self.reference_longitude?,
+        );
+        let lat_span_deg = range_nm / 60.;
+        let lon_span_deg = (range_nm / (60. * lat.to_radians().cos().max(0.01))).min(360.);
+        Some(
+            spatial_index
+                .query(relative, lat_span_deg, lon_span_deg)
+                .filter_map(|id| self.objects.get(&id))
+                .filter(move |object| {
+                    object.ty.contains(&Tag::Air) && object.is_in_coalition(coalition)
+                }),
+        )
+    }
+
+    pub fn find_bullseye(&self, coalition: Option<&str>) -> Option<&TacviewObject> {
         self.objects
             .values()
-            .filter(|object| {
-                object.ty.contains(&Tag::Air) && object.coalition.as_deref() == Some(coalition)
-            })
+            .find(|object| object.ty.contains(&Tag::Bullseye) && object.is_in_coalition(coalition))
+    }
+
+    /// Whether there is any air object other than the given one, regardless of coalition
+    pub fn has_other_air_objects(&self, object: &TacviewObject) -> bool {
+        self.list_air_object_by_coalition(None)
+            .any(|other| other.id != object.id)
+    }
+
+    pub fn list_air_callsigns_by_coalition<'a>(
+        &'a self,
+        coalition: Option<&'a str>,
+    ) -> impl Iterator<Item = String> + 'a {
+        self.list_air_object_by_coalition(coalition)
             .filter_map(|object| object.pilot.clone())
     }
 }
 
 impl TacviewState {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(spatial_index: bool) -> Self {
+        Self {
+            spatial_index: spatial_index.then(SpatialIndex::default),
+            ..Default::default()
+        }
+    }
+}
+
+/// Connects to Tacview, retrying until it succeeds or the stopper is stopped
+async fn reconnect(
+    tacview_config: &TacviewConfig,
+    stopper: &Stopper,
+) -> Option<RealTimeReader<BufStream<TcpStream>>> {
+    loop {
+        match stopper
+            .stop_future(crate::api::tacview::connect(tacview_config))
+            .await?
+        {
+            Ok(tacview_reader) => return Some(tacview_reader),
+            Err(error) => {
+                tracing::error!(
+                    error = format!("{error:#}"),
+                    "failed to reconnect to Tacview"
+                );
+                stopper
+                    .stop_future(tokio::time::sleep(RECONNECT_INTERVAL))
+                    .await?;
+            }
+        }
+    }
+}
+
+/// Seconds since midnight of an ISO 8601 time, e.g. "2011-06-02T05:00:00Z"
+fn parse_time_of_day(time: &str) -> Option<f64> {
+    let (_, time) = time.split_once('T')?;
+    let mut parts = time.trim_end_matches('Z').splitn(3, ':');
+    let hours = parts.next()?.parse::<f64>().ok()?;
+    let minutes = parts.next()?.parse::<f64>().ok()?;
+    let seconds = parts.next().unwrap_or("0").parse::<f64>().ok()?;
+    Some(hours * 3600. + minutes * 60. + seconds)
+}
+
+/// Removes the objects of a Destroyed event, recording them in the timeline.
+/// Objects are referenced by their hexadecimal ID, e.g. `0,Event=Destroyed|11ff|`.
+fn remove_destroyed_objects(
+    state: &mut TacviewState,
+    params: &[String],
+    timeline: &TimelineRecorder,
+) {
+    for id in params
+        .iter()
+        .filter_map(|id| u64::from_str_radix(id, 16).ok())
+    {
+        if let Some(object) = state.remove_object(id) {
+            let name = object.pilot.as_deref().or(object.name.as_deref());
+            timeline.record(MissionEvent::object_destroyed(id, name));
+        }
     }
 }
 
 pub async fn state_loop(
+    tacview_config: TacviewConfig,
     mut tacview_reader: RealTimeReader<BufStream<TcpStream>>,
     state: Arc<RwLock<TacviewState>>,
+    heartbeat: Heartbeat,
+    hung: Arc<Notify>,
+    timeline: TimelineRecorder,
     stopper: Stopper,
 ) {
     loop {
-        match stopper.stop_future(tacview_reader.next()).await {
+        let res = tokio::select! {
+            res = stopper.stop_future(tacview_reader.next()) => res,
+            _ = hung.notified() => {
+                // Replacing the reader drops the hung connection
+                match reconnect(&tacview_config, &stopper).await {
+                    Some(new_reader) => {
+                        tacview_reader = new_reader;
+                        heartbeat.beat();
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+        };
+        if let Some(Ok(_)) = &res {
+            heartbeat.beat();
+        }
+        match res {
             Some(Ok(record)) => match record {
                 Record::Remove(id) => {
                     let mut state = state.write().await;
-                    state.objects.remove(&id);
+                    if let Some(pilot) = state.remove_object(id).and_then(|object| object.pilot) {
+                        timeline.record(MissionEvent::pilot_checked_out(&pilot));
+                    }
                 }
-                Record::Frame(_) => {
-                    // Do nothing
+                Record::Frame(time) => {
+                    let mut state = state.write().await;
+                    state.simulation_time = Some(time);
+                }
+                Record::Event(event) if matches!(event.kind, EventKind::Destroyed) => {
+                    let mut state = state.write().await;
+                    remove_destroyed_objects(&mut state, &event.params, &timeline);
                 }
                 Record::Event(_) => {
-                    // Do nothing
+                    // Other events don't change the state
                 }
                 Record::GlobalProperties(global_properties) => {
                     for global_property in global_properties {
@@ -109,14 +593,30 @@ pub async fn state_loop(
                                 state.reference_latitude = Some(lat);
 
                                 // When ReferenceLatitude occured, assume new connection was made, so clear the objects.
-                                state.objects.clear();
+                                state.clear_objects();
                             }
                             GlobalProperty::ReferenceLongitude(lng) => {
                                 let mut state = state.write().await;
                                 state.reference_longitude = Some(lng);
 
                                 // When ReferenceLongitude occured, assume new connection was made, so clear the objects.
-                                state.objects.clear();
+                                state.clear_objects();
+                            }
+                            GlobalProperty::ReferenceTime(time) => {
+                                let mut state = state.write().await;
+                                state.reference_time_of_day = parse_time_of_day(&time);
+                            }
+                            GlobalProperty::Unknown(key, value) => {
+                                let Ok(value) = value.parse::<f64>() else {
+                                    continue;
+                                };
+                                let mut state = state.write().await;
+                                match key.to_lowercase().as_str() {
+                                    "winddirection" => state.weather.wind_direction = Some(value),
+                                    "windspeed" => state.weather.wind_speed = Some(value),
+                                    "qnh" => state.weather.qnh = Some(value),
+                                    _ => {}
+                                }
                             }
                             _ => {}
                         }
@@ -124,11 +624,66 @@ pub async fn state_loop(
                 }
                 Record::Update(id, object_properties) => {
                     let mut state = state.write().await;
-                    let object = state.objects.entry(id).or_default();
+                    if let Some(max_objects) = tacview_config.max_objects {
+                        if !state.objects.contains_key(&id) {
+                            if let Some(evicted) = state.evict_least_recently_updated(max_objects) {
+                                tracing::info!(
+                                    id = evicted.id,
+                                    name = ?evicted.name,
+                                    max_objects,
+                                    "too many objects, evicted the least recently updated one"
+                                );
+                            }
+                        }
+                    }
+                    let (reference_latitude, simulation_time) =
+                        (state.reference_latitude, state.simulation_time);
+                    let new_object = TacviewObject {
+                        id,
+                        created_at: Some(Instant::now()),
+                        created_sim_time: simulation_time,
+                        ..Default::default()
+                    };
+                    let coalition = object_properties
+                        .iter()
+                        .find_map(|property| match property {
+                            ObjectProperty::Coalition(coalition) => Some(coalition),
+                            _ => None,
+                        });
+                    let object = match state.objects.entry(id) {
+                        Entry::Occupied(entry) => {
+                            let object = entry.into_mut();
+                            // Tacview reuses IDs of removed objects, and a reused ID often
+                            // belongs to an object of the other coalition
+                            if let (Some(previous), Some(coalition)) =
+                                (&object.coalition, coalition)
+                            {
+                                if previous != coalition {
+                                    tracing::warn!(
+                                        id,
+                                        %previous,
+                                        %coalition,
+                                        previous_created_sim_time = ?object.created_sim_time,
+                                        "object ID reused with different coalition, resetting state"
+                                    );
+                                    *object = new_object;
+                                }
+                            }
+                            object
+                        }
+                        Entry::Vacant(entry) => entry.insert(new_object),
+                    };
+                    object.last_updated = Some(Instant::now());
+                    let had_pilot = object.pilot.is_some();
                     for object_property in object_properties {
                         match object_property {
                             ObjectProperty::T(coords) => {
-                                object.coords.update(&coords);
+                                object.update_position(
+                                    &coords,
+                                    reference_latitude,
+                                    simulation_time,
+                                    tacview_config.max_plausible_speed_knots,
+                                );
                             }
                             ObjectProperty::Type(ty) => {
                                 object.ty = ty;
@@ -142,16 +697,556 @@ pub async fn state_loop(
                             ObjectProperty::Coalition(coalition) => {
                                 object.coalition = Some(coalition);
                             }
+                            ObjectProperty::TAS(tas) => {
+                                object.tas = Some(tas);
+                            }
+                            ObjectProperty::Mach(mach) => {
+                                object.mach = Some(mach);
+                            }
                             _ => {}
                         }
                     }
+                    if let (false, Some(pilot)) = (had_pilot, &object.pilot) {
+                        timeline.record(MissionEvent::pilot_checked_in(
+                            pilot,
+                            object.coalition.as_deref(),
+                        ));
+                    }
+                    state.reindex_object(id);
                 }
             },
             Some(Err(error)) => {
-                tracing::error!(%error, "Tacview realtime telemetry client read error");
+                tracing::error!(
+                    error = format!("{error:#}"),
+                    "Tacview realtime telemetry client read error"
+                );
             }
             None => break,
         }
     }
     tracing::info!("exiting state loop");
 }
+
+pub async fn housekeeping_loop(
+    tacview_config: TacviewConfig,
+    state: Arc<RwLock<TacviewState>>,
+    stopper: Stopper,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    while stopper.stop_future(interval.tick()).await.is_some() {
+        let mut state = state.write().await;
+
+        let removed = state.compact(Duration::from_secs(
+            tacview_config.incomplete_object_timeout_secs,
+        ));
+        if removed > 0 {
+            tracing::debug!(removed, "removed incomplete objects");
+        }
+    }
+    tracing::info!("exiting housekeeping loop");
+}
+
+/// Checks once, shortly after Tacview connects, that the configured coalition matches how Tacview tags the bot's own aircraft
+pub async fn check_coalition(
+    common_config: CommonConfig,
+    state: Arc<RwLock<TacviewState>>,
+    stopper: Stopper,
+) {
+    if common_config.open_mode {
+        return;
+    }
+    if stopper
+        .stop_future(tokio::time::sleep(Duration::from_secs(10)))
+        .await
+        .is_none()
+    {
+        return;
+    }
+
+    let state = state.read().await;
+    let expected = common_config.coalition.as_tacview_coalition();
+    if let Some(object) = state.find_air_object_by_callsign(&common_config.callsign, None) {
+        match object.coalition.as_deref() {
+            Some(actual) if actual != expected => {
+                tracing::warn!(
+                    "Coalition mismatch detected: config says {} but your aircraft is tagged {} in Tacview. Check coalition configuration.",
+                    expected,
+                    actual
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn air_object(id: u64, pilot: &str, coalition: &str) -> TacviewObject {
+        TacviewObject {
+            id,
+            ty: HashSet::from([Tag::Air]),
+            pilot: Some(pilot.to_string()),
+            coalition: Some(coalition.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn state_with(objects: impl IntoIterator<Item = TacviewObject>) -> TacviewState {
+        let mut state = TacviewState::new(false);
+        for object in objects {
+            state.objects.insert(object.id, object);
+        }
+        state
+    }
+
+    #[test]
+    fn exact_callsign_match_is_preferred() {
+        let now = Instant::now();
+        let state = state_with([
+            TacviewObject {
+                last_updated: Some(now),
+                ..air_object(1, "Chevy 1-12", "Enemies")
+            },
+            TacviewObject {
+                last_updated: Some(now - Duration::from_secs(10)),
+                ..air_object(2, "Chevy 1-1", "Enemies")
+            },
+        ]);
+        let found = state.find_air_object_by_callsign("chevy 11", Some("Enemies"));
+        assert_eq!(found.map(|object| object.id), Some(2));
+    }
+
+    #[test]
+    fn most_recently_updated_match_is_preferred() {
+        let now = Instant::now();
+        let state = state_with([
+            TacviewObject {
+                last_updated: Some(now - Duration::from_secs(10)),
+                ..air_object(1, "Chevy 1-1 | Alpha", "Enemies")
+            },
+            TacviewObject {
+                last_updated: Some(now),
+                ..air_object(2, "Chevy 1-1 | Bravo", "Enemies")
+            },
+        ]);
+        let found = state.find_air_object_by_callsign("Chevy 1-1", Some("Enemies"));
+        assert_eq!(found.map(|object| object.id), Some(2));
+    }
+
+    #[test]
+    fn callsign_match_respects_coalition_and_type() {
+        let state = state_with([
+            air_object(1, "Chevy 1-1", "Allies"),
+            TacviewObject {
+                ty: HashSet::from([Tag::Ground]),
+                ..air_object(2, "Chevy 1-1", "Enemies")
+            },
+        ]);
+        assert!(state
+            .find_air_object_by_callsign("Chevy 1-1", Some("Enemies"))
+            .is_none());
+        assert_eq!(
+            state
+                .find_air_object_by_callsign("Chevy 1-1", None)
+                .map(|object| object.id),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn normalized_callsigns_drop_diacritics() {
+        assert_eq!(normalize_callsign(" Söldner 1-1 "), "soldner11");
+        assert_eq!(normalize_callsign("Ñandú 2-1"), "nandu21");
+        assert_eq!(normalize_callsign("Crème Brûlée"), "cremebrulee");
+        // Compatibility characters decompose too
+        assert_eq!(normalize_callsign("ﬁghter 1"), "fighter1");
+    }
+
+    #[test]
+    fn normalized_callsigns_keep_non_latin_scripts() {
+        // Cyrillic is case folded, and "ё" is written as "е" as often as not
+        assert_eq!(normalize_callsign("СОКОЛ 1-1"), "сокол11");
+        assert_eq!(normalize_callsign("Ёлка 2-1"), "елка21");
+        // Arabic has no case, and its vowel marks are optional
+        assert_eq!(normalize_callsign("صقر 1-1"), "صقر11");
+        assert_eq!(normalize_callsign("صَقْر 1-1"), "صقر11");
+        // Chinese has no case, and full width digits, dashes, and spaces are the ASCII ones
+        assert_eq!(normalize_callsign("猎鹰 1-1"), "猎鹰11");
+        assert_eq!(normalize_callsign("猎鹰\u{3000}１－１"), "猎鹰11");
+    }
+
+    #[test]
+    fn callsign_match_works_across_scripts() {
+        let state = state_with([
+            air_object(1, "Сокол 1-1", "Enemies"),
+            air_object(2, "صقر 1-1", "Enemies"),
+            air_object(3, "猎鹰 1-1", "Enemies"),
+        ]);
+        for (callsign, id) in [("СОКОЛ 11", 1), ("صقر 11", 2), ("猎鹰１－１", 3)] {
+            assert_eq!(
+                state
+                    .find_air_object_by_callsign(callsign, Some("Enemies"))
+                    .map(|object| object.id),
+                Some(id),
+                "{callsign}"
+            );
+        }
+    }
+
+    #[test]
+    fn callsign_match_ignores_diacritics() {
+        let state = state_with([air_object(1, "Söldner 1-1", "Enemies")]);
+        assert_eq!(
+            state
+                .find_air_object_by_callsign("soldner 11", Some("Enemies"))
+                .map(|object| object.id),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn least_recently_updated_aircraft_is_evicted() {
+        let now = Instant::now();
+        let updated = |id: u64, ago_secs: u64| TacviewObject {
+            last_updated: Some(now - Duration::from_secs(ago_secs)),
+            ..air_object(id, "Chevy 1-1", "Enemies")
+        };
+        let mut state = state_with([updated(1, 60), updated(2, 120), updated(3, 90)]);
+        assert!(state.evict_least_recently_updated(4).is_none());
+        assert_eq!(
+            state
+                .evict_least_recently_updated(3)
+                .map(|object| object.id),
+            Some(2)
+        );
+        assert_eq!(
+            state
+                .evict_least_recently_updated(2)
+                .map(|object| object.id),
+            Some(3)
+        );
+        assert_eq!(state.objects.keys().copied().collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn recently_active_objects_are_not_evicted() {
+        let now = Instant::now();
+        let mut state = state_with([
+            TacviewObject {
+                last_updated: Some(now - Duration::from_secs(5)),
+                ..air_object(1, "Chevy 1-1", "Enemies")
+            },
+            TacviewObject {
+                last_updated: Some(now - Duration::from_secs(29)),
+                ..air_object(2, "Chevy 1-2", "Enemies")
+            },
+        ]);
+        assert!(state.evict_least_recently_updated(1).is_none());
+        assert_eq!(state.objects.len(), 2);
+    }
+
+    #[test]
+    fn objects_other_than_aircraft_and_weapons_are_not_evicted() {
+        let long_ago = Some(Instant::now() - Duration::from_secs(3600));
+        let mut state = state_with([
+            TacviewObject {
+                ty: HashSet::from([Tag::Navaid, Tag::Bullseye]),
+                last_updated: long_ago,
+                ..air_object(1, "", "Enemies")
+            },
+            TacviewObject {
+                ty: HashSet::from([Tag::Ground, Tag::AntiAircraft]),
+                last_updated: long_ago,
+                ..air_object(2, "", "Allies")
+            },
+            TacviewObject {
+                ty: HashSet::from([Tag::Weapon, Tag::Missile]),
+                last_updated: Some(Instant::now() - Duration::from_secs(60)),
+                ..air_object(3, "", "Allies")
+            },
+        ]);
+        assert_eq!(
+            state
+                .evict_least_recently_updated(3)
+                .map(|object| object.id),
+            Some(3)
+        );
+        assert!(state.evict_least_recently_updated(2).is_none());
+        assert_eq!(state.objects.len(), 2);
+    }
+
+    fn coords(latitude: f64, longitude: f64) -> Coords {
+        Coords {
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ground_speed_accounts_for_the_reference_latitude() {
+        let mut object = air_object(1, "Chevy 1-1", "Enemies");
+        object.update_position(&coords(0., 0.), Some(60.), Some(100.), 4000.);
+        // Half a nautical mile east at 60 degrees north in 10 seconds
+        object.update_position(&coords(0., 1. / 60.), Some(60.), Some(110.), 4000.);
+        let ground_speed = object.ground_speed.unwrap();
+        assert!((ground_speed - 180.).abs() < 1., "{ground_speed}");
+        let ground_track = object.ground_track.unwrap();
+        assert!((ground_track - 90.).abs() < 0.1, "{ground_track}");
+    }
+
+    #[test]
+    fn ground_speed_is_timed_by_simulation_time() {
+        let mut object = air_object(1, "Chevy 1-1", "Enemies");
+        object.update_position(&coords(0., 0.), Some(0.), Some(100.), 4000.);
+        // One nautical mile north in 10 seconds of simulation time, however fast the frames arrive
+        object.update_position(&coords(1. / 60., 0.), Some(0.), Some(110.), 4000.);
+        let ground_speed = object.ground_speed.unwrap();
+        assert!((ground_speed - 360.).abs() < 0.1, "{ground_speed}");
+
+        // Updates within the same frame don't derive a speed
+        object.update_position(&coords(2. / 60., 0.), Some(0.), Some(110.), 4000.);
+        assert_eq!(object.ground_speed, Some(ground_speed));
+    }
+
+    #[test]
+    fn teleporting_keeps_the_last_plausible_speed_and_track() {
+        let mut object = air_object(1, "Chevy 1-1", "Enemies");
+        object.update_position(&coords(0., 0.), Some(0.), Some(100.), 4000.);
+        // One nautical mile north in 10 seconds
+        object.update_position(&coords(1. / 60., 0.), Some(0.), Some(110.), 4000.);
+        let (ground_speed, ground_track) = (object.ground_speed, object.ground_track);
+
+        // Five degrees east in a single second
+        object.update_position(&coords(1. / 60., 5.), Some(0.), Some(111.), 4000.);
+        assert_eq!(object.ground_speed, ground_speed);
+        assert_eq!(object.ground_track, ground_track);
+        // The glitched position itself is still taken
+        assert_eq!(object.coords.longitude, Some(5.));
+    }
+
+    #[test]
+    fn fast_aircraft_are_not_taken_for_teleporting() {
+        let mut object = air_object(1, "Chevy 1-1", "Enemies");
+        object.update_position(&coords(0., 0.), Some(0.), Some(100.), 4000.);
+        // Five nautical miles east in 10 seconds, 1800 knots
+        object.update_position(&coords(0., 5. / 60.), Some(0.), Some(110.), 4000.);
+        let ground_speed = object.ground_speed.unwrap();
+        assert!((ground_speed - 1800.).abs() < 1., "{ground_speed}");
+
+        // Unless above the configured limit
+        let mut object = air_object(2, "Chevy 1-2", "Enemies");
+        object.update_position(&coords(0., 0.), Some(0.), Some(100.), 1500.);
+        object.update_position(&coords(0., 5. / 60.), Some(0.), Some(110.), 1500.);
+        assert_eq!(object.ground_speed, None);
+    }
+
+    #[test]
+    fn position_is_extrapolated_from_ground_speed_and_track() {
+        let mut state = TacviewState {
+            reference_latitude: Some(0.),
+            reference_longitude: Some(0.),
+            ..Default::default()
+        };
+        let object = TacviewObject {
+            coords: coords(0., 0.),
+            ground_speed: Some(360.),
+            ground_track: Some(0.),
+            position_updated: Some(Instant::now() - Duration::from_secs(2)),
+            ..air_object(1, "Chevy 1-1", "Enemies")
+        };
+        state.objects.insert(object.id, object);
+        let object = &state.objects[&1];
+
+        assert_eq!(state.get_latlng(object), Some((0., 0.)));
+        // 0.2 nautical miles north in 2 seconds
+        let (lat, lon) = state
+            .get_extrapolated_latlng(object, Duration::from_secs(5))
+            .unwrap();
+        assert!((lat * 60. - 0.2).abs() < 0.01, "{lat}");
+        assert!(lon.abs() < 1e-9, "{lon}");
+        // Capped at the maximum extrapolation
+        let (lat, _) = state
+            .get_extrapolated_latlng(object, Duration::from_secs(1))
+            .unwrap();
+        assert!((lat * 60. - 0.1).abs() < 0.001, "{lat}");
+    }
+
+    #[test]
+    fn position_update_without_altitude_keeps_the_last_known_altitude() {
+        let mut object = air_object(1, "Chevy 1-1", "Enemies");
+        object.update_position(
+            &Coords {
+                altitude: Some(6000.),
+                heading: Some(90.),
+                ..coords(0., 0.)
+            },
+            Some(0.),
+            Some(100.),
+            4000.,
+        );
+        object.update_position(&coords(0., 1. / 60.), Some(0.), Some(110.), 4000.);
+        assert_eq!(object.coords.longitude, Some(1. / 60.));
+        assert_eq!(object.coords.altitude, Some(6000.));
+        assert_eq!(object.coords.heading, Some(90.));
+    }
+
+    fn coords_fields(coords: &Coords) -> [Option<f64>; 9] {
+        [
+            coords.longitude,
+            coords.latitude,
+            coords.altitude,
+            coords.roll,
+            coords.pitch,
+            coords.yaw,
+            coords.u,
+            coords.v,
+            coords.heading,
+        ]
+    }
+
+    #[test]
+    fn merged_coords_keep_every_field_missing_from_the_update() {
+        let mut merged = Coords {
+            longitude: Some(1.),
+            latitude: Some(2.),
+            altitude: Some(3.),
+            roll: Some(4.),
+            pitch: Some(5.),
+            yaw: Some(6.),
+            u: Some(7.),
+            v: Some(8.),
+            heading: Some(9.),
+        };
+        let previous = merged.clone();
+        merge_coords(&mut merged, &Coords::default());
+        assert_eq!(coords_fields(&merged), coords_fields(&previous));
+
+        merge_coords(
+            &mut merged,
+            &Coords {
+                altitude: Some(30.),
+                yaw: Some(60.),
+                heading: Some(90.),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            coords_fields(&merged),
+            [
+                Some(1.),
+                Some(2.),
+                Some(30.),
+                Some(4.),
+                Some(5.),
+                Some(60.),
+                Some(7.),
+                Some(8.),
+                Some(90.)
+            ]
+        );
+    }
+
+    fn indexed_state_with(objects: impl IntoIterator<Item = TacviewObject>) -> TacviewState {
+        let mut state = TacviewState::new(true);
+        for object in objects {
+            state.insert_object(object);
+        }
+        state
+    }
+
+    fn positioned_object(id: u64, latitude: f64, longitude: f64) -> TacviewObject {
+        TacviewObject {
+            coords: coords(latitude, longitude),
+            ..air_object(id, "Chevy 1-1", "Enemies")
+        }
+    }
+
+    /// IDs indexed in the cells around the relative position
+    fn indexed_ids(state: &TacviewState, position: (f64, f64)) -> Vec<u64> {
+        let mut ids = state
+            .spatial_index
+            .as_ref()
+            .unwrap()
+            .query(position, 0.01, 0.01)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn spatial_index_follows_position_updates() {
+        let mut state = indexed_state_with([
+            positioned_object(1, 0.1, 0.1),
+            positioned_object(2, 0.2, 0.2),
+        ]);
+        assert_eq!(indexed_ids(&state, (0.1, 0.1)), [1, 2]);
+
+        let object = state.objects.get_mut(&1).unwrap();
+        object.update_position(&coords(3.1, 3.1), None, None, 4000.);
+        state.reindex_object(1);
+        assert_eq!(indexed_ids(&state, (0.1, 0.1)), [2]);
+        assert_eq!(indexed_ids(&state, (3.1, 3.1)), [1]);
+
+        // Moving within the cell keeps it indexed there
+        let object = state.objects.get_mut(&1).unwrap();
+        object.update_position(&coords(3.2, 3.2), None, None, 4000.);
+        state.reindex_object(1);
+        assert_eq!(indexed_ids(&state, (3.1, 3.1)), [1]);
+    }
+
+    #[test]
+    fn spatial_index_forgets_removed_and_cleared_objects() {
+        let mut state = indexed_state_with([
+            positioned_object(1, 0.1, 0.1),
+            positioned_object(2, 0.2, 0.2),
+        ]);
+        assert!(state.remove_object(1).is_some());
+        assert_eq!(indexed_ids(&state, (0.1, 0.1)), [2]);
+
+        state.clear_objects();
+        assert!(indexed_ids(&state, (0.1, 0.1)).is_empty());
+
+        // Objects seen again after clearing are indexed anew
+        state.insert_object(positioned_object(2, 0.2, 0.2));
+        assert_eq!(indexed_ids(&state, (0.1, 0.1)), [2]);
+    }
+
+    #[test]
+    fn spatial_index_keeps_positioned_objects_through_compaction() {
+        let mut state = indexed_state_with([
+            positioned_object(1, 0.1, 0.1),
+            TacviewObject {
+                created_at: Some(Instant::now() - Duration::from_secs(120)),
+                ..air_object(2, "Chevy 1-2", "Enemies")
+            },
+            positioned_object(3, 5.1, 5.1),
+        ]);
+        assert_eq!(state.compact(Duration::from_secs(60)), 1);
+        assert!(!state.objects.contains_key(&2));
+        assert_eq!(indexed_ids(&state, (0.1, 0.1)), [1]);
+        assert_eq!(indexed_ids(&state, (5.1, 5.1)), [3]);
+    }
+
+    #[test]
+    fn destroyed_objects_are_removed_and_recorded() {
+        let mut state = state_with([
+            air_object(0x11ff, "Chevy 1-1", "Enemies"),
+            air_object(2, "Chevy 1-2", "Enemies"),
+        ]);
+        let timeline = TimelineRecorder::default();
+        remove_destroyed_objects(
+            &mut state,
+            &["11ff".to_string(), "not an id".to_string()],
+            &timeline,
+        );
+        assert!(!state.objects.contains_key(&0x11ff));
+        assert!(state.objects.contains_key(&2));
+        assert!(matches!(
+            timeline.events().as_slice(),
+            [MissionEvent::ObjectDestroyed { id: 0x11ff, name: Some(name), .. }] if name == "Chevy 1-1"
+        ));
+    }
+}
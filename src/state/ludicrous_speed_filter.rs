@@ -0,0 +1,7 @@
+//! Rejection of glitched tracks, where an object teleports across the map in a single frame
+
+/// Whether a speed derived from successive positions could be real.
+/// A glitched track jumps hundreds of miles in a single frame, far beyond `max_speed_knots`.
+pub fn is_velocity_plausible(speed_knots: f64, max_speed_knots: f64) -> bool {
+    speed_knots <= max_speed_knots
+}
@@ -0,0 +1,84 @@
+//! Grid over object positions, so range queries don't scan every object
+
+use std::collections::{HashMap, HashSet};
+
+/// About 30 nautical miles of latitude
+const CELL_SIZE_DEG: f64 = 0.5;
+
+type Cell = (i64, i64);
+
+fn get_cell(lat: f64, lon: f64) -> Cell {
+    (
+        (lat / CELL_SIZE_DEG).floor() as i64,
+        (lon / CELL_SIZE_DEG).floor() as i64,
+    )
+}
+
+/// Objects by grid cell of their position, relative to the Tacview reference point
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    cells: HashMap<Cell, HashSet<u64>>,
+    object_cells: HashMap<u64, Cell>,
+}
+
+impl SpatialIndex {
+    pub fn update(&mut self, id: u64, lat: f64, lon: f64) {
+        let cell = get_cell(lat, lon);
+        match self.object_cells.insert(id, cell) {
+            Some(previous) if previous == cell => return,
+            Some(previous) => self.remove_from_cell(id, previous),
+            None => {}
+        }
+        self.cells.entry(cell).or_default().insert(id);
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        if let Some(cell) = self.object_cells.remove(&id) {
+            self.remove_from_cell(id, cell);
+        }
+    }
+
+    fn remove_from_cell(&mut self, id: u64, cell: Cell) {
+        if let Some(ids) = self.cells.get_mut(&cell) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.object_cells.clear();
+    }
+
+    /// IDs of the objects in cells overlapping the given box around a position.
+    /// Objects near the corners of the box or just outside of it may be included.
+    pub fn query(
+        &self,
+        (lat, lon): (f64, f64),
+        lat_span_deg: f64,
+        lon_span_deg: f64,
+    ) -> impl Iterator<Item = u64> + '_ {
+        let (min_lat, min_lon) = get_cell(lat - lat_span_deg, lon - lon_span_deg);
+        let (max_lat, max_lon) = get_cell(lat + lat_span_deg, lon + lon_span_deg);
+        let in_box = move |(cell_lat, cell_lon): &Cell| {
+            (min_lat..=max_lat).contains(cell_lat) && (min_lon..=max_lon).contains(cell_lon)
+        };
+        // Looking up every cell of a large box is slower than filtering the occupied cells
+        let box_cell_count = (max_lat - min_lat + 1).saturating_mul(max_lon - min_lon + 1);
+        let cells: Vec<&HashSet<u64>> = if box_cell_count as usize > self.cells.len() {
+            self.cells
+                .iter()
+                .filter(|(cell, _)| in_box(cell))
+                .map(|(_, ids)| ids)
+                .collect()
+        } else {
+            (min_lat..=max_lat)
+                .flat_map(|cell_lat| (min_lon..=max_lon).map(move |cell_lon| (cell_lat, cell_lon)))
+                .filter_map(|cell| self.cells.get(&cell))
+                .collect()
+        };
+        cells.into_iter().flatten().copied()
+    }
+}
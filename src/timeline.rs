@@ -0,0 +1,322 @@
+//! Recording mission events for the debrief
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::Serialize;
+
+/// Events kept for the debrief, dropping the oldest beyond it so a long-running server doesn't grow unbounded
+const MAX_EVENTS: usize = 10000;
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Event of the mission, with the Unix time in seconds it was recorded at
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum MissionEvent {
+    PilotCheckedIn {
+        at: u64,
+        callsign: String,
+        coalition: Option<String>,
+    },
+    PilotCheckedOut {
+        at: u64,
+        callsign: String,
+    },
+    ThreatAppeared {
+        at: u64,
+        id: u64,
+        name: String,
+    },
+    ThreatDisappeared {
+        at: u64,
+        id: u64,
+        name: String,
+    },
+    BanditCommitted {
+        at: u64,
+        callsign: String,
+        bandit_id: u64,
+        bandit_name: Option<String>,
+    },
+    BanditMerged {
+        at: u64,
+        callsign: String,
+        bandit_id: u64,
+    },
+    Mayday {
+        at: u64,
+        callsign: String,
+        transcript: String,
+    },
+    ObjectDestroyed {
+        at: u64,
+        id: u64,
+        name: Option<String>,
+    },
+}
+
+impl MissionEvent {
+    pub fn pilot_checked_in(callsign: &str, coalition: Option<&str>) -> Self {
+        Self::PilotCheckedIn {
+            at: unix_secs(),
+            callsign: callsign.to_string(),
+            coalition: coalition.map(str::to_string),
+        }
+    }
+
+    pub fn pilot_checked_out(callsign: &str) -> Self {
+        Self::PilotCheckedOut {
+            at: unix_secs(),
+            callsign: callsign.to_string(),
+        }
+    }
+
+    pub fn threat_appeared(id: u64, name: &str) -> Self {
+        Self::ThreatAppeared {
+            at: unix_secs(),
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn threat_disappeared(id: u64, name: &str) -> Self {
+        Self::ThreatDisappeared {
+            at: unix_secs(),
+            id,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn bandit_committed(callsign: &str, bandit_id: u64, bandit_name: Option<&str>) -> Self {
+        Self::BanditCommitted {
+            at: unix_secs(),
+            callsign: callsign.to_string(),
+            bandit_id,
+            bandit_name: bandit_name.map(str::to_string),
+        }
+    }
+
+    pub fn bandit_merged(callsign: &str, bandit_id: u64) -> Self {
+        Self::BanditMerged {
+            at: unix_secs(),
+            callsign: callsign.to_string(),
+            bandit_id,
+        }
+    }
+
+    pub fn mayday(callsign: &str, transcript: &str) -> Self {
+        Self::Mayday {
+            at: unix_secs(),
+            callsign: callsign.to_string(),
+            transcript: transcript.to_string(),
+        }
+    }
+
+    pub fn object_destroyed(id: u64, name: Option<&str>) -> Self {
+        Self::ObjectDestroyed {
+            at: unix_secs(),
+            id,
+            name: name.map(str::to_string),
+        }
+    }
+
+    pub fn at(&self) -> u64 {
+        match self {
+            Self::PilotCheckedIn { at, .. }
+            | Self::PilotCheckedOut { at, .. }
+            | Self::ThreatAppeared { at, .. }
+            | Self::ThreatDisappeared { at, .. }
+            | Self::BanditCommitted { at, .. }
+            | Self::BanditMerged { at, .. }
+            | Self::Mayday { at, .. }
+            | Self::ObjectDestroyed { at, .. } => *at,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::PilotCheckedIn {
+                callsign,
+                coalition: Some(coalition),
+                ..
+            } => format!("{callsign} checked in ({coalition})"),
+            Self::PilotCheckedIn { callsign, .. } => format!("{callsign} checked in"),
+            Self::PilotCheckedOut { callsign, .. } => format!("{callsign} checked out"),
+            Self::ThreatAppeared { id, name, .. } => format!("threat {name} ({id:x}) appeared"),
+            Self::ThreatDisappeared { id, name, .. } => {
+                format!("threat {name} ({id:x}) off scope")
+            }
+            Self::BanditCommitted {
+                callsign,
+                bandit_id,
+                bandit_name,
+                ..
+            } => format!(
+                "{callsign} committed on {} ({bandit_id:x})",
+                bandit_name.as_deref().unwrap_or("bandit")
+            ),
+            Self::BanditMerged {
+                callsign,
+                bandit_id,
+                ..
+            } => format!("{callsign} merged with bandit ({bandit_id:x})"),
+            Self::Mayday {
+                callsign,
+                transcript,
+                ..
+            } => format!("MAYDAY from {callsign}: \"{transcript}\""),
+            Self::ObjectDestroyed { id, name, .. } => {
+                format!("{} ({id:x}) destroyed", name.as_deref().unwrap_or("object"))
+            }
+        }
+    }
+}
+
+/// Formats Unix seconds as a UTC time of day, e.g. "13:04:05Z"
+fn format_time_of_day(unix_secs: u64) -> String {
+    let secs = unix_secs % 86400;
+    format!(
+        "{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+#[derive(Default)]
+struct Timeline {
+    events: VecDeque<MissionEvent>,
+    /// Number of the oldest events dropped beyond `max_events`
+    dropped: usize,
+}
+
+/// Mission events in the order they were recorded, shared by every controller
+#[derive(Clone)]
+pub struct TimelineRecorder {
+    timeline: Arc<Mutex<Timeline>>,
+    max_events: usize,
+}
+
+impl Default for TimelineRecorder {
+    fn default() -> Self {
+        Self::with_max_events(MAX_EVENTS)
+    }
+}
+
+impl TimelineRecorder {
+    fn with_max_events(max_events: usize) -> Self {
+        Self {
+            timeline: Default::default(),
+            max_events,
+        }
+    }
+
+    pub fn record(&self, event: MissionEvent) {
+        tracing::debug!(?event, "mission event");
+        let mut timeline = self.timeline.lock().unwrap();
+        if timeline.events.len() >= self.max_events {
+            timeline.events.pop_front();
+            if timeline.dropped == 0 {
+                tracing::warn!(
+                    max_events = self.max_events,
+                    "mission timeline is full, dropping the oldest events"
+                );
+            }
+            timeline.dropped += 1;
+        }
+        timeline.events.push_back(event);
+    }
+
+    pub fn events(&self) -> Vec<MissionEvent> {
+        self.timeline
+            .lock()
+            .unwrap()
+            .events
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Markdown report for the mission debrief
+    pub fn to_markdown(&self) -> String {
+        let (events, dropped) = {
+            let timeline = self.timeline.lock().unwrap();
+            (
+                timeline.events.iter().cloned().collect::<Vec<_>>(),
+                timeline.dropped,
+            )
+        };
+        let mut report = String::from("# Mission timeline\n\n");
+        if events.is_empty() {
+            report.push_str("No events recorded.\n");
+            return report;
+        }
+        if dropped > 0 {
+            let _ = writeln!(report, "Earlier events dropped: {dropped}\n");
+        }
+        report.push_str("| Time (UTC) | Event |\n|---|---|\n");
+        for event in &events {
+            let _ = writeln!(
+                report,
+                "| {} | {} |",
+                format_time_of_day(event.at()),
+                event.describe().replace('|', "\\|")
+            );
+        }
+        report
+    }
+
+    /// Writes the timeline as JSON if the path ends with `.json`, as a Markdown report otherwise
+    pub async fn export(&self, path: &Path) -> anyhow::Result<()> {
+        let contents = if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+        {
+            serde_json::to_string_pretty(&self.events())
+                .context("failed to serialize mission timeline")?
+        } else {
+            self.to_markdown()
+        };
+        tokio::fs::write(path, contents)
+            .await
+            .with_context(|| format!("failed to write mission timeline to `{}`", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oldest_events_are_dropped_beyond_the_cap() {
+        let timeline = TimelineRecorder::with_max_events(2);
+        for callsign in ["Chevy 1-1", "Chevy 1-2", "Chevy 1-3"] {
+            timeline.record(MissionEvent::pilot_checked_out(callsign));
+        }
+        let callsigns = timeline
+            .events()
+            .into_iter()
+            .map(|event| match event {
+                MissionEvent::PilotCheckedOut { callsign, .. } => callsign,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(callsigns, ["Chevy 1-2", "Chevy 1-3"]);
+
+        let report = timeline.to_markdown();
+        assert!(report.contains("Earlier events dropped: 1\n"), "{report}");
+        assert!(!report.contains("Chevy 1-1"), "{report}");
+    }
+}
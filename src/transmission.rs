@@ -1,51 +1,135 @@
 //! transmitting a sentence to SRS
 
 use std::{
-    io::Cursor,
+    collections::HashMap,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
 
 use anyhow::Context;
-use futures_util::{stream::SplitSink, SinkExt};
-use srs::VoiceStream;
+use futures_util::SinkExt;
 use stopper::Stopper;
 
-use crate::config::OpenAiConfig;
+use crate::{
+    api::srs::SrsClientState,
+    config::{MessageCategory, OpenAiConfig},
+};
+
+mod ogg_decoder;
+mod phonetic;
+
+pub use phonetic::NATO_ALPHABET;
 
 #[derive(Debug)]
 pub struct OutgoingTransmission {
     pub to_callsign: String,
     pub from_callsign: String,
     pub message: String,
+    pub category: MessageCategory,
+    /// Start of the commit this is an update of, withdrawn from the queue if the commit is aborted
+    pub commit_started_at: Option<Instant>,
+}
+
+/// Commits aborted by pilots, keyed by callsign, whose queued updates are withdrawn
+#[derive(Clone, Default)]
+pub struct AbortedCommits(Arc<Mutex<HashMap<String, Instant>>>);
+
+impl AbortedCommits {
+    /// Withdraws the queued updates of the pilot's commit that started at `started_at`
+    pub fn abort(&self, callsign: &str, started_at: Instant) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(callsign.to_string(), started_at);
+    }
+
+    fn is_aborted(&self, outgoing_transmission: &OutgoingTransmission) -> bool {
+        outgoing_transmission
+            .commit_started_at
+            .is_some_and(|started_at| {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .get(&outgoing_transmission.to_callsign)
+                    == Some(&started_at)
+            })
+    }
 }
 
 impl OutgoingTransmission {
-    fn to_speech_string(&self) -> String {
-        format!(
-            "{}, {}, {}",
-            self.to_callsign, self.from_callsign, self.message
-        )
+    fn to_speech_string(&self, phonetic_callsigns: bool) -> String {
+        if phonetic_callsigns {
+            format!(
+                "{}, {}, {}",
+                phonetic::phoneticize_callsign(&self.to_callsign),
+                phonetic::phoneticize_callsign(&self.from_callsign),
+                self.message
+            )
+        } else {
+            format!(
+                "{}, {}, {}",
+                self.to_callsign, self.from_callsign, self.message
+            )
+        }
     }
 }
 
+/// Duration of an Opus packet, read from its TOC byte (RFC 6716 section 3.1)
+fn get_opus_packet_duration(packet: &[u8]) -> Option<Duration> {
+    let toc = *packet.first()?;
+    let config = toc >> 3;
+    // Frame size in units of 2.5ms
+    let frame_size = match config {
+        0..=11 => [4, 8, 16, 24][(config % 4) as usize],
+        12..=15 => [4, 8][(config % 2) as usize],
+        _ => [1, 2, 4, 8][(config % 4) as usize],
+    };
+    let frame_count = match toc & 0x3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => u64::from(*packet.get(1)? & 0x3f),
+    };
+    Some(Duration::from_micros(frame_size * frame_count * 2500))
+}
+
 pub async fn transmission_loop(
     openai_config: OpenAiConfig,
-    mut srs_sink: SplitSink<VoiceStream, Vec<u8>>,
+    phonetic_callsigns: bool,
+    srs_client: SrsClientState,
     mut transmission_rx: tokio::sync::mpsc::UnboundedReceiver<OutgoingTransmission>,
+    mut priority_transmission_rx: tokio::sync::mpsc::UnboundedReceiver<OutgoingTransmission>,
+    aborted_commits: AbortedCommits,
     stopper: Stopper,
 ) {
-    while let Some(outgoing_transmission) =
-        stopper.stop_future(transmission_rx.recv()).await.flatten()
-    {
+    loop {
+        // Priority transmissions jump ahead of everything queued
+        let next = stopper
+            .stop_future(async {
+                tokio::select! {
+                    biased;
+                    Some(outgoing_transmission) = priority_transmission_rx.recv() => Some(outgoing_transmission),
+                    outgoing_transmission = transmission_rx.recv() => outgoing_transmission,
+                }
+            })
+            .await
+            .flatten();
+        let Some(outgoing_transmission) = next else {
+            break;
+        };
+        if aborted_commits.is_aborted(&outgoing_transmission) {
+            tracing::debug!(?outgoing_transmission, "dropping update of aborted commit");
+            continue;
+        }
         tracing::info!(?outgoing_transmission, "outgoing transmission");
         if let Err(error) = transmit(
-            outgoing_transmission.to_speech_string(),
+            outgoing_transmission.to_speech_string(phonetic_callsigns),
+            outgoing_transmission.category,
             &openai_config,
-            &mut srs_sink,
+            &srs_client,
         )
         .await
         {
-            tracing::error!(%error, "transmit error");
+            tracing::error!(error = format!("{error:#}"), "transmit error");
         }
     }
     tracing::info!("exiting transmission loop");
@@ -53,36 +137,43 @@ pub async fn transmission_loop(
 
 async fn transmit(
     line: String,
+    category: MessageCategory,
     openai_config: &OpenAiConfig,
-    srs_sink: &mut SplitSink<VoiceStream, Vec<u8>>,
+    srs_client: &SrsClientState,
 ) -> anyhow::Result<()> {
-    let speech_ogg = crate::api::openai::speech(openai_config, &line).await?;
-    let mut ogg_reader = ogg::PacketReader::new(Cursor::new(speech_ogg));
-
-    ogg_reader
-        .read_packet_expected()
-        .context("failed to read from OGG reader")?; // header
-    ogg_reader
-        .read_packet_expected()
-        .context("failed to read from OGG reader")?; // tag
-
-    let mut frames = Vec::new();
-
-    while let Some(packet) = ogg_reader
-        .read_packet()
-        .context("failed to read from OGG reader")?
+    let speech_ogg = match crate::api::openai::speech(openai_config, &line, category)
+        .await
+        .context("failed to generate speech")
     {
-        frames.push(packet.data);
-    }
+        Ok(speech_ogg) => speech_ogg,
+        Err(error) => {
+            let Some(fallback_file) = &openai_config.speech_fallback_file else {
+                return Err(error);
+            };
+            tracing::warn!(error = format!("{error:#}"), fallback_file = %fallback_file.display(), "speech generation failed, using fallback audio");
+            tokio::fs::read(fallback_file).await.with_context(|| {
+                format!(
+                    "failed to read speech fallback file `{}`",
+                    fallback_file.display()
+                )
+            })?
+        }
+    };
+    let frames = ogg_decoder::read_opus_frames(speech_ogg)?;
+
+    // Locked only once speech is ready, so a reconnect is not held up by speech generation
+    let mut srs_sink = srs_client.sink().lock().await;
 
     let start = Instant::now();
-    for (i, frame) in frames.iter().enumerate() {
+    let mut playtime = Duration::ZERO;
+    for frame in frames {
+        playtime += get_opus_packet_duration(&frame).unwrap_or(Duration::from_millis(20));
+
         srs_sink
-            .send(frame.clone())
+            .send(frame)
             .await
             .context("failed to send to SRS")?;
 
-        let playtime = Duration::from_millis((i as u64 + 1) * 20);
         let elapsed = start.elapsed();
         if playtime > elapsed {
             tokio::time::sleep(playtime - elapsed).await;
@@ -95,3 +186,89 @@ async fn transmit(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_update(
+        to_callsign: &str,
+        commit_started_at: Option<Instant>,
+    ) -> OutgoingTransmission {
+        OutgoingTransmission {
+            to_callsign: to_callsign.to_string(),
+            from_callsign: "Magic".to_string(),
+            message: "target braa 090 20 angels 25".to_string(),
+            category: MessageCategory::Response,
+            commit_started_at,
+        }
+    }
+
+    #[test]
+    fn opus_packet_duration_follows_the_toc_byte() {
+        // SILK-only, 10ms to 60ms frames
+        assert_eq!(
+            get_opus_packet_duration(&[0]),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            get_opus_packet_duration(&[1 << 3]),
+            Some(Duration::from_millis(20))
+        );
+        assert_eq!(
+            get_opus_packet_duration(&[3 << 3]),
+            Some(Duration::from_millis(60))
+        );
+        // Hybrid, 10ms and 20ms frames
+        assert_eq!(
+            get_opus_packet_duration(&[12 << 3]),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(
+            get_opus_packet_duration(&[13 << 3]),
+            Some(Duration::from_millis(20))
+        );
+        // CELT-only, 2.5ms to 20ms frames
+        assert_eq!(
+            get_opus_packet_duration(&[16 << 3]),
+            Some(Duration::from_micros(2500))
+        );
+        assert_eq!(
+            get_opus_packet_duration(&[31 << 3]),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[test]
+    fn opus_packet_duration_counts_frames() {
+        // Two frames of 20ms
+        assert_eq!(
+            get_opus_packet_duration(&[(31 << 3) | 1]),
+            Some(Duration::from_millis(40))
+        );
+        assert_eq!(
+            get_opus_packet_duration(&[(31 << 3) | 2]),
+            Some(Duration::from_millis(40))
+        );
+        // Arbitrary number of frames, given in the second byte
+        assert_eq!(
+            get_opus_packet_duration(&[(31 << 3) | 3, 6]),
+            Some(Duration::from_millis(120))
+        );
+        assert_eq!(get_opus_packet_duration(&[(31 << 3) | 3]), None);
+        assert_eq!(get_opus_packet_duration(&[]), None);
+    }
+
+    #[test]
+    fn only_updates_of_the_aborted_commit_are_dropped() {
+        let aborted_commits = AbortedCommits::default();
+        let aborted_commit = Instant::now();
+        let new_commit = aborted_commit + Duration::from_secs(60);
+        aborted_commits.abort("Chevy 1-1", aborted_commit);
+
+        assert!(aborted_commits.is_aborted(&commit_update("Chevy 1-1", Some(aborted_commit))));
+        assert!(!aborted_commits.is_aborted(&commit_update("Chevy 1-1", Some(new_commit))));
+        assert!(!aborted_commits.is_aborted(&commit_update("Chevy 1-1", None)));
+        assert!(!aborted_commits.is_aborted(&commit_update("Enfield 1-1", Some(aborted_commit))));
+    }
+}
@@ -0,0 +1,143 @@
+//! Reading Opus frames out of an OGG Opus file (RFC 7845)
+
+use std::io::Cursor;
+
+use anyhow::Context;
+
+/// Checks the identification header, which is the first packet of the stream
+fn validate_opus_head(packet: &[u8]) -> anyhow::Result<()> {
+    if packet.len() < 19 || &packet[0..8] != b"OpusHead" {
+        anyhow::bail!("missing OpusHead signature, not an OGG Opus stream");
+    }
+    let version = packet[8];
+    if version != 1 {
+        anyhow::bail!("unsupported OGG Opus version {}", version);
+    }
+    let channel_count = packet[9];
+    if channel_count != 1 {
+        anyhow::bail!(
+            "expected mono OGG Opus stream, got {} channels",
+            channel_count
+        );
+    }
+    // The input sample rate is informational only, as Opus is always decoded at 48kHz
+    let input_sample_rate = u32::from_le_bytes(packet[12..16].try_into().unwrap());
+    tracing::trace!(input_sample_rate, "OGG Opus stream");
+    Ok(())
+}
+
+/// Opus frames of the stream, after validating its headers
+pub fn read_opus_frames(ogg: Vec<u8>) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut ogg_reader = ogg::PacketReader::new(Cursor::new(ogg));
+
+    let head = ogg_reader
+        .read_packet_expected()
+        .context("failed to read OGG Opus header packet")?;
+    validate_opus_head(&head.data)?;
+
+    let tags = ogg_reader
+        .read_packet_expected()
+        .context("failed to read OGG Opus tag packet")?;
+    if !tags.data.starts_with(b"OpusTags") {
+        anyhow::bail!("missing OpusTags signature");
+    }
+
+    let mut frames = Vec::new();
+    while let Some(packet) = ogg_reader
+        .read_packet()
+        .context("failed to read OGG Opus audio packet")?
+    {
+        if packet.data.is_empty() {
+            anyhow::bail!("empty Opus packet after {} frames", frames.len());
+        }
+        frames.push(packet.data);
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opus_head(version: u8, channel_count: u8) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.extend([version, channel_count]);
+        head.extend(312u16.to_le_bytes());
+        head.extend(24000u32.to_le_bytes());
+        head.extend([0, 0, 0]);
+        head
+    }
+
+    /// CRC of OGG pages, with polynomial 0x04c11db7 and no reflection
+    fn ogg_crc(data: &[u8]) -> u32 {
+        data.iter().fold(0, |crc, byte| {
+            (0..8).fold(crc ^ (u32::from(*byte) << 24), |crc, _| {
+                if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ 0x04c1_1db7
+                } else {
+                    crc << 1
+                }
+            })
+        })
+    }
+
+    /// OGG stream with one page per packet, each shorter than 255 bytes
+    fn ogg_stream(packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for (i, packet) in packets.iter().enumerate() {
+            let header_type = match i {
+                0 => 0x02,
+                _ if i + 1 == packets.len() => 0x04,
+                _ => 0,
+            };
+            let mut page = b"OggS".to_vec();
+            page.extend([0, header_type]);
+            page.extend((i as u64).to_le_bytes());
+            page.extend(1u32.to_le_bytes());
+            page.extend((i as u32).to_le_bytes());
+            page.extend([0; 4]);
+            page.extend([1, packet.len() as u8]);
+            page.extend(packet);
+            let crc = ogg_crc(&page);
+            page[22..26].copy_from_slice(&crc.to_le_bytes());
+            stream.extend(page);
+        }
+        stream
+    }
+
+    #[test]
+    fn frames_follow_the_headers() {
+        let ogg = ogg_stream(&[
+            opus_head(1, 1),
+            b"OpusTags".to_vec(),
+            vec![0xf8, 1, 2],
+            vec![0xf8, 3, 4],
+        ]);
+        assert_eq!(
+            read_opus_frames(ogg).unwrap(),
+            [vec![0xf8, 1, 2], vec![0xf8, 3, 4]]
+        );
+    }
+
+    #[test]
+    fn identification_header_is_validated() {
+        assert!(validate_opus_head(&opus_head(1, 1)).is_ok());
+        assert!(validate_opus_head(&opus_head(2, 1)).is_err());
+        assert!(validate_opus_head(&opus_head(1, 2)).is_err());
+        assert!(validate_opus_head(b"OpusHead").is_err());
+        assert!(validate_opus_head(&[b"OggS".as_slice(), &[0; 15]].concat()).is_err());
+    }
+
+    #[test]
+    fn invalid_streams_are_rejected() {
+        // An MP3 frame header instead of OGG
+        assert!(read_opus_frames(vec![0xff, 0xfb, 0x90, 0x64, 0, 0, 0, 0]).is_err());
+        assert!(read_opus_frames(ogg_stream(&[opus_head(1, 1), b"Tags".to_vec()])).is_err());
+        assert!(read_opus_frames(ogg_stream(&[
+            opus_head(1, 1),
+            b"OpusTags".to_vec(),
+            Vec::new()
+        ]))
+        .is_err());
+    }
+}
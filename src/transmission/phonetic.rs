@@ -0,0 +1,48 @@
+//! Reading letters of alphanumeric identifiers in callsigns with the NATO phonetic alphabet
+
+pub const NATO_ALPHABET: [&str; 26] = [
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliett",
+    "kilo", "lima", "mike", "november", "oscar", "papa", "quebec", "romeo", "sierra", "tango",
+    "uniform", "victor", "whiskey", "x-ray", "yankee", "zulu",
+];
+
+/// Whether the word is an identifier rather than an ordinary word, e.g. "4A" or "KC", but not "Uzi"
+fn is_identifier(word: &str) -> bool {
+    let has_letter = word.chars().any(|c| c.is_ascii_alphabetic());
+    let has_digit = word.chars().any(|c| c.is_ascii_digit());
+    let is_abbreviation = word.len() <= 3 && word.chars().all(|c| c.is_ascii_uppercase());
+    has_letter && (has_digit || is_abbreviation)
+}
+
+/// Reads letters of identifiers phonetically, keeping ordinary words and numbers intact,
+/// e.g. "Uzi 4A" becomes "Uzi 4 alpha"
+pub fn phoneticize_callsign(callsign: &str) -> String {
+    callsign
+        .split_whitespace()
+        .map(|word| {
+            if !is_identifier(word) {
+                return word.to_string();
+            }
+            let mut parts = Vec::<String>::new();
+            let mut digits = String::new();
+            for c in word.chars() {
+                if c.is_ascii_digit() {
+                    digits.push(c);
+                    continue;
+                }
+                if !digits.is_empty() {
+                    parts.push(std::mem::take(&mut digits));
+                }
+                if c.is_ascii_alphabetic() {
+                    let index = (c.to_ascii_lowercase() as u8 - b'a') as usize;
+                    parts.push(NATO_ALPHABET[index].to_string());
+                }
+            }
+            if !digits.is_empty() {
+                parts.push(digits);
+            }
+            parts.join(" ")
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
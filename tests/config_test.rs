@@ -0,0 +1,150 @@
+//! Parsing and validation of config files
+
+#[allow(dead_code)]
+#[path = "../src/config.rs"]
+mod config;
+
+use std::path::PathBuf;
+
+use config::Config;
+
+const SAMPLE: &str = include_str!("fixtures/sample_config.toml");
+
+/// Writes the config into a file of the test's own under the system temp directory
+fn write_config(test: &str, contents: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("magic-gci-bot-config-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{test}.toml"));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Error of reading the config, with its full context chain
+async fn config_error(test: &str, contents: &str) -> String {
+    match Config::from_path(&write_config(test, contents)).await {
+        Ok(_) => panic!("config of `{test}` is valid"),
+        Err(error) => format!("{error:#}"),
+    }
+}
+
+/// The sample config with the first occurrence of `from` replaced
+fn sample_with(from: &str, to: &str) -> String {
+    assert!(SAMPLE.contains(from), "sample config has no `{from}`");
+    SAMPLE.replacen(from, to, 1)
+}
+
+#[test]
+fn sample_config_parses() {
+    assert!(toml::from_str::<Config>(SAMPLE).is_ok());
+}
+
+#[tokio::test]
+async fn sample_config_is_valid() {
+    let config = Config::from_path(&write_config("sample", SAMPLE))
+        .await
+        .unwrap();
+    assert_eq!(config.coalition_configs().len(), 1);
+}
+
+#[tokio::test]
+async fn example_config_is_valid() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+    Config::from_path(&path).await.unwrap();
+}
+
+#[tokio::test]
+async fn missing_api_key_names_the_field() {
+    let error = config_error(
+        "missing_api_key",
+        &sample_with("api_key = \"OPENAI_API_KEY\"\n", ""),
+    )
+    .await;
+    assert!(error.contains("api_key"), "{error}");
+}
+
+#[tokio::test]
+async fn invalid_coalition_lists_the_valid_ones() {
+    let error = config_error(
+        "invalid_coalition",
+        &sample_with("coalition = \"Blue\"", "coalition = \"Green\""),
+    )
+    .await;
+    assert!(error.contains("Green"), "{error}");
+    assert!(error.contains("Blue") && error.contains("Red"), "{error}");
+}
+
+#[tokio::test]
+async fn zero_speech_speed_is_invalid() {
+    let error = config_error(
+        "zero_speech_speed",
+        &sample_with("speech_speed = 1.0", "speech_speed = 0.0"),
+    )
+    .await;
+    assert!(error.contains("`openai.speech_speed`"), "{error}");
+}
+
+#[tokio::test]
+async fn out_of_range_temperature_is_invalid() {
+    let error = config_error(
+        "out_of_range_temperature",
+        &sample_with("chat_temperature = 0.2", "chat_temperature = 2.5"),
+    )
+    .await;
+    assert!(error.contains("`openai.chat_temperature`"), "{error}");
+}
+
+#[tokio::test]
+async fn invalid_active_hours_are_invalid() {
+    let error = config_error(
+        "invalid_active_hours",
+        &sample_with("end = \"02:00\"", "end = \"25:00\""),
+    )
+    .await;
+    assert!(error.contains("`active_hours.end`"), "{error}");
+}
+
+#[tokio::test]
+async fn orbit_track_speed_and_leg_must_be_positive() {
+    let error = config_error(
+        "orbit_track_speed",
+        &sample_with("speed_knots = 420.0", "speed_knots = 0.0"),
+    )
+    .await;
+    assert!(
+        error.contains("`speed_knots` of orbit track `Texaco`"),
+        "{error}"
+    );
+
+    let error = config_error(
+        "orbit_track_leg",
+        &sample_with("leg_nm = 20.0", "leg_nm = -5.0"),
+    )
+    .await;
+    assert!(
+        error.contains("`leg_nm` of orbit track `Texaco`"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn provider_base_url_must_be_http() {
+    let error = config_error(
+        "provider_base_url",
+        &sample_with("http://localhost:8000/v1", "localhost:8000/v1"),
+    )
+    .await;
+    assert!(error.contains("provider `local`"), "{error}");
+}
+
+#[tokio::test]
+async fn common_without_srs_is_invalid() {
+    let srs = SAMPLE.find("[srs]").unwrap();
+    let openai = SAMPLE.find("[openai]").unwrap();
+    let contents = format!("{}{}", &SAMPLE[..srs], &SAMPLE[openai..]);
+    let error = config_error("common_without_srs", &contents).await;
+    assert!(
+        error.contains("`common` and `srs` must be configured together"),
+        "{error}"
+    );
+}